@@ -13,12 +13,27 @@
 // Lint levels of Clippy.
 #![warn(clippy::cargo, clippy::nursery, clippy::pedantic)]
 
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+
+use anyhow::Context;
 use clap::Parser;
+use sz::{ArchiveWriter, CompressionLevel, Entry};
 
 /// Create archive from files.
 #[derive(Debug, Parser)]
 #[clap(version, about)]
 struct Opt {
+    /// Store symbolic links instead of the files they point to.
+    #[clap(long)]
+    pub no_follow: bool,
+
+    /// Compression level from 0 to 9.
+    #[clap(short = 'x', long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub level: Option<u8>,
+
     /// Archive to create.
     pub archive: std::path::PathBuf,
 
@@ -26,9 +41,56 @@ struct Opt {
     pub file: Vec<std::path::PathBuf>,
 }
 
+/// Adds the file or the directory at `path` recursively.
+fn add(writer: &mut ArchiveWriter<File>, path: &Path, follow: bool) -> anyhow::Result<()> {
+    let metadata = if follow {
+        fs::metadata(path)
+    } else {
+        fs::symlink_metadata(path)
+    }
+    .with_context(|| format!("could not read {}", path.display()))?;
+    let name = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let entry = Entry::from_metadata(name, &metadata);
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        writer
+            .add_symlink(entry, &target.to_string_lossy())
+            .with_context(|| format!("could not add {}", path.display()))?;
+    } else if metadata.is_dir() {
+        writer.add_dir(entry);
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+        for path in paths {
+            add(writer, &path, follow)?;
+        }
+    } else {
+        let file =
+            File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        writer
+            .add_file(entry, file)
+            .with_context(|| format!("could not add {}", path.display()))?;
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
-    #[allow(unused_variables)]
     let opt = Opt::parse();
 
-    Err(anyhow::anyhow!("not yet implemented"))
+    let file = File::create(&opt.archive)
+        .with_context(|| format!("could not create {}", opt.archive.display()))?;
+    let mut writer = ArchiveWriter::new(file).context("could not create the archive")?;
+    if let Some(level) = opt.level.and_then(CompressionLevel::new) {
+        writer.set_compression_level(level);
+    }
+    for path in &opt.file {
+        add(&mut writer, path, !opt.no_follow)?;
+    }
+    writer.finish().context("could not write the archive")?;
+    Ok(())
 }
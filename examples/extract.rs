@@ -13,12 +13,32 @@
 // Lint levels of Clippy.
 #![warn(clippy::cargo, clippy::nursery, clippy::pedantic)]
 
+use std::{
+    fs::{self, File},
+    io,
+    path::{Component, Path},
+};
+
+use anyhow::Context;
 use clap::Parser;
+use sz::{Archive, Entry, FileType, PermissionPolicy};
 
 /// Extract files in archive.
 #[derive(Debug, Parser)]
 #[clap(version, about)]
 struct Opt {
+    /// Password to decrypt archive.
+    #[clap(short, long)]
+    pub password: Option<String>,
+
+    /// Umask to clear from restored permissions, in octal.
+    #[clap(long, value_parser = parse_octal)]
+    pub umask: Option<u32>,
+
+    /// Do not restore permissions.
+    #[clap(long, conflicts_with = "umask")]
+    pub no_permissions: bool,
+
     /// Archive to extract.
     pub archive: std::path::PathBuf,
 
@@ -28,9 +48,97 @@ struct Opt {
     pub entry: Vec<std::path::PathBuf>,
 }
 
+/// Parses an octal number.
+fn parse_octal(s: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(s, 8)
+}
+
+/// Returns `true` if the target of a symbolic link is a relative path which
+/// does not go up with `..`.
+fn is_safe_target(target: &str) -> bool {
+    Path::new(target)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Returns `true` if any parent of `path` is a symbolic link.
+fn has_symlink_parent(path: &Path) -> bool {
+    path.ancestors().skip(1).any(|p| {
+        fs::symlink_metadata(p).map_or(false, |metadata| metadata.file_type().is_symlink())
+    })
+}
+
 fn main() -> anyhow::Result<()> {
-    #[allow(unused_variables)]
     let opt = Opt::parse();
 
-    Err(anyhow::anyhow!("not yet implemented"))
+    let file = File::open(&opt.archive)
+        .with_context(|| format!("could not open {}", opt.archive.display()))?;
+    let mut archive = match opt.password {
+        Some(password) => Archive::with_password(file, &password),
+        None => Archive::new(file),
+    }
+    .context("could not read the archive")?;
+    let policy = if opt.no_permissions {
+        PermissionPolicy::Ignore
+    } else {
+        opt.umask
+            .map_or(PermissionPolicy::Apply, PermissionPolicy::Umask)
+    };
+    let mut dirs = Vec::<Entry>::new();
+    let mut symlinks = Vec::<Entry>::new();
+    archive
+        .for_each_entry(|entry, reader| {
+            let path = Path::new(entry.name());
+            if !(opt.entry.is_empty() || opt.entry.iter().any(|e| e == path)) {
+                return Ok(());
+            }
+            if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+                eprintln!("skipping unsafe path {}", path.display());
+                return Ok(());
+            }
+            if entry.is_dir() {
+                fs::create_dir_all(path)?;
+                dirs.push(entry.clone());
+            } else if entry.file_type() == FileType::Symlink {
+                symlinks.push(entry.clone());
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(reader, &mut File::create(path)?)?;
+                entry.restore_permissions(path, policy)?;
+            }
+            Ok(())
+        })
+        .context("could not extract the archive")?;
+    // The symbolic links are created after the files, so no file is written
+    // through them. A link is created only if its target stays inside the
+    // directory, and not through another link, so no link points outside.
+    for entry in &symlinks {
+        let path = Path::new(entry.name());
+        if !entry.symlink_target().map_or(false, is_safe_target) {
+            eprintln!("skipping unsafe symbolic link {}", path.display());
+            continue;
+        }
+        if has_symlink_parent(path) {
+            eprintln!(
+                "skipping symbolic link inside symbolic link {}",
+                path.display()
+            );
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry
+            .create_symlink(path)
+            .with_context(|| format!("could not create {}", entry.name()))?;
+    }
+    // The directories are restored after their contents are extracted.
+    for entry in dirs.iter().rev() {
+        entry
+            .restore_permissions(entry.name(), policy)
+            .with_context(|| format!("could not set permissions of {}", entry.name()))?;
+    }
+    Ok(())
 }
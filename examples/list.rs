@@ -13,19 +13,49 @@
 // Lint levels of Clippy.
 #![warn(clippy::cargo, clippy::nursery, clippy::pedantic)]
 
+use std::fs::File;
+
+use anyhow::Context;
 use clap::Parser;
+use sz::{Archive, FileType};
 
 /// List entries in archive.
 #[derive(Debug, Parser)]
 #[clap(version, about)]
 struct Opt {
+    /// Password to decrypt archive.
+    #[clap(short, long)]
+    pub password: Option<String>,
+
     /// Archive to list entries.
     pub archive: std::path::PathBuf,
 }
 
 fn main() -> anyhow::Result<()> {
-    #[allow(unused_variables)]
     let opt = Opt::parse();
 
-    Err(anyhow::anyhow!("not yet implemented"))
+    let file = File::open(&opt.archive)
+        .with_context(|| format!("could not open {}", opt.archive.display()))?;
+    let archive = match opt.password {
+        Some(password) => Archive::with_password(file, &password),
+        None => Archive::new(file),
+    }
+    .context("could not read the archive")?;
+    for entry in archive.entries() {
+        let kind = match entry.file_type() {
+            FileType::Regular => '-',
+            FileType::Directory => 'd',
+            FileType::Symlink => 'l',
+            FileType::BlockDevice => 'b',
+            FileType::CharDevice => 'c',
+            FileType::Fifo => 'p',
+            FileType::Socket => 's',
+        };
+        let attributes = entry.attributes().map_or_else(
+            || String::from("....."),
+            |attributes| attributes.to_string(),
+        );
+        println!("{kind} {attributes} {:>12} {}", entry.size(), entry.name());
+    }
+    Ok(())
 }
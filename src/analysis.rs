@@ -0,0 +1,277 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Analysis of the data of files to select the methods.
+
+use crate::method::{Method, MethodSpec, Param, Sz};
+
+/// The number of the first bytes of a file which are analyzed.
+pub const HEAD_SIZE: usize = 1 << 12;
+
+/// The entropy in bits per byte above which the data are regarded as
+/// incompressible.
+///
+/// The entropy of [`HEAD_SIZE`] random bytes is about 7.95 bits per byte,
+/// while compressed files such as JPEG and ZIP are usually above 7.9.
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.9;
+
+/// Reads the 2-byte integer at `pos`.
+fn read_u16(data: &[u8], pos: usize, is_big_endian: bool) -> Option<u16> {
+    let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+    Some(if is_big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+/// Reads the 4-byte integer at `pos`.
+fn read_u32(data: &[u8], pos: usize, is_big_endian: bool) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+    Some(if is_big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Returns the filter for the machine of a PE executable.
+fn detect_pe(data: &[u8]) -> Option<Method> {
+    if !data.starts_with(b"MZ") {
+        return None;
+    }
+    let pos = usize::try_from(read_u32(data, 0x3c, false)?).ok()?;
+    if data.get(pos..pos.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    match read_u16(data, pos + 4, false)? {
+        // i386 and AMD64.
+        0x014c | 0x8664 => Some(Method::Sz(Sz::Bcj)),
+        // ARM.
+        0x01c0 => Some(Method::Sz(Sz::Arm)),
+        // Thumb and ARMNT.
+        0x01c2 | 0x01c4 => Some(Method::Sz(Sz::ArmT)),
+        // ARM64.
+        0xaa64 => Some(Method::Arm64),
+        _ => None,
+    }
+}
+
+/// Returns the filter for the machine of an ELF executable.
+fn detect_elf(data: &[u8]) -> Option<Method> {
+    if !data.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let is_big_endian = match data.get(5)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    match (read_u16(data, 18, is_big_endian)?, is_big_endian) {
+        // i386, i486 and x86-64.
+        (3 | 6 | 62, false) => Some(Method::Sz(Sz::Bcj)),
+        // ARM.
+        (40, false) => Some(Method::Sz(Sz::Arm)),
+        // AArch64.
+        (183, false) => Some(Method::Arm64),
+        // PowerPC and PowerPC64.
+        (20 | 21, true) => Some(Method::Sz(Sz::Ppc)),
+        // SPARC, SPARC32PLUS and SPARC V9.
+        (2 | 18 | 43, true) => Some(Method::Sz(Sz::Sparc)),
+        _ => None,
+    }
+}
+
+/// Returns the filter for the CPU type of a Mach-O executable.
+fn detect_mach_o(data: &[u8]) -> Option<Method> {
+    let is_big_endian = match read_u32(data, 0, false)? {
+        0xfeed_face | 0xfeed_facf => false,
+        0xcefa_edfe | 0xcffa_edfe => true,
+        _ => return None,
+    };
+    match (read_u32(data, 4, is_big_endian)?, is_big_endian) {
+        // x86 and x86-64.
+        (0x0000_0007 | 0x0100_0007, false) => Some(Method::Sz(Sz::Bcj)),
+        // ARM.
+        (0x0000_000c, false) => Some(Method::Sz(Sz::Arm)),
+        // ARM64.
+        (0x0100_000c, false) => Some(Method::Arm64),
+        // PowerPC and PowerPC64.
+        (0x0000_0012 | 0x0100_0012, true) => Some(Method::Sz(Sz::Ppc)),
+        _ => None,
+    }
+}
+
+/// Returns the Delta filter whose distance is the size of a sample frame of
+/// a WAV file of PCM.
+fn detect_wav(data: &[u8]) -> Option<MethodSpec> {
+    if !data.starts_with(b"RIFF") || data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut pos = 12;
+    loop {
+        let size = usize::try_from(read_u32(data, pos + 4, false)?).ok()?;
+        if data.get(pos..pos + 4)? == b"fmt " {
+            break;
+        }
+        // The chunks are padded to an even size.
+        pos = pos.checked_add(8 + size + size % 2)?;
+    }
+    let format = read_u16(data, pos + 8, false)?;
+    let block_align = read_u16(data, pos + 20, false)?;
+    let bits_per_sample = read_u16(data, pos + 22, false)?;
+    // PCM and WAVE_FORMAT_EXTENSIBLE.
+    if !matches!(format, 0x0001 | 0xfffe)
+        || bits_per_sample % 8 != 0
+        || !(2..=256).contains(&block_align)
+    {
+        return None;
+    }
+    let mut spec = MethodSpec::new(Method::Delta);
+    spec.params.push(Param::new("", block_align.to_string()));
+    Some(spec)
+}
+
+/// Returns the filter which suits the data, judging from the first bytes of a
+/// file as 7-Zip does.
+///
+/// The branch converter is selected for the executables of x86, ARM, ARM64,
+/// PowerPC and SPARC, and the Delta filter is selected for WAV files of PCM.
+pub fn detect_filter(head: &[u8]) -> Option<MethodSpec> {
+    detect_pe(head)
+        .or_else(|| detect_elf(head))
+        .or_else(|| detect_mach_o(head))
+        .map(MethodSpec::new)
+        .or_else(|| detect_wav(head))
+}
+
+/// Returns the Shannon entropy of the bytes of `data` in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [u32::default(); 256];
+    for &b in data {
+        counts[usize::from(b)] += 1;
+    }
+    let total = f64::from(counts.iter().sum::<u32>());
+    counts
+        .iter()
+        .filter(|&&count| count != 0)
+        .map(|&count| {
+            let p = f64::from(count) / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Returns `true` if the data seem not to be compressible, judging from the
+/// first bytes of a file.
+///
+/// The files which are shorter than [`HEAD_SIZE`] are never regarded as
+/// incompressible, since their entropy cannot be estimated well.
+pub fn is_incompressible(head: &[u8]) -> bool {
+    head.len() >= HEAD_SIZE && entropy(&head[..HEAD_SIZE]) > INCOMPRESSIBLE_ENTROPY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elf(machine: u16, is_big_endian: bool) -> Vec<u8> {
+        let mut data = b"\x7fELF\x02".to_vec();
+        data.push(if is_big_endian { 2 } else { 1 });
+        data.resize(18, 0);
+        if is_big_endian {
+            data.extend(machine.to_be_bytes());
+        } else {
+            data.extend(machine.to_le_bytes());
+        }
+        data.resize(64, 0);
+        data
+    }
+
+    fn wav(format: u16, channels: u16, bits_per_sample: u16) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+        data.extend(b"LIST\x03\0\0\0abc\0");
+        data.extend(b"fmt \x10\0\0\0");
+        data.extend(format.to_le_bytes());
+        data.extend(channels.to_le_bytes());
+        data.extend(44100_u32.to_le_bytes());
+        data.extend((44100 * u32::from(block_align)).to_le_bytes());
+        data.extend(block_align.to_le_bytes());
+        data.extend(bits_per_sample.to_le_bytes());
+        data.extend(b"data\0\0\0\0");
+        data
+    }
+
+    fn detect(data: &[u8]) -> Option<String> {
+        detect_filter(data).map(|spec| spec.to_string())
+    }
+
+    #[test]
+    fn detect_executable() {
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x3c, 0);
+        pe.extend(0x80_u32.to_le_bytes());
+        pe.resize(0x80, 0);
+        pe.extend(b"PE\0\0");
+        for (machine, filter) in [
+            (0x8664, Some("BCJ")),
+            (0x01c4, Some("ARMT")),
+            (0xaa64, Some("ARM64")),
+            (0x0200, None),
+        ] {
+            let mut pe = pe.clone();
+            pe.extend(u16::to_le_bytes(machine));
+            assert_eq!(detect(&pe).as_deref(), filter);
+        }
+        assert_eq!(detect(&pe), None);
+
+        assert_eq!(detect(&elf(62, false)).as_deref(), Some("BCJ"));
+        assert_eq!(detect(&elf(40, false)).as_deref(), Some("ARM"));
+        assert_eq!(detect(&elf(183, false)).as_deref(), Some("ARM64"));
+        assert_eq!(detect(&elf(21, true)).as_deref(), Some("PPC"));
+        assert_eq!(detect(&elf(43, true)).as_deref(), Some("SPARC"));
+        assert_eq!(detect(&elf(21, false)), None);
+        assert_eq!(detect(&elf(243, false)), None);
+        assert_eq!(detect(&elf(62, false)[..16]), None);
+
+        let mut mach_o = 0xfeed_facf_u32.to_le_bytes().to_vec();
+        mach_o.extend(0x0100_000c_u32.to_le_bytes());
+        assert_eq!(detect(&mach_o).as_deref(), Some("ARM64"));
+    }
+
+    #[test]
+    fn detect_audio() {
+        assert_eq!(detect(&wav(1, 2, 16)).as_deref(), Some("Delta:4"));
+        assert_eq!(detect(&wav(0xfffe, 6, 24)).as_deref(), Some("Delta:18"));
+        assert_eq!(detect(&wav(1, 1, 8)), None);
+        assert_eq!(detect(&wav(3, 2, 32)), None);
+        assert_eq!(detect(&wav(1, 2, 16)[..30]), None);
+        assert_eq!(detect(b"Hello, world!\n"), None);
+    }
+
+    #[test]
+    fn detect_incompressible() {
+        let mut state = 1_u32;
+        let random = (0..HEAD_SIZE)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                state.to_be_bytes()[0]
+            })
+            .collect::<Vec<_>>();
+        assert!(is_incompressible(&random));
+        assert!(!is_incompressible(&random[..HEAD_SIZE - 1]));
+
+        let text = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n".repeat(80);
+        assert!(!is_incompressible(&text));
+        let bytes = (0..=255).cycle().take(HEAD_SIZE).collect::<Vec<u8>>();
+        assert!((entropy(&bytes) - 8.0).abs() < f64::EPSILON);
+        assert!(entropy(&[0; 16]).abs() < f64::EPSILON);
+        let mut half = random;
+        half[HEAD_SIZE / 2..].fill(0);
+        assert!(!is_incompressible(&half));
+    }
+}
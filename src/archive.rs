@@ -0,0 +1,1201 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! 7z archive reader.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use zeroize::Zeroizing;
+
+use crate::{
+    attributes::FileType,
+    codec::CodecRegistry,
+    crypto::KeyCache,
+    decoder,
+    error::InvalidArchive,
+    header::{Folder, Header, ReadExt, StartHeader, StreamsInfo, SIGNATURE_HEADER_SIZE},
+    method::{Crypto, Method, MethodChain, MethodSpec},
+    password::{FixedPassword, Passwords},
+    property::Property,
+    Entry, Error, PasswordProvider, Result,
+};
+
+/// The maximum size of the target of a symbolic link.
+const SYMLINK_TARGET_MAX: u64 = 1 << 16;
+
+/// A reader which computes the CRC of the data read through it.
+struct CrcReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> CrcReader<R> {
+    /// Creates a new `CrcReader`.
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    /// Returns the CRC of the data read so far.
+    fn crc(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Whether an entry can be extracted, as reported by
+/// [`Archive::extractability`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Extractability {
+    /// The entry can be extracted.
+    Extractable,
+
+    /// The entry is encrypted, but no password or key was given.
+    PasswordRequired,
+
+    /// The entry uses a method which is not supported by this build or by the
+    /// registered codecs.
+    UnsupportedMethod(Method),
+}
+
+impl Extractability {
+    /// Returns `true` if the entry can be extracted.
+    #[must_use]
+    pub const fn is_extractable(&self) -> bool {
+        matches!(self, Self::Extractable)
+    }
+}
+
+/// A 7z archive reader.
+#[derive(Debug)]
+pub struct Archive<R> {
+    reader: R,
+    passwords: Passwords,
+    codecs: CodecRegistry,
+    streams_info: StreamsInfo,
+    entries: Vec<Entry>,
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Opens a 7z archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read. In particular, returns
+    /// [`Error::PasswordRequired`] if the header is encrypted.
+    ///
+    /// [`Error::PasswordRequired`]: crate::Error::PasswordRequired
+    pub fn new(reader: R) -> Result<Self> {
+        OpenOptions::new().open(reader)
+    }
+
+    /// Opens a 7z archive with the password.
+    ///
+    /// The password is used to decrypt both the header and the entries. The
+    /// copy of the password kept by this `Archive` is zeroized on drop.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read. In particular, returns
+    /// [`Error::WrongPassword`] if the header is encrypted and the password is
+    /// detected to be wrong.
+    ///
+    /// [`Error::WrongPassword`]: crate::Error::WrongPassword
+    pub fn with_password(reader: R, password: &str) -> Result<Self> {
+        OpenOptions::new().password(password).open(reader)
+    }
+
+    /// Opens a 7z archive with the password provider.
+    ///
+    /// The password is requested from `provider` only when an encrypted
+    /// folder is decoded, and is requested again while it is detected to be
+    /// wrong. This allows each folder to be encrypted with a different
+    /// password.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read. In particular, returns
+    /// [`Error::WrongPassword`] if the header is encrypted and `provider` gave
+    /// up after a wrong password.
+    ///
+    /// [`Error::WrongPassword`]: crate::Error::WrongPassword
+    pub fn with_password_provider<P>(reader: R, provider: P) -> Result<Self>
+    where
+        P: PasswordProvider + Send + 'static,
+    {
+        OpenOptions::new().password_provider(provider).open(reader)
+    }
+
+    /// Opens a 7z archive with the raw AES key.
+    ///
+    /// `key` is used as the key of 7zAES for both the header and the entries,
+    /// skipping the key derivation from a password. The copy of `key` kept by
+    /// this `Archive` is zeroized on drop.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read. In particular, returns
+    /// [`Error::WrongPassword`] if the header is encrypted and the key is
+    /// detected to be wrong.
+    ///
+    /// [`Error::WrongPassword`]: crate::Error::WrongPassword
+    pub fn with_key(reader: R, key: [u8; 32]) -> Result<Self> {
+        OpenOptions::new().key(key).open(reader)
+    }
+
+    /// Opens a 7z archive with the password provider and the key cache.
+    ///
+    /// The keys derived from the passwords are cached in `keys`, so opening
+    /// many archives encrypted with the same password with clones of the same
+    /// [`KeyCache`] derives the key only once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read.
+    pub fn with_key_cache<P>(reader: R, provider: P, keys: KeyCache) -> Result<Self>
+    where
+        P: PasswordProvider + Send + 'static,
+    {
+        OpenOptions::new()
+            .password_provider(provider)
+            .key_cache(keys)
+            .open(reader)
+    }
+
+    /// Opens a 7z archive with the options.
+    fn open(mut reader: R, options: OpenOptions) -> Result<Self> {
+        reader.seek(SeekFrom::Start(u64::default()))?;
+        let start_header = StartHeader::read(&mut reader)?;
+        let mut archive = Self {
+            reader,
+            passwords: options.passwords,
+            codecs: options.codecs,
+            streams_info: StreamsInfo::default(),
+            entries: Vec::new(),
+        };
+        if start_header.size == 0 {
+            return Ok(archive);
+        }
+
+        let archive_size = archive.reader.seek(SeekFrom::End(0))?;
+        let next_header_pos = SIGNATURE_HEADER_SIZE
+            .checked_add(start_header.offset)
+            .filter(|pos| {
+                pos.checked_add(start_header.size)
+                    .map_or(false, |end| end <= archive_size)
+            })
+            .ok_or(InvalidArchive::Header)?;
+        archive.reader.seek(SeekFrom::Start(next_header_pos))?;
+        let mut buf = vec![
+            u8::default();
+            usize::try_from(start_header.size).map_err(|_| InvalidArchive::Header)?
+        ];
+        archive.reader.read_exact(&mut buf)?;
+        if crc32fast::hash(&buf) != start_header.crc {
+            return Err(InvalidArchive::NextHeaderCrc.into());
+        }
+
+        let header = loop {
+            let mut data = &buf[..];
+            match data.read_u8()? {
+                id if id == u8::from(Property::Header) => break Header::read(&mut data)?,
+                id if id == u8::from(Property::EncodedHeader) => {
+                    let streams_info = StreamsInfo::read(&mut data, Property::EncodedHeader)?;
+                    buf = archive.decode_header(&streams_info)?;
+                }
+                id => {
+                    return Err(InvalidArchive::UnexpectedProperty {
+                        pos: Property::Header,
+                        id,
+                    }
+                    .into())
+                }
+            }
+        };
+        // The indices of the folders of the header differ from the ones of the
+        // data.
+        archive.passwords.finish_header();
+        archive.streams_info = header.main_streams_info;
+        archive.entries = header.entries;
+        Ok(archive)
+    }
+
+    /// Decodes the encoded header.
+    fn decode_header(&mut self, streams_info: &StreamsInfo) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for i in 0..streams_info.folders.len() {
+            let start = buf.len();
+            loop {
+                match self.decode_header_folder(streams_info, i, &mut buf) {
+                    // A wrong password may be detected only after decoding, so
+                    // the next password is tried.
+                    Err(Error::WrongPassword) if self.passwords.reject(i) => buf.truncate(start),
+                    result => break result?,
+                }
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Decodes the folder of the encoded header at `index` into `buf`.
+    fn decode_header_folder(
+        &mut self,
+        streams_info: &StreamsInfo,
+        index: usize,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        let folder = &streams_info.folders[index];
+        let size = folder.unpack_size();
+        let reader =
+            self.passwords
+                .folder_reader(&mut self.reader, streams_info, index, &self.codecs)?;
+        let mut reader = CrcReader::new(reader.take(size));
+        let n = reader.read_to_end(buf)?;
+        check_data(
+            folder,
+            n as u64,
+            size,
+            reader.crc(),
+            folder.crc,
+            self.passwords.is_confirmed(index),
+        )?;
+        self.passwords.confirm(index);
+        Ok(())
+    }
+
+    /// Returns the entries in this archive.
+    #[must_use]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the methods of the entry at `index` in the order of decoding,
+    /// as `7z l -slt` shows them.
+    ///
+    /// Returns [`None`] if the entry has no data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[must_use]
+    pub fn methods(&self, index: usize) -> Option<MethodChain> {
+        let folder = &self.streams_info.folders[self.entries[index].folder?];
+        let specs = folder
+            .decoding_order()
+            .into_iter()
+            .map(|i| {
+                let coder = &folder.coders[i];
+                MethodSpec::from_properties(coder.method.clone(), &coder.properties)
+            })
+            .collect();
+        Some(MethodChain(specs))
+    }
+
+    /// Checks whether each entry can be extracted without decoding the data.
+    ///
+    /// The returned report is in the same order as [`Archive::entries`]. An
+    /// entry which is reported as extractable may still fail to be extracted
+    /// if the data are corrupted or the password is wrong.
+    #[must_use]
+    pub fn extractability(&self) -> Vec<Extractability> {
+        let folders = self
+            .streams_info
+            .folders
+            .iter()
+            .map(|folder| self.folder_extractability(folder))
+            .collect::<Vec<_>>();
+        self.entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .folder
+                    .map_or(Extractability::Extractable, |i| folders[i].clone())
+            })
+            .collect()
+    }
+
+    /// Checks whether the folder can be decoded.
+    fn folder_extractability(&self, folder: &Folder) -> Extractability {
+        if let Some(coder) = folder.coders.iter().find(|coder| {
+            coder.num_out_streams != 1
+                || !self.codecs.can_decode(&coder.method)
+                || (self.codecs.get(coder.method.id()).is_none() && coder.num_in_streams != 1)
+        }) {
+            return Extractability::UnsupportedMethod(coder.method.clone());
+        }
+        let encryption = Method::Crypto(Crypto::SzAes);
+        if folder.uses(&encryption)
+            && self.codecs.get(encryption.id()).is_none()
+            && !self.passwords.has_secret()
+        {
+            return Extractability::PasswordRequired;
+        }
+        Extractability::Extractable
+    }
+
+    /// Reads the data of the entry at `index` into `writer`.
+    ///
+    /// Returns the number of bytes written. In a solid archive, all the
+    /// preceding data in the same folder are decoded, so
+    /// [`Archive::for_each_entry`] should be used to read many entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the data could not be decoded, or if the CRC of the
+    /// data mismatched. If the data are encrypted with a wrong password,
+    /// [`Error::WrongPassword`] is returned, and the password is requested
+    /// again from the provider on the next read. If the decrypted data are not
+    /// decoded with LZMA or LZMA2, a wrong password is detected only by the
+    /// CRC, so a CRC mismatch is reported as it until the data of the folder
+    /// are verified once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// [`Error::WrongPassword`]: crate::Error::WrongPassword
+    pub fn read_entry<W: Write + ?Sized>(&mut self, index: usize, writer: &mut W) -> Result<u64> {
+        let folder = match self.entries[index].folder {
+            Some(folder) => folder,
+            None => return Ok(u64::default()),
+        };
+        let result = self.read_folder_entry(index, folder, writer);
+        if matches!(result, Err(Error::WrongPassword)) {
+            self.passwords.reject(folder);
+        }
+        result
+    }
+
+    /// Reads the data of the entry at `index` in `folder` into `writer`.
+    fn read_folder_entry<W: Write + ?Sized>(
+        &mut self,
+        index: usize,
+        folder: usize,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let entry = &self.entries[index];
+        let mut reader = self.passwords.folder_reader(
+            &mut self.reader,
+            &self.streams_info,
+            folder,
+            &self.codecs,
+        )?;
+        skip(&mut reader, entry.offset)?;
+        let mut reader = CrcReader::new(reader.take(entry.size));
+        let n = io::copy(&mut reader, writer)?;
+        check_data(
+            &self.streams_info.folders[folder],
+            n,
+            entry.size,
+            reader.crc(),
+            entry.crc,
+            self.passwords.is_confirmed(folder),
+        )?;
+        self.passwords.confirm(folder);
+        Ok(n)
+    }
+
+    /// Returns the target of the symbolic link at `index`, reading the data of
+    /// the entry.
+    ///
+    /// Returns [`None`] if the entry is not a symbolic link.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the data could not be decoded, if the CRC of the data
+    /// mismatched, or if the target is larger than 64 KiB or is not valid
+    /// UTF-8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn symlink_target(&mut self, index: usize) -> Result<Option<String>> {
+        let entry = &self.entries[index];
+        if entry.file_type() != FileType::Symlink {
+            return Ok(None);
+        }
+        if entry.size > SYMLINK_TARGET_MAX {
+            return Err(InvalidArchive::SymlinkTarget.into());
+        }
+        let mut target = Vec::new();
+        self.read_entry(index, &mut target)?;
+        String::from_utf8(target)
+            .map(Some)
+            .map_err(|_| InvalidArchive::SymlinkTarget.into())
+    }
+
+    /// Calls `f` with each entry and the reader of its data.
+    ///
+    /// The entries are visited in the order they are stored, so each folder is
+    /// decoded only once. Any data not read by `f` are skipped, and the CRC of
+    /// the data is checked after `f` returns. The target of a symbolic link is
+    /// read in advance, so [`Entry::symlink_target`] returns it. A target which
+    /// is larger than 64 KiB or is not valid UTF-8 is not set, but the data are
+    /// passed to `f` as usual.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the data could not be decoded, if the CRC of the data
+    /// mismatched, or if `f` returned [`Err`]. A wrong
+    /// password is reported as [`Error::WrongPassword`], as
+    /// [`Archive::read_entry`] does.
+    ///
+    /// [`Error::WrongPassword`]: crate::Error::WrongPassword
+    pub fn for_each_entry<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&Entry, &mut dyn Read) -> Result<()>,
+    {
+        let mut folder = None;
+        let result = self.visit_entries(&mut f, &mut folder);
+        if let (Err(Error::WrongPassword), Some(folder)) = (&result, folder) {
+            self.passwords.reject(folder);
+        }
+        result
+    }
+
+    /// Calls `f` with each entry, setting `last_folder` to the folder which is
+    /// being decoded.
+    fn visit_entries<F>(&mut self, f: &mut F, last_folder: &mut Option<usize>) -> Result<()>
+    where
+        F: FnMut(&Entry, &mut dyn Read) -> Result<()>,
+    {
+        let mut current: Option<(usize, u64, Box<dyn Read + '_>)> = None;
+        for entry in &self.entries {
+            let folder = if let Some(folder) = entry.folder {
+                folder
+            } else {
+                f(entry, &mut io::empty())?;
+                continue;
+            };
+            let (_, pos, reader) = match current.as_mut() {
+                Some(current) if current.0 == folder && current.1 <= entry.offset => current,
+                _ => {
+                    // The previous folder must be closed before reopening the reader.
+                    current = None;
+                    *last_folder = Some(folder);
+                    let reader = self.passwords.folder_reader(
+                        &mut self.reader,
+                        &self.streams_info,
+                        folder,
+                        &self.codecs,
+                    )?;
+                    current.insert((folder, u64::default(), reader))
+                }
+            };
+            skip(reader, entry.offset - *pos)?;
+            let mut data = CrcReader::new(reader.take(entry.size));
+            if entry.file_type() == FileType::Symlink && entry.size <= SYMLINK_TARGET_MAX {
+                let mut target = Vec::new();
+                data.read_to_end(&mut target)?;
+                let mut entry = entry.clone();
+                entry.symlink_target = String::from_utf8(target.clone()).ok();
+                f(&entry, &mut &target[..])?;
+            } else {
+                f(entry, &mut data)?;
+            }
+            io::copy(&mut data, &mut io::sink())?;
+            let n = entry.size - data.inner.limit();
+            *pos = entry.offset + n;
+            check_data(
+                &self.streams_info.folders[folder],
+                n,
+                entry.size,
+                data.crc(),
+                entry.crc,
+                self.passwords.is_confirmed(folder),
+            )?;
+            self.passwords.confirm(folder);
+        }
+        Ok(())
+    }
+
+    /// Unwraps this `Archive`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Options to open a 7z archive.
+///
+/// This is used to combine the options which the constructors of [`Archive`]
+/// take separately.
+#[derive(Debug, Default)]
+pub struct OpenOptions {
+    passwords: Passwords,
+    codecs: CodecRegistry,
+}
+
+impl OpenOptions {
+    /// Creates a new `OpenOptions` with no password and no codecs.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the password to decrypt the archive.
+    ///
+    /// See [`Archive::with_password`].
+    #[must_use]
+    pub fn password(self, password: &str) -> Self {
+        self.password_provider(FixedPassword(Zeroizing::new(password.to_string())))
+    }
+
+    /// Sets the password provider to request the passwords from.
+    ///
+    /// See [`Archive::with_password_provider`].
+    #[must_use]
+    pub fn password_provider<P>(mut self, provider: P) -> Self
+    where
+        P: PasswordProvider + Send + 'static,
+    {
+        self.passwords.set_provider(Box::new(provider));
+        self
+    }
+
+    /// Sets the raw AES key to decrypt the archive.
+    ///
+    /// The key takes precedence over the password. See [`Archive::with_key`].
+    #[must_use]
+    pub fn key(mut self, key: [u8; 32]) -> Self {
+        self.passwords.set_key(Zeroizing::new(key));
+        self
+    }
+
+    /// Sets the cache of the keys derived from the passwords.
+    ///
+    /// See [`Archive::with_key_cache`].
+    #[must_use]
+    pub fn key_cache(mut self, keys: KeyCache) -> Self {
+        self.passwords.set_keys(keys);
+        self
+    }
+
+    /// Sets the registry of the codecs.
+    ///
+    /// The registered codecs are used to decode both the header and the
+    /// entries, taking precedence over the built-in methods.
+    #[must_use]
+    pub fn codecs(mut self, codecs: CodecRegistry) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Opens a 7z archive with these options.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the header could not be read.
+    pub fn open<R: Read + Seek>(self, reader: R) -> Result<Archive<R>> {
+        Archive::open(reader, self)
+    }
+}
+
+/// Skips `n` bytes of `reader`.
+fn skip<R: Read + ?Sized>(reader: &mut R, n: u64) -> Result<()> {
+    if io::copy(&mut Read::take(reader, n), &mut io::sink())? == n {
+        Ok(())
+    } else {
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+}
+
+/// Checks the size and the CRC of the data decoded from `folder`.
+///
+/// A wrong password for an encrypted folder is usually detected when the
+/// folder is opened. If the decrypted data are not decoded with LZMA or LZMA2,
+/// such as with Copy or only filters, it cannot be detected there, so a CRC
+/// mismatch is reported as [`Error::WrongPassword`] unless the password of the
+/// folder is `confirmed` by the data verified before.
+fn check_data(
+    folder: &Folder,
+    size: u64,
+    expected_size: u64,
+    crc: u32,
+    expected_crc: Option<u32>,
+    confirmed: bool,
+) -> Result<()> {
+    if size != expected_size {
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    } else if expected_crc.map_or(true, |c| c == crc) {
+        Ok(())
+    } else if !confirmed
+        && folder.uses(&Method::Crypto(Crypto::SzAes))
+        && !decoder::checks_password(folder)
+    {
+        Err(Error::WrongPassword)
+    } else {
+        Err(InvalidArchive::DataCrc.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{Error, WinAttributes};
+
+    const COPY: &[u8] = include_bytes!("../tests/data/copy.7z");
+    const LZMA: &[u8] = include_bytes!("../tests/data/lzma.7z");
+    const LZMA2: &[u8] = include_bytes!("../tests/data/lzma2.7z");
+    const ENCRYPTED: &[u8] = include_bytes!("../tests/data/encrypted.7z");
+    const ENCRYPTED_HEADER: &[u8] = include_bytes!("../tests/data/encrypted_header.7z");
+    const COPY_ENCRYPTED: &[u8] = include_bytes!("../tests/data/copy_encrypted.7z");
+    const MULTIPLE_PASSWORDS: &[u8] = include_bytes!("../tests/data/multiple_passwords.7z");
+    const ZSTD: &[u8] = include_bytes!("../tests/data/zstd.7z");
+    const SWAP2: &[u8] = include_bytes!("../tests/data/swap2.7z");
+    const SWAP4_DELTA: &[u8] = include_bytes!("../tests/data/swap4_delta.7z");
+    const CUSTOM: &[u8] = include_bytes!("../tests/data/custom.7z");
+    const UNKNOWN: &[u8] = include_bytes!("../tests/data/unknown.7z");
+    #[cfg(feature = "lz4")]
+    const LZ4: &[u8] = include_bytes!("../tests/data/lz4.7z");
+
+    const HELLO: &[u8] = b"Hello, world!\n";
+
+    fn lorem() -> Vec<u8> {
+        b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+          incididunt ut labore et dolore magna aliqua.\n"
+            .repeat(32)
+    }
+
+    /// A codec which XORs the data with the key in the properties.
+    struct Xor;
+
+    impl crate::Codec for Xor {
+        fn name(&self) -> &'static str {
+            "XOR"
+        }
+
+        fn check_properties(&self, properties: &[u8]) -> Result<()> {
+            if properties.len() == 1 {
+                Ok(())
+            } else {
+                Err(crate::error::InvalidArchive::Header.into())
+            }
+        }
+
+        fn decoder<'a>(
+            &self,
+            mut inputs: Vec<Box<dyn Read + 'a>>,
+            properties: &[u8],
+            unpack_size: u64,
+        ) -> Result<Box<dyn Read + 'a>> {
+            let mut buf = Vec::new();
+            inputs.remove(0).take(unpack_size).read_to_end(&mut buf)?;
+            for b in &mut buf {
+                *b ^= properties[0];
+            }
+            Ok(Box::new(Cursor::new(buf)))
+        }
+    }
+
+    fn check_entries(archive: &Archive<Cursor<&[u8]>>) {
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].name(), "dir");
+        assert!(entries[0].is_dir());
+        assert!(!entries[0].has_stream());
+        assert_eq!(entries[0].attributes(), Some(WinAttributes::DIRECTORY));
+        assert_eq!(entries[1].name(), "dir/hello.txt");
+        assert!(!entries[1].is_dir());
+        assert_eq!(entries[1].size(), 14);
+        assert_eq!(entries[1].crc(), Some(crc32fast::hash(HELLO)));
+        assert_eq!(
+            entries[1].modified(),
+            Some(crate::FileTime::from(132_539_328_000_000_000))
+        );
+        assert_eq!(entries[1].created(), None);
+        assert_eq!(entries[2].name(), "lorem.txt");
+        assert_eq!(entries[2].size(), 3968);
+        assert_eq!(entries[3].name(), "empty.txt");
+        assert!(!entries[3].is_dir());
+        assert!(!entries[3].has_stream());
+        assert_eq!(entries[3].size(), 0);
+    }
+
+    fn check_data(archive: &mut Archive<Cursor<&[u8]>>) {
+        let mut buf = Vec::new();
+        assert_eq!(archive.read_entry(2, &mut buf).unwrap(), 3968);
+        assert_eq!(buf, lorem());
+
+        let mut data = Vec::new();
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                data.push((entry.name().to_string(), buf));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            data,
+            [
+                ("dir".to_string(), Vec::new()),
+                ("dir/hello.txt".to_string(), HELLO.to_vec()),
+                ("lorem.txt".to_string(), lorem()),
+                ("empty.txt".to_string(), Vec::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn read_archive() {
+        for archive in [COPY, LZMA, LZMA2] {
+            let mut archive = Archive::new(Cursor::new(archive)).unwrap();
+            check_entries(&archive);
+            check_data(&mut archive);
+        }
+    }
+
+    #[test]
+    fn read_archive_with_filters() {
+        for archive in [SWAP2, SWAP4_DELTA] {
+            let mut archive = Archive::new(Cursor::new(archive)).unwrap();
+            check_entries(&archive);
+            check_data(&mut archive);
+        }
+    }
+
+    #[test]
+    fn list_methods() {
+        for (archive, methods) in [
+            (COPY, "Copy"),
+            (LZMA2, "LZMA2:16"),
+            (SWAP2, "LZMA2:16 Swap2"),
+            (SWAP4_DELTA, "LZMA2:16 Delta:4 Swap4"),
+            (UNKNOWN, "7F01"),
+        ] {
+            let archive = Archive::new(Cursor::new(archive)).unwrap();
+            assert!(archive.methods(0).is_none());
+            assert_eq!(archive.methods(1).unwrap().to_string(), methods);
+        }
+    }
+
+    #[test]
+    fn check_extractability() {
+        use Extractability::{Extractable, PasswordRequired, UnsupportedMethod};
+
+        let archive = Archive::new(Cursor::new(COPY)).unwrap();
+        assert!(archive
+            .extractability()
+            .iter()
+            .all(Extractability::is_extractable));
+
+        let unknown = UnsupportedMethod(Method::Unknown(vec![0x7f, 0x01]));
+        let archive = Archive::new(Cursor::new(UNKNOWN)).unwrap();
+        assert_eq!(
+            archive.extractability(),
+            [Extractable, unknown.clone(), unknown, Extractable]
+        );
+        let mut codecs = CodecRegistry::new();
+        codecs.register([0x7f, 0x01], Xor);
+        let archive = OpenOptions::new()
+            .codecs(codecs)
+            .open(Cursor::new(UNKNOWN))
+            .unwrap();
+        assert!(archive
+            .extractability()
+            .iter()
+            .all(Extractability::is_extractable));
+
+        let archive = Archive::new(Cursor::new(ENCRYPTED)).unwrap();
+        assert_eq!(
+            archive.extractability(),
+            [Extractable, PasswordRequired, PasswordRequired, Extractable]
+        );
+        let archive = Archive::with_password(Cursor::new(ENCRYPTED), "password").unwrap();
+        assert!(archive
+            .extractability()
+            .iter()
+            .all(Extractability::is_extractable));
+
+        let archive = Archive::new(Cursor::new(ZSTD)).unwrap();
+        assert_eq!(
+            archive.extractability()[1].is_extractable(),
+            cfg!(feature = "zstd")
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn read_zstd_archive() {
+        let mut archive = Archive::new(Cursor::new(ZSTD)).unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn read_lz4_archive() {
+        let mut archive = Archive::new(Cursor::new(LZ4)).unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn read_zstd_archive_without_feature() {
+        let mut archive = Archive::new(Cursor::new(ZSTD)).unwrap();
+        check_entries(&archive);
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::UnsupportedArchive(crate::error::UnsupportedArchive::CompressionMethod(name))
+                if name == "ZSTD"
+        ));
+    }
+
+    #[test]
+    fn read_archive_with_custom_codec() {
+        let mut codecs = CodecRegistry::new();
+        codecs.register([0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01], Xor);
+        let mut archive = OpenOptions::new()
+            .codecs(codecs)
+            .open(Cursor::new(CUSTOM))
+            .unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+    }
+
+    #[test]
+    fn read_archive_without_custom_codec() {
+        let mut archive = Archive::new(Cursor::new(CUSTOM)).unwrap();
+        check_entries(&archive);
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::UnsupportedArchive(crate::error::UnsupportedArchive::UnknownMethod(id))
+                if id == [0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]
+        ));
+    }
+
+    #[test]
+    fn read_archive_with_unknown_method() {
+        let mut archive = Archive::new(Cursor::new(UNKNOWN)).unwrap();
+        check_entries(&archive);
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::UnsupportedArchive(crate::error::UnsupportedArchive::UnknownMethod(id))
+                if id == [0x7f, 0x01]
+        ));
+
+        let mut codecs = CodecRegistry::new();
+        codecs.register([0x7f, 0x01], Xor);
+        let mut archive = OpenOptions::new()
+            .codecs(codecs)
+            .open(Cursor::new(UNKNOWN))
+            .unwrap();
+        check_data(&mut archive);
+    }
+
+    #[test]
+    fn read_archive_with_overridden_codec() {
+        let mut codecs = CodecRegistry::new();
+        codecs.register([0x00], Xor);
+        let mut archive = OpenOptions::new()
+            .codecs(codecs)
+            .open(Cursor::new(COPY))
+            .unwrap();
+        let mut buf = Vec::new();
+        assert!(matches!(
+            archive.read_entry(1, &mut buf).unwrap_err(),
+            Error::InvalidArchive(crate::error::InvalidArchive::Header)
+        ));
+    }
+
+    #[test]
+    fn read_empty_archive() {
+        let mut buf = crate::property::SIGNATURE.to_vec();
+        buf.extend([0x00, 0x04]);
+        buf.extend(crc32fast::hash(&[0x00; 20]).to_le_bytes());
+        buf.extend([0x00; 20]);
+        let archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert!(archive.entries().is_empty());
+    }
+
+    #[test]
+    fn read_archive_with_skipped_data() {
+        let mut archive = Archive::new(Cursor::new(LZMA2)).unwrap();
+        let mut names = Vec::new();
+        archive
+            .for_each_entry(|entry, _| {
+                names.push(entry.name().to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(names, ["dir", "dir/hello.txt", "lorem.txt", "empty.txt"]);
+    }
+
+    #[test]
+    fn read_archive_with_corrupted_data() {
+        let mut buf = COPY.to_vec();
+        buf[32] ^= 0xff;
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::DataCrc)
+        ));
+        assert!(archive.read_entry(2, &mut io::sink()).is_ok());
+    }
+
+    #[test]
+    fn read_encrypted_archive_with_corrupted_data() {
+        let archive = Archive::new(Cursor::new(ENCRYPTED)).unwrap();
+        let pack_size = archive.streams_info.pack_info.pack_sizes[0];
+        let mut buf = ENCRYPTED.to_vec();
+        buf[usize::try_from(SIGNATURE_HEADER_SIZE + pack_size / 2).unwrap()] ^= 0x01;
+        let mut archive = Archive::with_password(Cursor::new(&buf[..]), "password").unwrap();
+        assert!(matches!(
+            archive.read_entry(2, &mut io::sink()).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::DataCrc)
+        ));
+        assert!(matches!(
+            archive.read_entry(2, &mut io::sink()).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::DataCrc)
+        ));
+
+        // The corrupted data of a Copy folder are reported as a wrong password
+        // until the password is confirmed.
+        let mut buf = COPY_ENCRYPTED.to_vec();
+        buf[usize::try_from(SIGNATURE_HEADER_SIZE).unwrap()] ^= 0x01;
+        let mut archive = Archive::with_password(Cursor::new(&buf[..]), "password").unwrap();
+        assert!(matches!(
+            archive.read_entry(0, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+
+    #[test]
+    fn read_archive_with_corrupted_header() {
+        let mut buf = COPY.to_vec();
+        let len = buf.len();
+        buf[len - 1] ^= 0xff;
+        assert!(matches!(
+            Archive::new(Cursor::new(&buf[..])).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::NextHeaderCrc)
+        ));
+    }
+
+    #[test]
+    fn read_encrypted_archive() {
+        let mut archive = Archive::new(Cursor::new(ENCRYPTED)).unwrap();
+        check_entries(&archive);
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::PasswordRequired
+        ));
+
+        let mut archive = Archive::with_password(Cursor::new(ENCRYPTED), "password").unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+    }
+
+    #[test]
+    fn read_archive_with_encrypted_header() {
+        assert!(matches!(
+            Archive::new(Cursor::new(ENCRYPTED_HEADER)).unwrap_err(),
+            Error::PasswordRequired
+        ));
+
+        let mut archive =
+            Archive::with_password(Cursor::new(ENCRYPTED_HEADER), "password").unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+    }
+
+    #[test]
+    fn read_encrypted_archive_with_wrong_password() {
+        let mut archive = Archive::with_password(Cursor::new(ENCRYPTED), "wrong").unwrap();
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+        assert!(matches!(
+            archive.for_each_entry(|_, _| Ok(())).unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+
+    #[test]
+    fn read_copy_encrypted_archive_with_wrong_password() {
+        let mut archive = Archive::with_password(Cursor::new(COPY_ENCRYPTED), "password").unwrap();
+        assert_eq!(archive.methods(0).unwrap().to_string(), "7zAES:6 Copy");
+        let mut buf = Vec::new();
+        archive.read_entry(0, &mut buf).unwrap();
+        assert_eq!(buf, HELLO);
+
+        // Copy has no stream to check, so a wrong password is detected by the
+        // CRC.
+        let mut archive = Archive::with_password(Cursor::new(COPY_ENCRYPTED), "wrong").unwrap();
+        assert!(matches!(
+            archive.read_entry(0, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+        assert!(matches!(
+            archive
+                .for_each_entry(|_, reader| {
+                    io::copy(reader, &mut io::sink())?;
+                    Ok(())
+                })
+                .unwrap_err(),
+            Error::WrongPassword
+        ));
+
+        // The next password is requested after the wrong one is detected.
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let provider = {
+            let attempts = Arc::clone(&attempts);
+            move |folder: usize, attempt: usize| {
+                attempts.lock().unwrap().push((folder, attempt));
+                ["wrong", "password"]
+                    .get(attempt - 1)
+                    .map(|&p| String::from(p))
+            }
+        };
+        let mut archive =
+            Archive::with_password_provider(Cursor::new(COPY_ENCRYPTED), provider).unwrap();
+        assert!(matches!(
+            archive.read_entry(0, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+        let mut buf = Vec::new();
+        archive.read_entry(0, &mut buf).unwrap();
+        assert_eq!(buf, HELLO);
+        assert_eq!(*attempts.lock().unwrap(), [(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn read_encrypted_archive_with_password_provider() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let provider = {
+            let attempts = Arc::clone(&attempts);
+            move |folder: usize, attempt: usize| {
+                attempts.lock().unwrap().push((folder, attempt));
+                ["wrong", "password"]
+                    .get(attempt - 1)
+                    .map(|&p| String::from(p))
+            }
+        };
+        let mut archive =
+            Archive::with_password_provider(Cursor::new(ENCRYPTED_HEADER), provider).unwrap();
+        assert_eq!(*attempts.lock().unwrap(), [(0, 1), (0, 2)]);
+        check_entries(&archive);
+        check_data(&mut archive);
+        // The password of the header is tried first for the data.
+        assert_eq!(*attempts.lock().unwrap(), [(0, 1), (0, 2)]);
+
+        let mut archive = Archive::with_password_provider(Cursor::new(ENCRYPTED), |_, attempt| {
+            (attempt < 4).then(|| String::from("wrong"))
+        })
+        .unwrap();
+        assert!(matches!(
+            archive.read_entry(1, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+
+    #[test]
+    fn read_encrypted_archive_with_key() {
+        let properties = crate::method::AesProps {
+            cycles_power: 6,
+            ..Default::default()
+        };
+        let key =
+            crate::crypto::derive_key(&properties, &crate::crypto::encode_password("password"))
+                .unwrap();
+        let mut archive = Archive::with_key(Cursor::new(ENCRYPTED_HEADER), *key).unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+
+        assert!(matches!(
+            Archive::with_key(Cursor::new(ENCRYPTED_HEADER), [u8::default(); 32]).unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+
+    #[test]
+    fn read_encrypted_archives_with_key_cache() {
+        let keys = KeyCache::new();
+        let provider = |_, attempt| (attempt == 1).then(|| String::from("password"));
+        let mut archive =
+            Archive::with_key_cache(Cursor::new(ENCRYPTED_HEADER), provider, keys.clone()).unwrap();
+        assert_eq!(keys.len(), 1);
+        check_data(&mut archive);
+        assert_eq!(keys.len(), 1);
+
+        let mut archive =
+            Archive::with_key_cache(Cursor::new(ENCRYPTED), provider, keys.clone()).unwrap();
+        check_data(&mut archive);
+        assert_eq!(keys.len(), 1);
+
+        let mut archive =
+            Archive::with_key_cache(Cursor::new(MULTIPLE_PASSWORDS), provider, keys.clone())
+                .unwrap();
+        assert!(archive.read_entry(2, &mut io::sink()).is_err());
+        assert_eq!(keys.len(), 1);
+        // The keys of wrong passwords are not cached.
+        let keys = KeyCache::new();
+        let mut archive = Archive::with_key_cache(
+            Cursor::new(ENCRYPTED),
+            |_, attempt| (attempt == 1).then(|| String::from("wrong")),
+            keys.clone(),
+        )
+        .unwrap();
+        assert!(archive.read_entry(1, &mut io::sink()).is_err());
+        assert!(keys.is_empty());
+        assert!(Archive::with_key_cache(
+            Cursor::new(ENCRYPTED_HEADER),
+            |_, attempt: usize| {
+                ["wrong", "password"]
+                    .get(attempt - 1)
+                    .map(|&p| String::from(p))
+            },
+            keys.clone(),
+        )
+        .is_ok());
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn read_archive_with_multiple_passwords() {
+        let provider = |folder, attempt| match (folder, attempt) {
+            (0 | 1, 1) => Some(String::from("foo")),
+            (1, 2) => Some(String::from("bar")),
+            _ => None,
+        };
+        let mut archive =
+            Archive::with_password_provider(Cursor::new(MULTIPLE_PASSWORDS), provider).unwrap();
+        check_entries(&archive);
+        check_data(&mut archive);
+
+        let mut archive = Archive::with_password(Cursor::new(MULTIPLE_PASSWORDS), "foo").unwrap();
+        let mut buf = Vec::new();
+        archive.read_entry(1, &mut buf).unwrap();
+        assert_eq!(buf, HELLO);
+        assert!(matches!(
+            archive.read_entry(2, &mut io::sink()).unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+
+    #[test]
+    fn read_archive_with_encrypted_header_and_wrong_password() {
+        assert!(matches!(
+            Archive::with_password(Cursor::new(ENCRYPTED_HEADER), "wrong").unwrap_err(),
+            Error::WrongPassword
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Windows attributes of entries.
+
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, BitOrAssign},
+};
+
+/// The mask of the file type of the Unix mode.
+const S_IFMT: u32 = 0o170_000;
+
+/// The mask of the permission bits of the Unix mode.
+pub const PERMISSION_MASK: u32 = 0o7777;
+
+/// The kind of a file, as the file type of the Unix mode.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FileType {
+    /// A regular file.
+    Regular,
+
+    /// A directory.
+    Directory,
+
+    /// A symbolic link.
+    Symlink,
+
+    /// A block device.
+    BlockDevice,
+
+    /// A character device.
+    CharDevice,
+
+    /// A named pipe.
+    Fifo,
+
+    /// A socket.
+    Socket,
+}
+
+impl FileType {
+    /// Returns the file type of the Unix mode, such as `0o040755`.
+    ///
+    /// Returns [`None`] if the mode has no file type or an unknown one.
+    #[must_use]
+    pub const fn from_unix_mode(mode: u32) -> Option<Self> {
+        match mode & S_IFMT {
+            0o100_000 => Some(Self::Regular),
+            0o040_000 => Some(Self::Directory),
+            0o120_000 => Some(Self::Symlink),
+            0o060_000 => Some(Self::BlockDevice),
+            0o020_000 => Some(Self::CharDevice),
+            0o010_000 => Some(Self::Fifo),
+            0o140_000 => Some(Self::Socket),
+            _ => None,
+        }
+    }
+
+    /// Returns the bits of the Unix mode for this file type.
+    #[must_use]
+    pub const fn unix_bits(self) -> u32 {
+        match self {
+            Self::Regular => 0o100_000,
+            Self::Directory => 0o040_000,
+            Self::Symlink => 0o120_000,
+            Self::BlockDevice => 0o060_000,
+            Self::CharDevice => 0o020_000,
+            Self::Fifo => 0o010_000,
+            Self::Socket => 0o140_000,
+        }
+    }
+}
+
+/// The Windows attributes of an entry, as the `kWinAttributes` property
+/// stores them.
+///
+/// p7zip and 7-Zip on Unix store the Unix mode in the high 16 bits of the
+/// attributes and set [`WinAttributes::UNIX_EXTENSION`]. The bits which have
+/// no name are kept as they are.
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+pub struct WinAttributes(u32);
+
+impl WinAttributes {
+    /// The file is read-only.
+    pub const READONLY: Self = Self(0x0001);
+
+    /// The file is hidden.
+    pub const HIDDEN: Self = Self(0x0002);
+
+    /// The file is used by the operating system.
+    pub const SYSTEM: Self = Self(0x0004);
+
+    /// The entry is a directory.
+    pub const DIRECTORY: Self = Self(0x0010);
+
+    /// The file should be archived.
+    pub const ARCHIVE: Self = Self(0x0020);
+
+    /// The entry is a device.
+    pub const DEVICE: Self = Self(0x0040);
+
+    /// The file has no other attributes.
+    pub const NORMAL: Self = Self(0x0080);
+
+    /// The file is temporary.
+    pub const TEMPORARY: Self = Self(0x0100);
+
+    /// The file is a sparse file.
+    pub const SPARSE_FILE: Self = Self(0x0200);
+
+    /// The entry is a reparse point, such as a symbolic link.
+    pub const REPARSE_POINT: Self = Self(0x0400);
+
+    /// The file is compressed.
+    pub const COMPRESSED: Self = Self(0x0800);
+
+    /// The data of the file are not available immediately.
+    pub const OFFLINE: Self = Self(0x1000);
+
+    /// The file is not indexed by the content indexing service.
+    pub const NOT_CONTENT_INDEXED: Self = Self(0x2000);
+
+    /// The file is encrypted.
+    pub const ENCRYPTED: Self = Self(0x4000);
+
+    /// The high 16 bits are the Unix mode.
+    pub const UNIX_EXTENSION: Self = Self(0x8000);
+
+    /// Returns the attributes without any flags.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new `WinAttributes` from the attribute word.
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the attribute word.
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if all the flags of `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Sets the flags of `other`.
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clears the flags of `other`.
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Returns the Unix mode in the high 16 bits, such as `0o100644`.
+    ///
+    /// Returns [`None`] if [`WinAttributes::UNIX_EXTENSION`] is not set.
+    #[must_use]
+    pub const fn unix_mode(self) -> Option<u32> {
+        if self.contains(Self::UNIX_EXTENSION) {
+            Some(self.0 >> 16)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the attributes with the Unix mode in the high 16 bits.
+    ///
+    /// [`None`] removes the Unix mode.
+    #[must_use]
+    pub const fn with_unix_mode(self, mode: Option<u32>) -> Self {
+        let windows = self.0 & 0x7fff;
+        match mode {
+            Some(mode) => Self(windows | Self::UNIX_EXTENSION.0 | ((mode & 0xffff) << 16)),
+            None => Self(windows),
+        }
+    }
+
+    /// Returns the permission bits of the Unix mode, such as `0o644`.
+    #[must_use]
+    pub const fn unix_permissions(self) -> Option<u32> {
+        match self.unix_mode() {
+            Some(mode) => Some(mode & PERMISSION_MASK),
+            None => None,
+        }
+    }
+
+    /// Returns the file type.
+    ///
+    /// The file type of the Unix mode is used if it is known. Otherwise, an
+    /// entry with [`WinAttributes::DIRECTORY`] is a directory, and the others
+    /// are regular files.
+    #[must_use]
+    pub const fn file_type(self) -> FileType {
+        if let Some(mode) = self.unix_mode() {
+            if let Some(file_type) = FileType::from_unix_mode(mode) {
+                return file_type;
+            }
+        }
+        if self.contains(Self::DIRECTORY) {
+            FileType::Directory
+        } else {
+            FileType::Regular
+        }
+    }
+}
+
+impl fmt::Debug for WinAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WinAttributes({:#x})", self.0)
+    }
+}
+
+impl fmt::Display for WinAttributes {
+    /// Formats the main flags as `7z l` does, such as `D....` or `.R..A`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, c) in [
+            (Self::DIRECTORY, 'D'),
+            (Self::READONLY, 'R'),
+            (Self::HIDDEN, 'H'),
+            (Self::SYSTEM, 'S'),
+            (Self::ARCHIVE, 'A'),
+        ] {
+            let c = if self.contains(flag) { c } else { '.' };
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<u32> for WinAttributes {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<WinAttributes> for u32 {
+    fn from(attributes: WinAttributes) -> Self {
+        attributes.0
+    }
+}
+
+impl BitOr for WinAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for WinAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for WinAttributes {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        let mut attributes = WinAttributes::READONLY | WinAttributes::ARCHIVE;
+        assert_eq!(attributes.bits(), 0x21);
+        assert!(attributes.contains(WinAttributes::READONLY));
+        assert!(!attributes.contains(WinAttributes::READONLY | WinAttributes::HIDDEN));
+        attributes.insert(WinAttributes::HIDDEN);
+        attributes.remove(WinAttributes::READONLY);
+        assert_eq!(attributes, WinAttributes::from_bits(0x22));
+        assert_eq!(attributes & WinAttributes::HIDDEN, WinAttributes::HIDDEN);
+        assert_eq!(u32::from(WinAttributes::from(0x8000_0010)), 0x8000_0010);
+        assert_eq!(format!("{attributes:?}"), "WinAttributes(0x22)");
+        assert_eq!(attributes.to_string(), "..H.A");
+        assert_eq!(WinAttributes::DIRECTORY.to_string(), "D....");
+    }
+
+    #[test]
+    fn unix_mode() {
+        let attributes = WinAttributes::from_bits(0x41ed_8010);
+        assert_eq!(attributes.unix_mode(), Some(0o040_755));
+        assert_eq!(attributes.unix_permissions(), Some(0o755));
+        assert_eq!(attributes.file_type(), FileType::Directory);
+
+        let attributes = WinAttributes::ARCHIVE.with_unix_mode(Some(0o120_777));
+        assert_eq!(attributes.bits(), 0xa1ff_8020);
+        assert_eq!(attributes.file_type(), FileType::Symlink);
+        let attributes = attributes.with_unix_mode(None);
+        assert_eq!(attributes, WinAttributes::ARCHIVE);
+        assert_eq!(attributes.unix_mode(), None);
+        assert_eq!(attributes.file_type(), FileType::Regular);
+
+        // A directory created on Linux may lack the directory attribute.
+        let attributes = WinAttributes::empty().with_unix_mode(Some(0o040_700));
+        assert_eq!(attributes.file_type(), FileType::Directory);
+        let attributes = WinAttributes::DIRECTORY.with_unix_mode(Some(0o755));
+        assert_eq!(attributes.file_type(), FileType::Directory);
+
+        for file_type in [
+            FileType::Regular,
+            FileType::Directory,
+            FileType::Symlink,
+            FileType::BlockDevice,
+            FileType::CharDevice,
+            FileType::Fifo,
+            FileType::Socket,
+        ] {
+            assert_eq!(
+                FileType::from_unix_mode(file_type.unix_bits() | 0o644),
+                Some(file_type)
+            );
+        }
+        assert_eq!(FileType::from_unix_mode(0o644), None);
+    }
+}
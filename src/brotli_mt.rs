@@ -0,0 +1,239 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Brotli streams in the frame format of brotli-mt.
+//!
+//! 7-Zip-zstd splits the Brotli stream into frames, each of which starts with
+//! a 16-byte skippable frame header:
+//!
+//! | Offset | Size | Field                                  |
+//! | ------ | ---- | -------------------------------------- |
+//! | 0      | 4    | Skippable frame magic (`0x184D2A50`)   |
+//! | 4      | 4    | Size of the rest of the header (8)     |
+//! | 8      | 4    | Compressed size of the frame           |
+//! | 12     | 2    | Brotli-mt magic (`0x5242`)             |
+//! | 14     | 2    | Uncompressed size of the frame / 64KiB |
+
+use std::io::{self, Cursor, Read, Write};
+
+use brotli::{CompressorWriter, Decompressor};
+
+/// The magic number of a skippable frame.
+const MAGIC_SKIPPABLE: u32 = 0x184d_2a50;
+
+/// The magic number of brotli-mt.
+const MAGIC: u16 = 0x5242;
+
+/// The size of the frame header.
+const HEADER_SIZE: usize = 16;
+
+/// The uncompressed size of a frame.
+const FRAME_SIZE: usize = 1 << 22;
+
+/// The size of the internal buffer of Brotli.
+const BUFFER_SIZE: usize = 4096;
+
+/// The base 2 logarithm of the window size of Brotli.
+const LG_WINDOW_SIZE: u32 = 22;
+
+/// Returns the reader which decompresses the Brotli stream.
+///
+/// Both a brotli-mt stream and a plain Brotli stream are supported.
+pub fn reader<'a, R: Read + 'a>(mut inner: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut magic = Vec::with_capacity(4);
+    inner.by_ref().take(4).read_to_end(&mut magic)?;
+    if magic[..] != MAGIC_SKIPPABLE.to_le_bytes() {
+        let inner = Cursor::new(magic).chain(inner);
+        return Ok(Box::new(Decompressor::new(inner, BUFFER_SIZE)));
+    }
+    let mut reader = BrotliReader { inner, frame: None };
+    reader.frame = Some(reader.read_frame(&magic)?);
+    Ok(Box::new(reader))
+}
+
+/// A reader which decompresses a brotli-mt stream.
+struct BrotliReader<R> {
+    inner: R,
+    frame: Option<Decompressor<Cursor<Vec<u8>>>>,
+}
+
+impl<R: Read> BrotliReader<R> {
+    /// Reads the next frame.
+    ///
+    /// `magic` is the first bytes of the frame header which have already been
+    /// read.
+    fn read_frame(&mut self, magic: &[u8]) -> io::Result<Decompressor<Cursor<Vec<u8>>>> {
+        let mut header = [u8::default(); HEADER_SIZE];
+        header[..magic.len()].copy_from_slice(magic);
+        self.inner.read_exact(&mut header[magic.len()..])?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid brotli-mt frame");
+        if header[..4] != MAGIC_SKIPPABLE.to_le_bytes()
+            || header[4..8] != 8_u32.to_le_bytes()
+            || header[12..14] != MAGIC.to_le_bytes()
+        {
+            return Err(invalid());
+        }
+        let size = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        let mut frame = Vec::new();
+        self.inner
+            .by_ref()
+            .take(u64::from(size))
+            .read_to_end(&mut frame)?;
+        if frame.len() != size as usize {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Decompressor::new(Cursor::new(frame), BUFFER_SIZE))
+    }
+}
+
+impl<R: Read> Read for BrotliReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let frame = match self.frame.as_mut() {
+                Some(frame) => frame,
+                None => return Ok(0),
+            };
+            let n = frame.read(buf)?;
+            if n != 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let mut magic = Vec::with_capacity(4);
+            self.inner.by_ref().take(4).read_to_end(&mut magic)?;
+            self.frame = if magic.is_empty() {
+                None
+            } else {
+                Some(self.read_frame(&magic)?)
+            };
+        }
+    }
+}
+
+/// A writer which compresses data into a brotli-mt stream.
+///
+/// At least one frame is written, so the stream of empty data is also valid.
+pub struct BrotliWriter<W> {
+    inner: W,
+    quality: u32,
+    buf: Vec<u8>,
+    is_empty: bool,
+}
+
+impl<W: Write> BrotliWriter<W> {
+    /// Creates a new `BrotliWriter`.
+    pub const fn new(inner: W, quality: u32) -> Self {
+        Self {
+            inner,
+            quality,
+            buf: Vec::new(),
+            is_empty: true,
+        }
+    }
+
+    /// Compresses the buffered data into a frame.
+    fn write_frame(&mut self) -> io::Result<()> {
+        let mut frame =
+            CompressorWriter::new(Vec::new(), BUFFER_SIZE, self.quality, LG_WINDOW_SIZE);
+        frame.write_all(&self.buf)?;
+        let frame = frame.into_inner();
+        let size = u32::try_from(frame.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let hint = u16::try_from(self.buf.len() >> 16).unwrap_or(u16::MAX);
+
+        let mut header = [u8::default(); HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC_SKIPPABLE.to_le_bytes());
+        header[4..8].copy_from_slice(&8_u32.to_le_bytes());
+        header[8..12].copy_from_slice(&size.to_le_bytes());
+        header[12..14].copy_from_slice(&MAGIC.to_le_bytes());
+        header[14..].copy_from_slice(&hint.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&frame)?;
+        self.buf.clear();
+        self.is_empty = false;
+        Ok(())
+    }
+
+    /// Writes the remaining data, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.is_empty || !self.buf.is_empty() {
+            self.write_frame()?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BrotliWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(FRAME_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == FRAME_SIZE {
+            self.write_frame()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(data: Vec<u8>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reader(Cursor::new(data))
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"Hello, world!\n".repeat(1024);
+        let mut writer = BrotliWriter::new(Vec::new(), 11);
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+        assert_eq!(compressed[..4], MAGIC_SKIPPABLE.to_le_bytes());
+        assert_eq!(compressed[12..14], MAGIC.to_le_bytes());
+        assert_eq!(decompress(compressed), data);
+    }
+
+    #[test]
+    fn round_trip_with_multiple_frames() {
+        let data = (0..=FRAME_SIZE * 2)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect::<Vec<_>>();
+        let mut writer = BrotliWriter::new(Vec::new(), 1);
+        writer.write_all(&data).unwrap();
+        assert_eq!(decompress(writer.finish().unwrap()), data);
+    }
+
+    #[test]
+    fn read_plain_stream() {
+        let data = b"Hello, world!\n".repeat(16);
+        let mut writer = CompressorWriter::new(Vec::new(), BUFFER_SIZE, 11, LG_WINDOW_SIZE);
+        writer.write_all(&data).unwrap();
+        assert_eq!(decompress(writer.into_inner()), data);
+    }
+
+    #[test]
+    fn read_empty_stream() {
+        let writer = BrotliWriter::new(Vec::new(), 11);
+        let compressed = writer.finish().unwrap();
+        assert!(compressed.len() > HEADER_SIZE);
+        assert!(decompress(compressed).is_empty());
+    }
+
+    #[test]
+    fn read_invalid_frame() {
+        let mut data = vec![u8::default(); HEADER_SIZE];
+        data[..4].copy_from_slice(&MAGIC_SKIPPABLE.to_le_bytes());
+        assert!(reader(Cursor::new(data)).is_err());
+    }
+}
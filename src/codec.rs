@@ -0,0 +1,241 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Pluggable codecs for methods.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Read, Write},
+    sync::Arc,
+};
+
+pub use crate::encoder::Encoder;
+use crate::{
+    error::UnsupportedArchive,
+    method::{Method, MethodSpec},
+    Result,
+};
+
+/// A trait for codecs which encode and decode the data of a method.
+///
+/// A codec is registered in a [`CodecRegistry`] with the ID of the method. It
+/// is mainly used for methods with a random ID (`3F ZZ ZZ ZZ ZZ ZZ MM MM`) as
+/// described in `Methods.txt`, but can also override the built-in methods.
+pub trait Codec: Send + Sync {
+    /// Returns the name of the method.
+    fn name(&self) -> &str;
+
+    /// Returns the number of the packed streams of a coder.
+    ///
+    /// The decoder receives this number of inputs. The default is 1.
+    fn num_packed_streams(&self) -> usize {
+        1
+    }
+
+    /// Checks the properties of a coder.
+    ///
+    /// This is called before [`Codec::decoder`] is called. The default accepts
+    /// any properties.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    fn check_properties(&self, properties: &[u8]) -> Result<()> {
+        let _ = properties;
+        Ok(())
+    }
+
+    /// Returns the reader which decodes the data from the packed `inputs`.
+    ///
+    /// `unpack_size` is the size of the decoded data.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the decoder could not be created.
+    fn decoder<'a>(
+        &self,
+        inputs: Vec<Box<dyn Read + 'a>>,
+        properties: &[u8],
+        unpack_size: u64,
+    ) -> Result<Box<dyn Read + 'a>>;
+
+    /// Returns `true` if this codec supports encoding.
+    ///
+    /// This should return `true` if [`Codec::encoder`] is implemented. The
+    /// default is `false`.
+    fn can_encode(&self) -> bool {
+        false
+    }
+
+    /// Returns the properties of a coder and the encoder which writes the
+    /// encoded data into `output`.
+    ///
+    /// `spec` is the method with the parameters given by the user, such as
+    /// the ones of `LZMA2:d=64m`. The properties should be parsed from them, so
+    /// that [`Codec::check_properties`] and [`Codec::decoder`] accept the
+    /// returned properties. `level` is the compression level. The default does
+    /// not support encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if encoding is not supported, or if the encoder could
+    /// not be created.
+    fn encoder<'a>(
+        &self,
+        output: Box<dyn Write + 'a>,
+        spec: &MethodSpec,
+        level: u32,
+    ) -> Result<(Vec<u8>, Box<dyn Encoder + 'a>)> {
+        let _ = (output, spec, level);
+        Err(UnsupportedArchive::CompressionMethod(self.name().to_string()).into())
+    }
+}
+
+/// A registry of codecs by the ID of the method.
+///
+/// The registered codecs take precedence over the built-in methods.
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    codecs: HashMap<Vec<u8>, Arc<dyn Codec>>,
+}
+
+impl CodecRegistry {
+    /// Creates a new empty `CodecRegistry`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the codec for the method with `id`.
+    ///
+    /// Returns the codec which was previously registered with `id`, if any.
+    pub fn register<C: Codec + 'static>(
+        &mut self,
+        id: impl Into<Vec<u8>>,
+        codec: C,
+    ) -> Option<Arc<dyn Codec>> {
+        self.codecs.insert(id.into(), Arc::new(codec))
+    }
+
+    /// Unregisters the codec for the method with `id`.
+    ///
+    /// Returns the codec which was registered with `id`, if any.
+    pub fn unregister(&mut self, id: &[u8]) -> Option<Arc<dyn Codec>> {
+        self.codecs.remove(id)
+    }
+
+    /// Returns the codec for the method with `id`.
+    #[must_use]
+    pub fn get(&self, id: &[u8]) -> Option<&dyn Codec> {
+        self.codecs.get(id).map(AsRef::as_ref)
+    }
+
+    /// Returns `true` if the method can be decoded by a registered codec or by
+    /// this build.
+    #[must_use]
+    pub fn can_decode(&self, method: &Method) -> bool {
+        self.get(method.id()).is_some() || method.can_decode()
+    }
+
+    /// Returns `true` if the method can be encoded by a registered codec or by
+    /// this build.
+    ///
+    /// A registered codec which does not support encoding takes precedence
+    /// over the built-in method.
+    #[must_use]
+    pub fn can_encode(&self, method: &Method) -> bool {
+        self.get(method.id())
+            .map_or_else(|| method.can_encode(), Codec::can_encode)
+    }
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.codecs.iter().map(|(id, codec)| (id, codec.name())))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    struct Reverse;
+
+    impl Codec for Reverse {
+        fn name(&self) -> &'static str {
+            "Reverse"
+        }
+
+        fn decoder<'a>(
+            &self,
+            mut inputs: Vec<Box<dyn Read + 'a>>,
+            _: &[u8],
+            _: u64,
+        ) -> Result<Box<dyn Read + 'a>> {
+            let mut buf = Vec::new();
+            inputs[0].read_to_end(&mut buf)?;
+            buf.reverse();
+            Ok(Box::new(io::Cursor::new(buf)))
+        }
+    }
+
+    #[test]
+    fn register_codec() {
+        let mut registry = CodecRegistry::new();
+        assert!(registry.get(&[0x3f]).is_none());
+        assert!(registry.register([0x3f], Reverse).is_none());
+        assert_eq!(registry.get(&[0x3f]).unwrap().name(), "Reverse");
+        assert!(registry.register(vec![0x3f], Reverse).is_some());
+        assert!(registry.unregister(&[0x3f]).is_some());
+        assert!(registry.get(&[0x3f]).is_none());
+    }
+
+    #[test]
+    fn default_codec_methods() {
+        let codec = Reverse;
+        assert_eq!(codec.num_packed_streams(), 1);
+        assert!(!codec.can_encode());
+        assert!(codec.check_properties(&[0x00]).is_ok());
+        assert!(codec
+            .encoder(Box::new(io::sink()), &Method::Copy.into(), 0)
+            .is_err());
+
+        let mut buf = Vec::new();
+        codec
+            .decoder(vec![Box::new(&b"abc"[..])], &[], 3)
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        assert_eq!(buf, b"cba");
+    }
+
+    #[test]
+    fn codec_capabilities() {
+        let custom = Method::Custom(vec![0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        let mut registry = CodecRegistry::new();
+        assert!(!registry.can_decode(&custom));
+        assert!(registry.can_decode(&Method::Lzma2));
+        assert!(registry.can_encode(&Method::Copy));
+
+        registry.register(custom.id(), Reverse);
+        registry.register([0x00], Reverse);
+        assert!(registry.can_decode(&custom));
+        assert!(!registry.can_encode(&custom));
+        assert!(!registry.can_encode(&Method::Copy));
+    }
+
+    #[test]
+    fn debug_registry() {
+        let mut registry = CodecRegistry::new();
+        registry.register([0x3f], Reverse);
+        assert_eq!(format!("{registry:?}"), r#"{[63]: "Reverse"}"#);
+    }
+}
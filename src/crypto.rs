@@ -0,0 +1,469 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! 7zAES decryption.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Read},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use aes::Aes256;
+use cbc::cipher::{generic_array::GenericArray, BlockDecryptMut, KeyIvInit};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{error::UnsupportedArchive, method::AesProps, Result};
+
+/// The block size of AES.
+const BLOCK_SIZE: usize = 16;
+
+/// The largest number of cycles power supported by 7-Zip.
+const MAX_CYCLES_POWER: u8 = 24;
+
+/// The number of cycles power which means no key stretching.
+const NO_KEY_STRETCHING: u8 = 0x3f;
+
+/// The default number of the keys which a [`KeyCache`] holds.
+const DEFAULT_CAPACITY: usize = 64;
+
+/// Derives the AES key from the password.
+///
+/// `password` is the password encoded in UTF-16LE.
+pub fn derive_key(properties: &AesProps, password: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([u8::default(); 32]);
+    if properties.cycles_power == NO_KEY_STRETCHING {
+        for (k, &b) in key.iter_mut().zip(properties.salt.iter().chain(password)) {
+            *k = b;
+        }
+    } else if properties.cycles_power <= MAX_CYCLES_POWER {
+        let mut hasher = Sha256::new();
+        for i in 0..(1_u64 << properties.cycles_power) {
+            hasher.update(&properties.salt);
+            hasher.update(password);
+            hasher.update(i.to_le_bytes());
+        }
+        hasher.finalize_into(GenericArray::from_mut_slice(key.as_mut_slice()));
+    } else {
+        return Err(UnsupportedArchive::CompressionMethod(format!(
+            "7zAES with 2^{} cycles",
+            properties.cycles_power
+        ))
+        .into());
+    }
+    Ok(key)
+}
+
+/// Returns the digest of the password, the salt and the number of cycles
+/// power, by which the derived key is cached.
+fn key_id(properties: &AesProps, password: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([properties.cycles_power]);
+    hasher.update((properties.salt.len() as u64).to_le_bytes());
+    hasher.update(&properties.salt);
+    hasher.update(password);
+    hasher.finalize().into()
+}
+
+/// The keys derived from a password which are not cached yet.
+type PendingKeys = Vec<([u8; 32], Zeroizing<[u8; 32]>)>;
+
+/// A secret to decrypt 7zAES.
+pub enum Secret {
+    /// The password encoded in UTF-16LE.
+    Password {
+        /// The encoded password.
+        password: Zeroizing<Vec<u8>>,
+
+        /// The keys derived from the password which are not known to be
+        /// correct yet.
+        pending: Mutex<PendingKeys>,
+    },
+
+    /// The raw AES key.
+    Key(Zeroizing<[u8; 32]>),
+}
+
+impl Secret {
+    /// Creates a new `Secret` from the password encoded in UTF-16LE.
+    pub fn password(password: Zeroizing<Vec<u8>>) -> Self {
+        Self::Password {
+            password,
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Returns the AES key.
+    ///
+    /// The key is looked up in `keys` before it is derived from the password.
+    /// A derived key is kept in this `Secret` until [`Secret::commit`] is
+    /// called, so the keys of wrong passwords are never cached.
+    pub fn key(&self, properties: &AesProps, keys: &KeyCache) -> Result<Zeroizing<[u8; 32]>> {
+        let (password, pending) = match self {
+            Self::Password { password, pending } => (password, pending),
+            Self::Key(key) => return Ok(key.clone()),
+        };
+        let id = key_id(properties, password);
+        if let Some(key) = keys.get(&id) {
+            return Ok(key);
+        }
+        let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, key)) = pending.iter().find(|(i, _)| *i == id) {
+            return Ok(key.clone());
+        }
+        let key = derive_key(properties, password)?;
+        pending.push((id, key.clone()));
+        drop(pending);
+        Ok(key)
+    }
+
+    /// Moves the keys derived from the password into `keys`.
+    ///
+    /// This should be called after the data decrypted with this `Secret` are
+    /// verified.
+    pub fn commit(&self, keys: &KeyCache) {
+        if let Self::Password { pending, .. } = self {
+            let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+            for (id, key) in pending.drain(..) {
+                keys.insert(id, key);
+            }
+        }
+    }
+}
+
+/// The keys cached by [`key_id`], with the time when each key was used last.
+struct Keys {
+    capacity: usize,
+    time: u64,
+    entries: HashMap<[u8; 32], (Zeroizing<[u8; 32]>, u64)>,
+}
+
+impl Keys {
+    /// Creates a new empty `Keys` which holds up to `capacity` keys.
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            time: u64::default(),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns the key, marking it as used.
+    fn get(&mut self, id: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        self.time += 1;
+        let time = self.time;
+        self.entries.get_mut(id).map(|(key, used)| {
+            *used = time;
+            key.clone()
+        })
+    }
+
+    /// Inserts the key, removing the least recently used key if full.
+    fn insert(&mut self, id: [u8; 32], key: Zeroizing<[u8; 32]>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&id) {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(id, _)| *id)
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.time += 1;
+        self.entries.insert(id, (key, self.time));
+    }
+}
+
+/// A cache of the keys derived from passwords for 7zAES.
+///
+/// The key derivation of 7zAES is slow by design, so the derived keys are
+/// cached by the password, the salt and the number of cycles. Clones of a
+/// `KeyCache` share the same keys, so a `KeyCache` can be shared between
+/// archives which are encrypted with the same password.
+///
+/// Only the keys which decrypted the data successfully are cached. The cache
+/// holds up to 64 keys by default, and the least recently used key is removed
+/// when it is full. The passwords are not stored, and the keys are zeroized
+/// when they are removed from the cache.
+#[derive(Clone)]
+pub struct KeyCache {
+    keys: Arc<Mutex<Keys>>,
+}
+
+impl KeyCache {
+    /// Creates a new empty `KeyCache`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new empty `KeyCache` which holds up to `capacity` keys.
+    ///
+    /// A capacity of 0 caches no keys.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            keys: Arc::new(Mutex::new(Keys::new(capacity))),
+        }
+    }
+
+    /// Returns the maximum number of the cached keys.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Returns the number of the cached keys.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns `true` if no keys are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Removes all the cached keys.
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Returns the cached key by [`key_id`].
+    fn get(&self, id: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        self.lock().get(id)
+    }
+
+    /// Caches the key by [`key_id`].
+    fn insert(&self, id: [u8; 32], key: Zeroizing<[u8; 32]>) {
+        self.lock().insert(id, key);
+    }
+
+    /// Locks the keys, ignoring the poisoning since they are always valid.
+    fn lock(&self) -> MutexGuard<'_, Keys> {
+        self.keys.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for KeyCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl fmt::Debug for KeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyCache")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Encodes the password in UTF-16LE.
+pub fn encode_password(password: &str) -> Zeroizing<Vec<u8>> {
+    // The capacity is enough for the encoded password, so the buffer is never
+    // reallocated and no copies of the password are left.
+    let mut buf = Zeroizing::new(Vec::with_capacity(password.len() * 2));
+    buf.extend(password.encode_utf16().flat_map(u16::to_le_bytes));
+    buf
+}
+
+/// A reader which decrypts AES-256-CBC.
+pub struct AesReader<R> {
+    inner: R,
+    cipher: cbc::Decryptor<Aes256>,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+}
+
+impl<R: Read> AesReader<R> {
+    /// Creates a new `AesReader`.
+    ///
+    /// `iv` is padded with zeros to the block size.
+    pub fn new(inner: R, key: &[u8; 32], iv: &[u8]) -> Self {
+        let mut block = [u8::default(); BLOCK_SIZE];
+        let len = iv.len().min(BLOCK_SIZE);
+        block[..len].copy_from_slice(&iv[..len]);
+        Self {
+            inner,
+            cipher: cbc::Decryptor::new(key.into(), &block.into()),
+            buf: vec![u8::default(); BLOCK_SIZE * 512].into_boxed_slice(),
+            pos: usize::default(),
+            len: usize::default(),
+        }
+    }
+
+    /// Reads and decrypts the next blocks into the buffer.
+    fn fill_buf(&mut self) -> io::Result<()> {
+        let mut len = 0;
+        while len < self.buf.len() {
+            match self.inner.read(&mut self.buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // The trailing partial block cannot be decrypted, so it is ignored.
+        let len = len - len % BLOCK_SIZE;
+        for block in self.buf[..len].chunks_exact_mut(BLOCK_SIZE) {
+            self.cipher
+                .decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        self.pos = 0;
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for AesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.len {
+            self.fill_buf()?;
+        }
+        let n = buf.len().min(self.len - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_aes_key() {
+        let properties = AesProps {
+            cycles_power: 0,
+            salt: Vec::new(),
+            iv: Vec::new(),
+        };
+        let password = encode_password("password");
+        let mut hasher = Sha256::new();
+        hasher.update(&password);
+        hasher.update(0_u64.to_le_bytes());
+        assert_eq!(
+            derive_key(&properties, &password).unwrap()[..],
+            hasher.finalize()[..]
+        );
+
+        let properties = AesProps {
+            cycles_power: NO_KEY_STRETCHING,
+            salt: vec![0xaa; 2],
+            iv: Vec::new(),
+        };
+        let mut expected = [u8::default(); 32];
+        expected[..2].copy_from_slice(&[0xaa; 2]);
+        expected[2..4].copy_from_slice(&[b'a', 0x00]);
+        assert_eq!(
+            *derive_key(&properties, &encode_password("a")).unwrap(),
+            expected
+        );
+
+        let properties = AesProps {
+            cycles_power: MAX_CYCLES_POWER + 1,
+            salt: Vec::new(),
+            iv: Vec::new(),
+        };
+        assert!(derive_key(&properties, &password).is_err());
+    }
+
+    #[test]
+    fn cache_keys() {
+        let cache = KeyCache::new();
+        assert!(cache.is_empty());
+        assert_eq!(cache.capacity(), DEFAULT_CAPACITY);
+        let properties = AesProps {
+            cycles_power: 6,
+            salt: Vec::new(),
+            iv: Vec::new(),
+        };
+        let password = encode_password("password");
+        let id = key_id(&properties, &password);
+        let key = derive_key(&properties, &password).unwrap();
+        assert!(cache.get(&id).is_none());
+        cache.insert(id, key.clone());
+        assert_eq!(cache.len(), 1);
+
+        let shared = cache.clone();
+        assert_eq!(shared.get(&id).unwrap(), key);
+        shared.insert(id, key);
+        assert_eq!(cache.len(), 1);
+        let properties = AesProps {
+            salt: vec![0xaa; 2],
+            ..properties
+        };
+        assert_ne!(key_id(&properties, &password), id);
+
+        cache.clear();
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn evict_least_recently_used_key() {
+        let cache = KeyCache::with_capacity(2);
+        let key = Zeroizing::new([u8::default(); 32]);
+        cache.insert([1; 32], key.clone());
+        cache.insert([2; 32], key.clone());
+        assert!(cache.get(&[1; 32]).is_some());
+        cache.insert([3; 32], key.clone());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&[1; 32]).is_some());
+        assert!(cache.get(&[2; 32]).is_none());
+        assert!(cache.get(&[3; 32]).is_some());
+
+        let cache = KeyCache::with_capacity(0);
+        cache.insert([1; 32], key);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn key_from_secret() {
+        let properties = AesProps {
+            cycles_power: 6,
+            salt: Vec::new(),
+            iv: Vec::new(),
+        };
+        let keys = KeyCache::new();
+        let secret = Secret::password(encode_password("password"));
+        let key = secret.key(&properties, &keys).unwrap();
+        assert_eq!(
+            key,
+            derive_key(&properties, &encode_password("password")).unwrap()
+        );
+        assert_eq!(secret.key(&properties, &keys).unwrap(), key);
+        // The key is not cached until it is known to be correct.
+        assert!(keys.is_empty());
+        secret.commit(&keys);
+        assert_eq!(keys.len(), 1);
+        let secret = Secret::password(encode_password("password"));
+        assert_eq!(secret.key(&properties, &keys).unwrap(), key);
+        secret.commit(&keys);
+        assert_eq!(keys.len(), 1);
+
+        let secret = Secret::Key(Zeroizing::new([0xaa; 32]));
+        assert_eq!(*secret.key(&properties, &keys).unwrap(), [0xaa; 32]);
+        secret.commit(&keys);
+        assert_eq!(keys.len(), 1);
+    }
+
+    #[test]
+    fn encode_password_in_utf16le() {
+        assert_eq!(*encode_password("7z"), [b'7', 0x00, b'z', 0x00]);
+        assert_eq!(*encode_password("\u{3042}"), [0x42, 0x30]);
+        assert_eq!(*encode_password("\u{1f600}"), [0x3d, 0xd8, 0x00, 0xde]);
+    }
+}
@@ -0,0 +1,390 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Decoders for folders.
+
+use std::{
+    cell::RefCell,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    rc::Rc,
+};
+
+use lzma_rust::{LZMA2Reader, LZMAReader};
+
+use crate::{
+    codec::CodecRegistry,
+    crypto::{AesReader, KeyCache, Secret},
+    error::InvalidArchive,
+    filter::{Arch, BranchReader, DeltaReader, SwapReader},
+    header::{Coder, Folder, StreamsInfo, SIGNATURE_HEADER_SIZE},
+    method::{AesProps, DeltaProps, Lzma2Props, LzmaProps},
+    property::{Crypto, Method, Sz},
+    Error, Result,
+};
+
+/// A packed stream which shares the underlying reader with other packed
+/// streams.
+struct PackedStream<'a, R> {
+    inner: Rc<RefCell<&'a mut R>>,
+    pos: u64,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> Read for PackedStream<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let len = usize::try_from(self.remaining).map_or(buf.len(), |r| r.min(buf.len()));
+        let mut inner = self.inner.borrow_mut();
+        inner.seek(SeekFrom::Start(self.pos))?;
+        let n = inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.pos += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// A reader of the unpacked data of an encrypted folder whose password passed
+/// [`check_decrypted`].
+///
+/// The data which could not be decoded are reported as
+/// [`InvalidArchive::DataCrc`], since they are corrupted.
+struct DecryptedReader<'a>(Box<dyn Read + 'a>);
+
+impl Read for DecryptedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+                Error::from(InvalidArchive::DataCrc).into()
+            }
+            _ => err,
+        })
+    }
+}
+
+/// Builds a reader which decodes the unpacked data of a folder.
+struct FolderDecoder<'a, 'b, R> {
+    inner: Rc<RefCell<&'a mut R>>,
+    folder: &'b Folder,
+    pack_streams: Vec<(u64, u64)>,
+    secret: Option<&'b Secret>,
+    keys: &'b KeyCache,
+    codecs: &'b CodecRegistry,
+}
+
+impl<'a, R: Read + Seek + 'a> FolderDecoder<'a, '_, R> {
+    /// Returns the reader of the coder.
+    ///
+    /// `depth` is used to reject cyclic bind pairs.
+    fn coder_reader(&self, index: usize, depth: usize) -> Result<Box<dyn Read + 'a>> {
+        if depth > self.folder.coders.len() {
+            return Err(InvalidArchive::Folder.into());
+        }
+        let coder = &self.folder.coders[index];
+        if coder.num_out_streams != 1 {
+            return Err(coder.method.unsupported().into());
+        }
+        let first_in_stream = self.folder.first_in_stream(index);
+        let mut inputs = Vec::with_capacity(coder.num_in_streams);
+        for in_index in first_in_stream..first_in_stream + coder.num_in_streams {
+            let input: Box<dyn Read + 'a> = if let Some(bp) = self
+                .folder
+                .bind_pairs
+                .iter()
+                .find(|bp| bp.in_index == in_index)
+            {
+                let input_coder = self
+                    .folder
+                    .coder_of_out_stream(bp.out_index)
+                    .ok_or(InvalidArchive::Folder)?;
+                let input = self.coder_reader(input_coder, depth + 1)?;
+                if self.folder.coders[input_coder].method == Method::Crypto(Crypto::SzAes) {
+                    check_decrypted(&coder.method, input)?
+                } else {
+                    input
+                }
+            } else {
+                let i = self
+                    .folder
+                    .packed_streams
+                    .iter()
+                    .position(|&p| p == in_index)
+                    .ok_or(InvalidArchive::Folder)?;
+                let (pos, size) = self.pack_streams[i];
+                Box::new(BufReader::new(PackedStream {
+                    inner: Rc::clone(&self.inner),
+                    pos,
+                    remaining: size,
+                }))
+            };
+            inputs.push(input);
+        }
+        let unpack_size = self.folder.unpack_sizes[self.folder.first_out_stream(index)];
+        decode(
+            coder,
+            inputs,
+            unpack_size,
+            self.secret,
+            self.keys,
+            self.codecs,
+        )
+    }
+}
+
+/// Returns the reader which decodes the coder.
+///
+/// The codec registered in `codecs` is used in preference to the built-in
+/// method.
+pub fn decode<'a>(
+    coder: &Coder,
+    mut inputs: Vec<Box<dyn Read + 'a>>,
+    unpack_size: u64,
+    secret: Option<&Secret>,
+    keys: &KeyCache,
+    codecs: &CodecRegistry,
+) -> Result<Box<dyn Read + 'a>> {
+    if let Some(codec) = codecs.get(coder.method.id()) {
+        if inputs.len() != codec.num_packed_streams() {
+            return Err(InvalidArchive::Folder.into());
+        }
+        codec.check_properties(&coder.properties)?;
+        return codec.decoder(inputs, &coder.properties, unpack_size);
+    }
+    let unsupported = || coder.method.unsupported();
+    if inputs.len() != 1 {
+        return Err(unsupported().into());
+    }
+    let input = inputs.remove(0);
+    let properties = &coder.properties;
+    match coder.method {
+        Method::Copy => Ok(Box::new(input.take(unpack_size))),
+        Method::Delta => {
+            let distance = usize::from(DeltaProps::parse(properties)?.distance);
+            Ok(Box::new(
+                DeltaReader::new(input, distance).take(unpack_size),
+            ))
+        }
+        Method::Sz(Sz::Bcj | Sz::Arm | Sz::ArmT | Sz::Ppc | Sz::Sparc) | Method::Arm64 => {
+            let arch = Arch::from_method(&coder.method).ok_or_else(unsupported)?;
+            let start = match **properties {
+                [] => 0,
+                [a, b, c, d] => u32::from_le_bytes([a, b, c, d]),
+                _ => return Err(InvalidArchive::Folder.into()),
+            };
+            Ok(Box::new(
+                BranchReader::new(input, arch, start).take(unpack_size),
+            ))
+        }
+        Method::Swap2 => Ok(Box::new(SwapReader::new(input, 2).take(unpack_size))),
+        Method::Swap4 => Ok(Box::new(SwapReader::new(input, 4).take(unpack_size))),
+        Method::Lzma2 => {
+            let dict_size = Lzma2Props::parse(properties)?.dict_size;
+            Ok(Box::new(
+                LZMA2Reader::new(input, dict_size, None).take(unpack_size),
+            ))
+        }
+        Method::Sz(Sz::Lzma) => {
+            let dict_size = LzmaProps::parse(properties)?.dict_size;
+            let reader =
+                LZMAReader::new_with_props(input, unpack_size, properties[0], dict_size, None)
+                    .map_err(|_| InvalidArchive::Folder)?;
+            Ok(Box::new(reader))
+        }
+        Method::Crypto(Crypto::SzAes) => {
+            let secret = secret.ok_or(Error::PasswordRequired)?;
+            let properties = AesProps::parse(properties)?;
+            let key = secret.key(&properties, keys)?;
+            Ok(Box::new(
+                AesReader::new(input, &key, &properties.iv).take(unpack_size),
+            ))
+        }
+        #[cfg(feature = "zstd")]
+        Method::Misc(crate::property::Misc::Zstd) => {
+            check_external_properties(properties)?;
+            Ok(Box::new(
+                zstd::stream::read::Decoder::new(input)?.take(unpack_size),
+            ))
+        }
+        #[cfg(feature = "brotli")]
+        Method::Misc(crate::property::Misc::Brotli) => {
+            check_external_properties(properties)?;
+            Ok(Box::new(crate::brotli_mt::reader(input)?.take(unpack_size)))
+        }
+        #[cfg(feature = "lz4")]
+        Method::Misc(crate::property::Misc::Lz4) => {
+            check_external_properties(properties)?;
+            Ok(Box::new(
+                lz4_flex::frame::FrameDecoder::new(input).take(unpack_size),
+            ))
+        }
+        _ => Err(unsupported().into()),
+    }
+}
+
+/// Checks the properties of the methods of 7-Zip-zstd.
+///
+/// The properties consist of the version of the library and the compression
+/// level, optionally followed by 2 reserved bytes. They are not needed to
+/// decode.
+#[cfg(any(feature = "brotli", feature = "lz4", feature = "zstd"))]
+fn check_external_properties(properties: &[u8]) -> Result<()> {
+    if matches!(properties.len(), 3 | 5) {
+        Ok(())
+    } else {
+        Err(InvalidArchive::Folder.into())
+    }
+}
+
+/// Checks that the decrypted data begin with a valid stream of the method.
+///
+/// This detects a wrong password without decoding the whole folder. The data
+/// read for the check are put back in front of the returned reader.
+fn check_decrypted<'a>(
+    method: &Method,
+    mut input: Box<dyn Read + 'a>,
+) -> Result<Box<dyn Read + 'a>> {
+    let mut buf = Vec::with_capacity(7);
+    input.by_ref().take(7).read_to_end(&mut buf)?;
+    let valid = match method {
+        Method::Lzma2 => is_lzma2_start(&buf),
+        Method::Sz(Sz::Lzma) => buf.first().map_or(true, |&b| b == 0x00),
+        _ => true,
+    };
+    if valid {
+        Ok(Box::new(io::Cursor::new(buf).chain(input)))
+    } else {
+        Err(Error::WrongPassword)
+    }
+}
+
+/// Returns `true` if a wrong password for the folder is detected by
+/// [`check_decrypted`] when the folder is opened.
+///
+/// This is the case if the decrypted data of each 7zAES coder are decoded with
+/// LZMA or LZMA2.
+pub fn checks_password(folder: &Folder) -> bool {
+    folder
+        .coders
+        .iter()
+        .enumerate()
+        .filter(|(_, coder)| coder.method == Method::Crypto(Crypto::SzAes))
+        .all(|(index, _)| {
+            let out_index = folder.first_out_stream(index);
+            folder
+                .bind_pairs
+                .iter()
+                .find(|bp| bp.out_index == out_index)
+                .and_then(|bp| folder.coder_of_in_stream(bp.in_index))
+                .map_or(false, |coder| {
+                    matches!(
+                        folder.coders[coder].method,
+                        Method::Lzma2 | Method::Sz(Sz::Lzma)
+                    )
+                })
+        })
+}
+
+/// Returns `true` if `buf` is a valid beginning of a LZMA2 stream.
+///
+/// The first chunk must be the end marker, an uncompressed chunk which resets
+/// the dictionary, or a LZMA chunk which resets the dictionary and the
+/// properties. The range coder of a LZMA chunk starts with a zero byte.
+fn is_lzma2_start(buf: &[u8]) -> bool {
+    match buf {
+        [] | [0x00 | 0x01, ..] => true,
+        [0xe0..=0xff, _, _, _, _, properties, rest @ ..] => {
+            let (lc, lp, pb) = (properties % 9, properties / 9 % 5, properties / 45);
+            lc + lp <= 4 && pb <= 4 && rest.first().map_or(true, |&b| b == 0x00)
+        }
+        [0xe0..=0xff, ..] => buf.len() < 6,
+        _ => false,
+    }
+}
+
+/// Returns the reader which decodes the unpacked data of the folder.
+///
+/// `reader` is shared with the packed streams, so it can be reused after an
+/// error. The key derived from the password of `secret` is looked up in
+/// `keys`. If the decrypted data are decoded with LZMA or LZMA2, a wrong
+/// password is reported as [`Error::WrongPassword`], and the data which could
+/// not be decoded after that are reported as [`InvalidArchive::DataCrc`].
+pub fn folder_reader<'a, R: Read + Seek>(
+    reader: &Rc<RefCell<&'a mut R>>,
+    streams_info: &StreamsInfo,
+    index: usize,
+    secret: Option<&Secret>,
+    keys: &KeyCache,
+    codecs: &CodecRegistry,
+) -> Result<Box<dyn Read + 'a>> {
+    let folder = &streams_info.folders[index];
+    let first_pack_stream = streams_info.first_pack_streams()[index];
+    let offsets = streams_info.pack_stream_offsets();
+    let pack_streams = (first_pack_stream..first_pack_stream + folder.packed_streams.len())
+        .map(|i| {
+            let pos = offsets[i]
+                .checked_add(SIGNATURE_HEADER_SIZE)
+                .ok_or(InvalidArchive::Header)?;
+            Ok((pos, streams_info.pack_info.pack_sizes[i]))
+        })
+        .collect::<Result<_>>()?;
+    let main_out_stream = folder.main_out_stream().ok_or(InvalidArchive::Folder)?;
+    let coder = folder
+        .coder_of_out_stream(main_out_stream)
+        .ok_or(InvalidArchive::Folder)?;
+    let decoder = FolderDecoder {
+        inner: Rc::clone(reader),
+        folder,
+        pack_streams,
+        secret,
+        keys,
+        codecs,
+    };
+    let reader = decoder.coder_reader(coder, 0)?;
+    if secret.is_some() && folder.uses(&Method::Crypto(Crypto::SzAes)) && checks_password(folder) {
+        Ok(Box::new(DecryptedReader(reader)))
+    } else {
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_lzma2_start() {
+        assert!(is_lzma2_start(&[]));
+        assert!(is_lzma2_start(&[0x00]));
+        assert!(is_lzma2_start(&[0x01, 0x00, 0x0d]));
+        assert!(is_lzma2_start(&[0xe0, 0x00, 0x0d, 0x00, 0x10, 0x5d, 0x00]));
+        assert!(!is_lzma2_start(&[0x02, 0x00, 0x0d]));
+        assert!(!is_lzma2_start(&[0x80, 0x00, 0x0d, 0x00, 0x10, 0x5d, 0x00]));
+        assert!(!is_lzma2_start(&[0xe0, 0x00, 0x0d, 0x00, 0x10, 0x5d, 0x01]));
+        assert!(!is_lzma2_start(&[0xe0, 0x00, 0x0d, 0x00, 0x10, 0xe1, 0x00]));
+        // lc + lp > 4
+        assert!(!is_lzma2_start(&[0xe0, 0x00, 0x0d, 0x00, 0x10, 0x0d, 0x00]));
+    }
+
+    #[test]
+    fn check_decrypted_data() {
+        let lzma = Method::Sz(Sz::Lzma);
+        let mut buf = Vec::new();
+        check_decrypted(&lzma, Box::new(&[0x00, 0x01, 0x02][..]))
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        assert_eq!(buf, [0x00, 0x01, 0x02]);
+        assert!(matches!(
+            check_decrypted(&lzma, Box::new(&[0x01, 0x01, 0x02][..])),
+            Err(Error::WrongPassword)
+        ));
+        assert!(check_decrypted(&Method::Copy, Box::new(&[0xff][..])).is_ok());
+    }
+}
@@ -0,0 +1,479 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Encoders for coders.
+
+use std::io::{self, Write};
+
+use lzma_rust::{
+    CountingWriter, LZMA2Options, LZMA2Writer, LZMAWriter, DICT_SIZE_MAX, DICT_SIZE_MIN,
+};
+
+use crate::{
+    codec::CodecRegistry,
+    error::UnsupportedArchive,
+    filter::{Arch, BranchWriter, DeltaWriter, SwapWriter},
+    method::{DeltaProps, Lzma2Props, LzmaProps, Method, MethodSpec, Param, Sz},
+    Result,
+};
+
+/// A writer which encodes data for a coder.
+///
+/// [`Encoder::finish`] must be called after all the data is written.
+pub trait Encoder: Write {
+    /// Writes the remaining encoded data.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// An encoder which writes data as it is.
+struct CopyEncoder<W>(W);
+
+impl<W: Write> Write for CopyEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Encoder for CopyEncoder<W> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Encoder for LZMA2Writer<W> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        Self::finish(&mut self)
+    }
+}
+
+impl<W: Write> Encoder for LZMAWriter<W> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        Self::finish(&mut self)
+    }
+}
+
+impl<W: Write> Encoder for SwapWriter<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self).map(|_| ())
+    }
+}
+
+impl<W: Write> Encoder for BranchWriter<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self).map(|_| ())
+    }
+}
+
+impl<W: Write> Encoder for DeltaWriter<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self).map(|_| ())
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Encoder for zstd::stream::write::Encoder<'_, W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self)?.flush()
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl<W: Write> Encoder for crate::brotli_mt::BrotliWriter<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self).map(|_| ())
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl<W: Write> Encoder for lz4_flex::frame::FrameEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Self::finish(*self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+            .flush()
+    }
+}
+
+/// Returns the value of the parameter, or [`Err`] if it is invalid.
+fn param_value<T>(param: &Param, value: Option<T>) -> Result<T> {
+    value.ok_or_else(|| UnsupportedArchive::MethodParam(param.to_string()).into())
+}
+
+/// Returns the options of LZMA and LZMA2 for the parameters of `spec`.
+///
+/// The dictionary size is given by the main parameter or `d`, such as
+/// `LZMA2:24` or `LZMA2:d=64m`. The number of fast bytes is given by `fb`, and
+/// the match finder is given by `mf`, which is either `hc4` or `bt4`.
+fn lzma_options(spec: &MethodSpec, level: u32) -> Result<LZMA2Options> {
+    let mut options = LZMA2Options::with_preset(level.min(9));
+    if let Some(param) = spec.param("").or_else(|| spec.param("d")) {
+        let size = param
+            .size()
+            .and_then(|size| u32::try_from(size).ok())
+            .filter(|size| (DICT_SIZE_MIN..=DICT_SIZE_MAX).contains(size));
+        options.dict_size = param_value(param, size)?;
+    }
+    if let Some(param) = spec.param("mf") {
+        // The presets 0 to 3 use HC4 and the others use BT4.
+        let preset = match param.value.to_ascii_lowercase().as_str() {
+            "hc4" => Some(LZMA2Options::with_preset(level.min(3))),
+            "bt4" => Some(LZMA2Options::with_preset(level.clamp(4, 9))),
+            _ => None,
+        };
+        let preset = param_value(param, preset)?;
+        options.mode = preset.mode;
+        options.mf = preset.mf;
+        options.depth_limit = preset.depth_limit;
+    }
+    if let Some(param) = spec.param("fb") {
+        let nice_len = param
+            .value
+            .parse()
+            .ok()
+            .filter(|n| (LZMA2Options::NICE_LEN_MIN..=LZMA2Options::NICE_LEN_MAX).contains(n));
+        options.nice_len = param_value(param, nice_len)?;
+    }
+    Ok(options)
+}
+
+/// Returns the properties of Delta for the parameters of `spec`.
+///
+/// The distance is given by the main parameter, such as `Delta:4`.
+fn delta_props(spec: &MethodSpec) -> Result<DeltaProps> {
+    let mut properties = DeltaProps::default();
+    if let Some(param) = spec.param("") {
+        let distance = param.value.parse().ok().filter(|n| (1..=256).contains(n));
+        properties.distance = param_value(param, distance)?;
+    }
+    Ok(properties)
+}
+
+/// Returns [`Err`] if the encoder of `spec` cannot be created by [`encoder`],
+/// such as if a parameter is invalid.
+///
+/// The parameters of the built-in methods are checked without creating the
+/// encoder, which may allocate a large dictionary.
+pub fn check_params(spec: &MethodSpec, level: u32, codecs: &CodecRegistry) -> Result<()> {
+    let method = &spec.method;
+    if codecs.get(method.id()).is_some() {
+        return encoder(spec, level, io::sink(), codecs).map(|_| ());
+    }
+    match method {
+        Method::Delta => delta_props(spec).map(|_| ()),
+        Method::Lzma2 | Method::Sz(Sz::Lzma) => lzma_options(spec, level).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the properties of the coder and the encoder which writes the
+/// encoded data into `writer`.
+///
+/// `level` is the compression level of the method, which is clamped to the
+/// range supported by the method. LZ4 is always compressed with the fast level
+/// 1, which is recorded in the properties. The parameters of `spec` which are
+/// not known are ignored. The codec registered in `codecs` is used in preference to the
+/// built-in method.
+#[cfg_attr(
+    not(any(feature = "brotli", feature = "lz4", feature = "zstd")),
+    allow(unused_variables)
+)]
+pub fn encoder<'a, W: Write + 'a>(
+    spec: &MethodSpec,
+    level: u32,
+    writer: W,
+    codecs: &CodecRegistry,
+) -> Result<(Vec<u8>, Box<dyn Encoder + 'a>)> {
+    let method = &spec.method;
+    if let Some(codec) = codecs.get(method.id()) {
+        return codec.encoder(Box::new(writer), spec, level);
+    }
+    match method {
+        Method::Copy => Ok((Vec::new(), Box::new(CopyEncoder(writer)))),
+        Method::Delta => {
+            let properties = delta_props(spec)?;
+            let encoder = DeltaWriter::new(writer, usize::from(properties.distance));
+            Ok((properties.to_bytes(), Box::new(encoder)))
+        }
+        Method::Sz(Sz::Bcj | Sz::Arm | Sz::ArmT | Sz::Ppc | Sz::Sparc) | Method::Arm64 => {
+            let arch = Arch::from_method(method).ok_or_else(|| method.unsupported())?;
+            Ok((Vec::new(), Box::new(BranchWriter::new(writer, arch, 0))))
+        }
+        Method::Swap2 => Ok((Vec::new(), Box::new(SwapWriter::new(writer, 2)))),
+        Method::Swap4 => Ok((Vec::new(), Box::new(SwapWriter::new(writer, 4)))),
+        Method::Lzma2 => {
+            let options = lzma_options(spec, level)?;
+            let properties = Lzma2Props {
+                dict_size: options.dict_size,
+            };
+            let encoder = LZMA2Writer::new(CountingWriter::new(writer), &options);
+            Ok((properties.to_bytes(), Box::new(encoder)))
+        }
+        Method::Sz(Sz::Lzma) => {
+            let options = lzma_options(spec, level)?;
+            let properties = LzmaProps {
+                dict_size: options.dict_size,
+                ..LzmaProps::default()
+            };
+            let encoder = LZMAWriter::new_no_header(CountingWriter::new(writer), &options, false)?;
+            Ok((properties.to_bytes(), Box::new(encoder)))
+        }
+        #[cfg(feature = "zstd")]
+        Method::Misc(crate::property::Misc::Zstd) => {
+            let level = u8::try_from(level).map_or(22, |level| level.clamp(1, 22));
+            let encoder = zstd::stream::write::Encoder::new(writer, i32::from(level))?;
+            let properties = vec![
+                u8::try_from(zstd::zstd_safe::VERSION_MAJOR).unwrap_or_default(),
+                u8::try_from(zstd::zstd_safe::VERSION_MINOR).unwrap_or_default(),
+                level,
+                0,
+                0,
+            ];
+            Ok((properties, Box::new(encoder)))
+        }
+        #[cfg(feature = "brotli")]
+        Method::Misc(crate::property::Misc::Brotli) => {
+            let quality = u8::try_from(level).map_or(11, |level| level.min(11));
+            let encoder = crate::brotli_mt::BrotliWriter::new(writer, u32::from(quality));
+            Ok((vec![1, 0, quality], Box::new(encoder)))
+        }
+        #[cfg(feature = "lz4")]
+        Method::Misc(crate::property::Misc::Lz4) => {
+            // lz4_flex supports only the fast compression.
+            let encoder = lz4_flex::frame::FrameEncoder::new(writer);
+            Ok((vec![1, 9, 1, 0, 0], Box::new(encoder)))
+        }
+        _ => Err(method.unsupported().into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{crypto::KeyCache, decoder, header::Coder, property::Misc};
+
+    fn round_trip(spec: impl Into<MethodSpec>, level: u32) {
+        let spec = spec.into();
+        let data = b"Hello, world!\n".repeat(1024);
+        let mut buf = Vec::new();
+        let (properties, mut encoder) =
+            encoder(&spec, level, &mut buf, &CodecRegistry::new()).unwrap();
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+
+        let coder = Coder {
+            method: spec.method,
+            num_in_streams: 1,
+            num_out_streams: 1,
+            properties,
+        };
+        let mut reader = decoder::decode(
+            &coder,
+            vec![Box::new(&buf[..])],
+            data.len() as u64,
+            None,
+            &KeyCache::new(),
+            &CodecRegistry::new(),
+        )
+        .unwrap();
+        let mut decoded = Vec::new();
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn encode_copy() {
+        round_trip(Method::Copy, 0);
+    }
+
+    #[test]
+    fn encode_lzma() {
+        round_trip(Method::Lzma2, 0);
+        round_trip(Method::Lzma2, 6);
+        round_trip(Method::Sz(Sz::Lzma), 5);
+    }
+
+    #[test]
+    fn encode_with_params() {
+        round_trip("LZMA2:20".parse::<MethodSpec>().unwrap(), 6);
+        round_trip("LZMA:d=64k".parse::<MethodSpec>().unwrap(), 6);
+        round_trip("LZMA2:d18:fb32:mf=hc4".parse::<MethodSpec>().unwrap(), 5);
+        round_trip("LZMA2:d24:fb273:mf=BT4".parse::<MethodSpec>().unwrap(), 1);
+        round_trip("Delta:4".parse::<MethodSpec>().unwrap(), 0);
+
+        let codecs = CodecRegistry::new();
+        for spec in [
+            "LZMA2:10",
+            "LZMA2:d=5x",
+            "LZMA2:fb=4",
+            "LZMA2:fb=274",
+            "LZMA2:mf=bt2",
+            "Delta:0",
+            "Delta:257",
+        ] {
+            let spec = spec.parse().unwrap();
+            assert!(encoder(&spec, 6, Vec::new(), &codecs).is_err());
+        }
+    }
+
+    #[test]
+    fn encode_filters() {
+        round_trip(Method::Delta, 0);
+        round_trip(Method::Swap2, 0);
+        round_trip(Method::Swap4, 0);
+        round_trip(Method::Sz(Sz::Bcj), 0);
+        round_trip(Method::Sz(Sz::Arm), 0);
+        round_trip(Method::Sz(Sz::ArmT), 0);
+        round_trip(Method::Arm64, 0);
+        round_trip(Method::Sz(Sz::Ppc), 0);
+        round_trip(Method::Sz(Sz::Sparc), 0);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn encode_zstd() {
+        round_trip(Method::Misc(Misc::Zstd), 3);
+        round_trip(Method::Misc(Misc::Zstd), u32::MAX);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn encode_brotli() {
+        round_trip(Method::Misc(Misc::Brotli), 11);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn encode_lz4() {
+        round_trip(Method::Misc(Misc::Lz4), 0);
+
+        // The level which is actually used is recorded.
+        let spec = MethodSpec::new(Method::Misc(Misc::Lz4));
+        let (properties, _) = encoder(&spec, 9, Vec::new(), &CodecRegistry::new()).unwrap();
+        assert_eq!(properties, [1, 9, 1, 0, 0]);
+    }
+
+    /// A codec which XORs the data with the key given by the `k` parameter.
+    struct Xor;
+
+    struct XorEncoder<'a>(Box<dyn Write + 'a>, u8);
+
+    impl Write for XorEncoder<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let buf = buf.iter().map(|b| b ^ self.1).collect::<Vec<_>>();
+            self.0.write(&buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Encoder for XorEncoder<'_> {
+        fn finish(mut self: Box<Self>) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl crate::Codec for Xor {
+        fn name(&self) -> &'static str {
+            "XOR"
+        }
+
+        fn check_properties(&self, properties: &[u8]) -> Result<()> {
+            if properties.len() == 1 {
+                Ok(())
+            } else {
+                Err(crate::error::InvalidArchive::Header.into())
+            }
+        }
+
+        fn decoder<'a>(
+            &self,
+            mut inputs: Vec<Box<dyn Read + 'a>>,
+            properties: &[u8],
+            unpack_size: u64,
+        ) -> Result<Box<dyn Read + 'a>> {
+            let mut buf = Vec::new();
+            inputs.remove(0).take(unpack_size).read_to_end(&mut buf)?;
+            for b in &mut buf {
+                *b ^= properties[0];
+            }
+            Ok(Box::new(io::Cursor::new(buf)))
+        }
+
+        fn can_encode(&self) -> bool {
+            true
+        }
+
+        fn encoder<'a>(
+            &self,
+            output: Box<dyn Write + 'a>,
+            spec: &MethodSpec,
+            _: u32,
+        ) -> Result<(Vec<u8>, Box<dyn Encoder + 'a>)> {
+            let key = match spec.param("k") {
+                Some(param) => param_value(param, param.value.parse().ok())?,
+                None => 0xff,
+            };
+            Ok((vec![key], Box::new(XorEncoder(output, key))))
+        }
+    }
+
+    #[test]
+    fn encode_with_codec_params() {
+        let mut codecs = CodecRegistry::new();
+        codecs.register([0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01], Xor);
+        let spec = "3F00000000000001:k=7".parse::<MethodSpec>().unwrap();
+        let mut buf = Vec::new();
+        let (properties, mut writer) = encoder(&spec, 0, &mut buf, &codecs).unwrap();
+        writer.write_all(b"abc").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(properties, [7]);
+        assert_eq!(buf, [b'a' ^ 7, b'b' ^ 7, b'c' ^ 7]);
+
+        let coder = Coder {
+            method: spec.method,
+            num_in_streams: 1,
+            num_out_streams: 1,
+            properties,
+        };
+        let mut reader = decoder::decode(
+            &coder,
+            vec![Box::new(&buf[..])],
+            3,
+            None,
+            &KeyCache::new(),
+            &codecs,
+        )
+        .unwrap();
+        let mut decoded = Vec::new();
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"abc");
+
+        let spec = "3F00000000000001:k=256".parse::<MethodSpec>().unwrap();
+        assert!(encoder(&spec, 0, Vec::new(), &codecs).is_err());
+    }
+
+    #[test]
+    fn encode_unsupported_method() {
+        assert!(encoder(
+            &MethodSpec::new(Method::Misc(Misc::Bzip2)),
+            0,
+            Vec::new(),
+            &CodecRegistry::new()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,547 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Entries in an archive.
+
+#[cfg(feature = "time")]
+use std::time::SystemTime;
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::Path,
+};
+
+use crate::{
+    attributes::{FileType, WinAttributes, PERMISSION_MASK},
+    FileTime,
+};
+
+/// The mask of the access bits of the Unix mode, without the setuid, setgid
+/// and sticky bits.
+const ACCESS_MASK: u32 = 0o777;
+
+/// The write bits of the Unix mode.
+const WRITE_BITS: u32 = 0o222;
+
+/// The owner write bit of the Unix mode.
+const OWNER_WRITE_BIT: u32 = 0o200;
+
+/// The policy to restore the permissions of extracted files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PermissionPolicy {
+    /// Leaves the permissions as the file system created them.
+    Ignore,
+
+    /// Applies the access bits of the entry, without the setuid, setgid and
+    /// sticky bits.
+    Apply,
+
+    /// Applies the permissions of the entry as they are, including the
+    /// setuid, setgid and sticky bits.
+    ///
+    /// This should be used only for trusted archives.
+    ApplyWithSpecialBits,
+
+    /// Applies the access bits of the entry, clearing the bits set in the
+    /// umask, such as `0o022`.
+    Umask(u32),
+}
+
+/// Converts the time of the metadata to a `FileTime`, if any.
+#[cfg(feature = "time")]
+fn file_time(time: io::Result<SystemTime>) -> Option<FileTime> {
+    time.ok().and_then(|time| FileTime::try_from(time).ok())
+}
+
+/// Returns the Windows attributes of the metadata.
+#[cfg(windows)]
+fn attributes(metadata: &Metadata) -> WinAttributes {
+    use std::os::windows::fs::MetadataExt;
+
+    metadata.file_attributes().into()
+}
+
+/// Returns the Windows attributes of the metadata, with the Unix mode in the
+/// high 16 bits.
+#[cfg(not(windows))]
+fn attributes(metadata: &Metadata) -> WinAttributes {
+    let mut attributes = if metadata.is_dir() {
+        WinAttributes::DIRECTORY
+    } else {
+        WinAttributes::ARCHIVE
+    };
+    if metadata.permissions().readonly() {
+        attributes |= WinAttributes::READONLY;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        attributes = attributes.with_unix_mode(Some(metadata.mode()));
+    }
+    attributes
+}
+
+/// Represents an entry in a 7z archive.
+#[derive(Clone, Debug, Default)]
+pub struct Entry {
+    pub(crate) name: String,
+    pub(crate) has_stream: bool,
+    pub(crate) is_dir: bool,
+    pub(crate) is_anti: bool,
+    pub(crate) size: u64,
+    pub(crate) crc: Option<u32>,
+    pub(crate) ctime: Option<FileTime>,
+    pub(crate) atime: Option<FileTime>,
+    pub(crate) mtime: Option<FileTime>,
+    pub(crate) attributes: Option<WinAttributes>,
+    pub(crate) symlink_target: Option<String>,
+    pub(crate) folder: Option<usize>,
+    pub(crate) offset: u64,
+}
+
+impl Entry {
+    /// Creates a new `Entry` with `name` to add to an archive.
+    ///
+    /// The path separator should be `/`. The kind and the data of the entry
+    /// are determined when it is added by [`ArchiveWriter`].
+    ///
+    /// [`ArchiveWriter`]: crate::ArchiveWriter
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a new `Entry` with `name` and the timestamps and the attributes
+    /// of `metadata`.
+    ///
+    /// On Unix, the attributes have the Unix mode in the high 16 bits, as
+    /// p7zip does. The timestamps are only set if the `time` feature is
+    /// enabled, and the ones which are not available on the platform are not
+    /// set.
+    #[must_use]
+    pub fn from_metadata(name: impl Into<String>, metadata: &Metadata) -> Self {
+        let mut entry = Self::new(name);
+        #[cfg(feature = "time")]
+        {
+            entry.ctime = file_time(metadata.created());
+            entry.atime = file_time(metadata.accessed());
+            entry.mtime = file_time(metadata.modified());
+        }
+        entry.attributes = Some(attributes(metadata));
+        entry
+    }
+
+    /// Sets the creation time of this entry.
+    pub fn set_created(&mut self, time: Option<FileTime>) -> &mut Self {
+        self.ctime = time;
+        self
+    }
+
+    /// Sets the last access time of this entry.
+    pub fn set_accessed(&mut self, time: Option<FileTime>) -> &mut Self {
+        self.atime = time;
+        self
+    }
+
+    /// Sets the last modification time of this entry.
+    pub fn set_modified(&mut self, time: Option<FileTime>) -> &mut Self {
+        self.mtime = time;
+        self
+    }
+
+    /// Sets the Windows attributes of this entry.
+    pub fn set_attributes(&mut self, attributes: Option<WinAttributes>) -> &mut Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Sets the Unix mode of this entry, such as `0o100644`.
+    ///
+    /// The mode is stored in the high 16 bits of the attributes with
+    /// [`WinAttributes::UNIX_EXTENSION`], as p7zip does. If the attributes are
+    /// not set, the Windows attributes are derived from the file type of the
+    /// mode. Otherwise, the other Windows attributes are kept, but
+    /// [`WinAttributes::DIRECTORY`] is set or cleared by the file type of the
+    /// mode if it has one. [`None`] removes the mode from the attributes.
+    pub fn set_unix_mode(&mut self, mode: Option<u32>) -> &mut Self {
+        let file_type = mode.and_then(FileType::from_unix_mode);
+        let mut attributes = match (self.attributes, mode) {
+            (Some(attributes), _) => attributes,
+            (None, None) => return self,
+            (None, Some(_)) if file_type == Some(FileType::Directory) => WinAttributes::empty(),
+            (None, Some(_)) => WinAttributes::ARCHIVE,
+        };
+        match file_type {
+            Some(FileType::Directory) => attributes.insert(WinAttributes::DIRECTORY),
+            Some(_) => attributes.remove(WinAttributes::DIRECTORY),
+            None => {}
+        }
+        self.attributes = Some(attributes.with_unix_mode(mode));
+        self
+    }
+
+    /// Returns the name of this entry.
+    ///
+    /// The path separator is always `/`.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if this entry has data.
+    #[must_use]
+    pub const fn has_stream(&self) -> bool {
+        self.has_stream
+    }
+
+    /// Returns `true` if this entry is a directory.
+    #[must_use]
+    pub const fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// Returns `true` if this entry is an anti-item.
+    ///
+    /// An anti-item marks the deletion of the item in an update archive.
+    #[must_use]
+    pub const fn is_anti(&self) -> bool {
+        self.is_anti
+    }
+
+    /// Returns the size of the data of this entry.
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the CRC of the data of this entry.
+    #[must_use]
+    pub const fn crc(&self) -> Option<u32> {
+        self.crc
+    }
+
+    /// Returns the creation time of this entry.
+    #[must_use]
+    pub const fn created(&self) -> Option<FileTime> {
+        self.ctime
+    }
+
+    /// Returns the last access time of this entry.
+    #[must_use]
+    pub const fn accessed(&self) -> Option<FileTime> {
+        self.atime
+    }
+
+    /// Returns the last modification time of this entry.
+    #[must_use]
+    pub const fn modified(&self) -> Option<FileTime> {
+        self.mtime
+    }
+
+    /// Returns the Windows attributes of this entry.
+    #[must_use]
+    pub const fn attributes(&self) -> Option<WinAttributes> {
+        self.attributes
+    }
+
+    /// Returns the Unix mode of this entry, such as `0o100644`.
+    ///
+    /// Returns [`None`] if the attributes do not have the Unix mode.
+    #[must_use]
+    pub fn unix_mode(&self) -> Option<u32> {
+        self.attributes.and_then(WinAttributes::unix_mode)
+    }
+
+    /// Returns the file type of this entry.
+    ///
+    /// A directory is always [`FileType::Directory`]. The file type of the
+    /// others is given by the attributes, as [`WinAttributes::file_type`]
+    /// returns.
+    #[must_use]
+    pub fn file_type(&self) -> FileType {
+        if self.is_dir {
+            return FileType::Directory;
+        }
+        self.attributes
+            .map_or(FileType::Regular, WinAttributes::file_type)
+    }
+
+    /// Returns the target of this symbolic link.
+    ///
+    /// A symbolic link is an entry whose Unix mode is a symbolic link, and its
+    /// data are the target, as p7zip stores it. The target is set for the
+    /// entries passed by [`Archive::for_each_entry`] and added by
+    /// [`ArchiveWriter::add_symlink`]. For the other entries, use
+    /// [`Archive::symlink_target`]. A target which is larger than 64 KiB or is
+    /// not valid UTF-8 is not set, and [`Archive::symlink_target`] fails for
+    /// it.
+    ///
+    /// [`Archive::for_each_entry`]: crate::Archive::for_each_entry
+    /// [`Archive::symlink_target`]: crate::Archive::symlink_target
+    /// [`ArchiveWriter::add_symlink`]: crate::ArchiveWriter::add_symlink
+    #[must_use]
+    pub fn symlink_target(&self) -> Option<&str> {
+        self.symlink_target.as_deref()
+    }
+
+    /// Creates the symbolic link of this entry at `path`.
+    ///
+    /// On Windows, a directory symbolic link is created if the target is an
+    /// existing directory. The target is not checked, so it may point outside
+    /// the directory into which the archive is extracted. The symbolic links
+    /// should be created after the other entries are extracted, so the files
+    /// are not written through them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if this entry has no target, if the platform does not
+    /// support symbolic links, or if an I/O error occurs.
+    pub fn create_symlink(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let target = self.symlink_target().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the entry has no target of a symbolic link",
+            )
+        })?;
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(target, path)
+        }
+        #[cfg(windows)]
+        {
+            let is_dir = path
+                .parent()
+                .map_or_else(|| Path::new(target).is_dir(), |p| p.join(target).is_dir());
+            if is_dir {
+                std::os::windows::fs::symlink_dir(target, path)
+            } else {
+                std::os::windows::fs::symlink_file(target, path)
+            }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (target, path);
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "symbolic links are not supported on this platform",
+            ))
+        }
+    }
+
+    /// Returns the permission bits to restore, given the current ones of the
+    /// extracted file.
+    ///
+    /// Returns [`None`] if the permissions should be left as they are.
+    fn restored_mode(&self, current: u32, policy: PermissionPolicy) -> Option<u32> {
+        let attributes = self.attributes?;
+        let mode = match attributes.unix_permissions() {
+            Some(mode) => mode,
+            None if attributes.contains(WinAttributes::READONLY) => current & !WRITE_BITS,
+            None => current | OWNER_WRITE_BIT,
+        };
+        match policy {
+            PermissionPolicy::Ignore => None,
+            PermissionPolicy::Apply => Some(mode & ACCESS_MASK),
+            PermissionPolicy::ApplyWithSpecialBits => Some(mode),
+            PermissionPolicy::Umask(umask) => Some(mode & ACCESS_MASK & !umask),
+        }
+    }
+
+    /// Restores the permissions of this entry to the extracted file at
+    /// `path`.
+    ///
+    /// If the attributes have the Unix mode, the permission bits of it are
+    /// applied. The setuid, setgid and sticky bits are applied only with
+    /// [`PermissionPolicy::ApplyWithSpecialBits`]. Otherwise, the read-only
+    /// attribute is mapped onto the write bits. On the platforms other than
+    /// Unix, only the read-only flag is restored. Nothing is done if the
+    /// attributes are not set.
+    ///
+    /// A directory should be restored after the files in it are extracted,
+    /// since its permissions may deny writing them. Nothing is done for a
+    /// symbolic link entry, since its permissions are not used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if `path` is a symbolic link, since the permissions
+    /// would be applied to the file it points to, or if an I/O error occurs.
+    pub fn restore_permissions(
+        &self,
+        path: impl AsRef<Path>,
+        policy: PermissionPolicy,
+    ) -> io::Result<()> {
+        if self.file_type() == FileType::Symlink {
+            return Ok(());
+        }
+        let path = path.as_ref();
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.file_type().is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "refusing to restore the permissions through a symbolic link",
+            ));
+        }
+        let mut permissions = metadata.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let current = permissions.mode() & PERMISSION_MASK;
+            match self.restored_mode(current, policy) {
+                Some(mode) if mode != current => permissions.set_mode(mode),
+                _ => return Ok(()),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let current = if permissions.readonly() { 0o444 } else { 0o666 };
+            match self.restored_mode(current, policy) {
+                Some(mode) if mode & OWNER_WRITE_BIT != current & OWNER_WRITE_BIT => {
+                    permissions.set_readonly(mode & OWNER_WRITE_BIT == 0);
+                }
+                _ => return Ok(()),
+            }
+        }
+        fs::set_permissions(path, permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_mode() {
+        let mut entry = Entry::new("a");
+        assert_eq!(entry.unix_mode(), None);
+        entry.set_unix_mode(Some(0o100_755));
+        assert_eq!(entry.unix_mode(), Some(0o100_755));
+        assert_eq!(entry.attributes(), Some(0x81ed_8020.into()));
+        entry.set_unix_mode(None);
+        assert_eq!(entry.unix_mode(), None);
+        assert_eq!(entry.attributes(), Some(WinAttributes::ARCHIVE));
+
+        entry.set_attributes(Some(WinAttributes::READONLY));
+        entry.set_unix_mode(Some(0o100_444));
+        assert_eq!(entry.attributes(), Some(0x8124_8001.into()));
+        entry.set_unix_mode(Some(0o040_755));
+        assert_eq!(entry.attributes(), Some(0x41ed_8011.into()));
+        entry.set_unix_mode(Some(0o100_644));
+        assert_eq!(entry.attributes(), Some(0x81a4_8001.into()));
+
+        let mut entry = Entry::new("dir");
+        entry.set_unix_mode(Some(0o040_755));
+        assert_eq!(entry.attributes(), Some(0x41ed_8010.into()));
+        assert_eq!(entry.file_type(), FileType::Directory);
+    }
+
+    #[test]
+    fn restored_mode() {
+        let mut entry = Entry::new("a");
+        assert_eq!(entry.restored_mode(0o644, PermissionPolicy::Apply), None);
+
+        entry.set_unix_mode(Some(0o104_775));
+        assert_eq!(entry.restored_mode(0o644, PermissionPolicy::Ignore), None);
+        assert_eq!(
+            entry.restored_mode(0o644, PermissionPolicy::Apply),
+            Some(0o775)
+        );
+        assert_eq!(
+            entry.restored_mode(0o644, PermissionPolicy::ApplyWithSpecialBits),
+            Some(0o4775)
+        );
+        assert_eq!(
+            entry.restored_mode(0o644, PermissionPolicy::Umask(0o022)),
+            Some(0o755)
+        );
+
+        entry.set_attributes(Some(WinAttributes::READONLY));
+        assert_eq!(
+            entry.restored_mode(0o664, PermissionPolicy::Apply),
+            Some(0o444)
+        );
+        entry.set_attributes(Some(WinAttributes::ARCHIVE));
+        assert_eq!(
+            entry.restored_mode(0o444, PermissionPolicy::Apply),
+            Some(0o644)
+        );
+        assert_eq!(
+            entry.restored_mode(0o444, PermissionPolicy::Umask(0o277)),
+            Some(0o400)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restore_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("sz-permissions-{}", std::process::id()));
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let mode = || fs::metadata(&path).unwrap().permissions().mode() & PERMISSION_MASK;
+
+        let mut entry = Entry::new("a.sh");
+        entry.set_unix_mode(Some(0o100_777));
+        entry
+            .restore_permissions(&path, PermissionPolicy::Ignore)
+            .unwrap();
+        assert_eq!(mode(), 0o600);
+        entry
+            .restore_permissions(&path, PermissionPolicy::Umask(0o027))
+            .unwrap();
+        assert_eq!(mode(), 0o750);
+        entry
+            .restore_permissions(&path, PermissionPolicy::Apply)
+            .unwrap();
+        assert_eq!(mode(), 0o777);
+        entry.set_attributes(Some(WinAttributes::READONLY));
+        entry
+            .restore_permissions(&path, PermissionPolicy::Apply)
+            .unwrap();
+        assert_eq!(mode(), 0o555);
+
+        // The file which a symbolic link points to is not changed.
+        let link = path.with_extension("link");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        let error = entry
+            .restore_permissions(&link, PermissionPolicy::Apply)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(mode(), 0o555);
+        fs::remove_file(&link).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("sz-entry-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        let entry = Entry::from_metadata("a.sh", &fs::metadata(&path).unwrap());
+        assert_eq!(entry.unix_mode(), Some(0o100_751));
+        assert_eq!(entry.attributes().unwrap().bits() & 0xffff, 0x8020);
+        #[cfg(feature = "time")]
+        assert!(entry.modified().is_some());
+        #[cfg(not(feature = "time"))]
+        assert!(entry.modified().is_none());
+
+        let entry = Entry::from_metadata("dir", &fs::metadata(&dir).unwrap());
+        assert_eq!(entry.file_type(), FileType::Directory);
+        assert_eq!(entry.attributes().unwrap().bits() & 0xffff, 0x8010);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
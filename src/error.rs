@@ -6,7 +6,7 @@
 
 //! The error type for this crate.
 
-use std::{borrow::Cow, io};
+use std::{borrow::Cow, fmt, io};
 
 use thiserror::Error;
 
@@ -21,12 +21,16 @@ pub enum Error {
 
     /// An error caused by I/O.
     #[error(transparent)]
-    Io(#[from] io::Error),
+    Io(io::Error),
 
     /// The password was required to decrypt the archive.
     #[error("password required to decrypt 7z archive")]
     PasswordRequired,
 
+    /// The password to decrypt the archive was wrong.
+    #[error("wrong password to decrypt 7z archive")]
+    WrongPassword,
+
     /// The archive was not supported.
     #[error("unsupported 7z archive")]
     UnsupportedArchive(#[from] UnsupportedArchive),
@@ -42,6 +46,28 @@ impl From<Error> for io::Error {
     }
 }
 
+impl From<io::Error> for Error {
+    /// Converts the I/O error, unwrapping the [`Error`] which it carries, such
+    /// as [`Error::WrongPassword`] returned by a reader of an archive.
+    fn from(error: io::Error) -> Self {
+        if !error
+            .get_ref()
+            .map_or(false, <dyn std::error::Error + Send + Sync>::is::<Self>)
+        {
+            return Self::Io(error);
+        }
+        let kind = error.kind();
+        match error
+            .into_inner()
+            .map(<dyn std::error::Error + Send + Sync>::downcast::<Self>)
+        {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => Self::Io(io::Error::new(kind, inner)),
+            None => Self::Io(kind.into()),
+        }
+    }
+}
+
 /// The error type if the archive was invalid.
 #[derive(Debug, Error)]
 pub enum InvalidArchive {
@@ -66,6 +92,33 @@ pub enum InvalidArchive {
         /// The id which was found.
         id: u8,
     },
+
+    /// An unexpected property was found.
+    #[error("unexpected property `{id:#04x}` in {pos:?}")]
+    UnexpectedProperty {
+        /// The position of property where the unexpected property was found.
+        pos: Property,
+
+        /// The id which was found.
+        id: u8,
+    },
+
+    /// The header was inconsistent.
+    #[error("invalid header")]
+    Header,
+
+    /// The coders of a folder were inconsistent.
+    #[error("invalid folder")]
+    Folder,
+
+    /// A CRC of the unpacked data mismatched.
+    #[error("data CRC mismatch")]
+    DataCrc,
+
+    /// The target of a symbolic link was larger than 64 KiB or was not valid
+    /// UTF-8.
+    #[error("invalid target of symbolic link")]
+    SymlinkTarget,
 }
 
 /// The error type if the archive was not supported.
@@ -84,6 +137,81 @@ pub enum UnsupportedArchive {
     /// The compression method was not supported.
     #[error("unsupported compression method `{0}`")]
     CompressionMethod(String),
+
+    /// The compression method of the ID was not known, and no codec was
+    /// registered for it.
+    #[error("unknown compression method `{}`", HexId(.0))]
+    UnknownMethod(Vec<u8>),
+
+    /// The parameter of the compression method was not supported.
+    #[error("unsupported parameter `{0}` of compression method")]
+    MethodParam(String),
+
+    /// The data stored outside the header were not supported.
+    #[error("unsupported external data")]
+    ExternalData,
+
+    /// The layout of the coders did not form a chain, so it could not be
+    /// written.
+    #[error("unsupported folder layout whose coders do not form a chain")]
+    FolderLayout,
+}
+
+/// The ID of a method in hexadecimal, as 7-Zip formats it.
+struct HexId<'a>(&'a [u8]);
+
+impl fmt::Display for HexId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02X}"))
+    }
+}
+
+/// The error type when parsing a method string failed.
+#[derive(Debug, Error)]
+pub enum ParseMethodError {
+    /// The method string was empty.
+    #[error("empty method string")]
+    Empty,
+
+    /// The name of the method was unknown.
+    #[error("unknown method `{0}`")]
+    UnknownMethod(String),
+
+    /// The parameter of the method was invalid.
+    #[error("invalid parameter `{0}`")]
+    InvalidParam(String),
+}
+
+/// The error type when building a folder layout failed.
+#[derive(Debug, Error)]
+pub enum BuildFolderError {
+    /// The folder had no coder.
+    #[error("folder has no coder")]
+    Empty,
+
+    /// The bind pair referred to a stream which did not exist.
+    #[error("invalid bind pair of input stream `{0}` and output stream `{1}`")]
+    BindPair(usize, usize),
+
+    /// The input stream was not bound or packed exactly once.
+    #[error("input stream `{0}` is not bound or packed exactly once")]
+    InStream(usize),
+
+    /// The output stream was bound more than once.
+    #[error("output stream `{0}` is bound more than once")]
+    OutStream(usize),
+
+    /// The number of the output streams which were not bound was not one.
+    #[error("folder does not have exactly one main output stream")]
+    MainStream,
+
+    /// The coders were bound in a cycle.
+    #[error("coders are bound in a cycle")]
+    Cycle,
+
+    /// The coder was not connected to the main output stream.
+    #[error("coder `{0}` is not connected to main output stream")]
+    UnusedCoder(usize),
 }
 
 /// Alias for a [`Result`](std::result::Result) with [`crate::Error`].
@@ -135,6 +263,54 @@ mod tests {
             ),
             "expected end id for Header, found `0x00`"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidArchive(InvalidArchive::UnexpectedProperty {
+                    pos: Property::UnpackInfo,
+                    id: u8::MAX
+                })
+                .source()
+                .unwrap()
+            ),
+            "unexpected property `0xff` in UnpackInfo"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidArchive(InvalidArchive::Header)
+                    .source()
+                    .unwrap()
+            ),
+            "invalid header"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidArchive(InvalidArchive::Folder)
+                    .source()
+                    .unwrap()
+            ),
+            "invalid folder"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidArchive(InvalidArchive::DataCrc)
+                    .source()
+                    .unwrap()
+            ),
+            "data CRC mismatch"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidArchive(InvalidArchive::SymlinkTarget)
+                    .source()
+                    .unwrap()
+            ),
+            "invalid target of symbolic link"
+        );
     }
 
     #[test]
@@ -153,6 +329,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn display_wrong_password_error() {
+        assert_eq!(
+            format!("{}", Error::WrongPassword),
+            "wrong password to decrypt 7z archive"
+        );
+    }
+
     #[test]
     fn display_unsupported_archive_error() {
         let unsupported_version_error = Error::UnsupportedArchive(UnsupportedArchive::Version {
@@ -179,6 +363,42 @@ mod tests {
             ),
             "unsupported compression method `LZMA`"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::UnsupportedArchive(UnsupportedArchive::UnknownMethod(vec![0x7f, 0x01]))
+                    .source()
+                    .unwrap()
+            ),
+            "unknown compression method `7F01`"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::UnsupportedArchive(UnsupportedArchive::MethodParam("d=x".to_string()))
+                    .source()
+                    .unwrap()
+            ),
+            "unsupported parameter `d=x` of compression method"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::UnsupportedArchive(UnsupportedArchive::ExternalData)
+                    .source()
+                    .unwrap()
+            ),
+            "unsupported external data"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::UnsupportedArchive(UnsupportedArchive::FolderLayout)
+                    .source()
+                    .unwrap()
+            ),
+            "unsupported folder layout whose coders do not form a chain"
+        );
     }
 
     #[test]
@@ -186,6 +406,54 @@ mod tests {
         assert_eq!(format!("{}", Error::Other("Error".into())), "Error");
     }
 
+    #[test]
+    fn display_parse_method_error() {
+        assert_eq!(
+            format!("{}", ParseMethodError::Empty),
+            "empty method string"
+        );
+        assert_eq!(
+            format!("{}", ParseMethodError::UnknownMethod("LZMA3".to_string())),
+            "unknown method `LZMA3`"
+        );
+        assert_eq!(
+            format!("{}", ParseMethodError::InvalidParam("d=x".to_string())),
+            "invalid parameter `d=x`"
+        );
+    }
+
+    #[test]
+    fn display_build_folder_error() {
+        assert_eq!(
+            format!("{}", BuildFolderError::Empty),
+            "folder has no coder"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::BindPair(1, 2)),
+            "invalid bind pair of input stream `1` and output stream `2`"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::InStream(1)),
+            "input stream `1` is not bound or packed exactly once"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::OutStream(1)),
+            "output stream `1` is bound more than once"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::MainStream),
+            "folder does not have exactly one main output stream"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::Cycle),
+            "coders are bound in a cycle"
+        );
+        assert_eq!(
+            format!("{}", BuildFolderError::UnusedCoder(1)),
+            "coder `1` is not connected to main output stream"
+        );
+    }
+
     #[test]
     fn source_invalid_archive_error() {
         assert!(
@@ -209,6 +477,29 @@ mod tests {
         .source()
         .unwrap()
         .is::<InvalidArchive>());
+        assert!(Error::InvalidArchive(InvalidArchive::UnexpectedProperty {
+            pos: Property::UnpackInfo,
+            id: u8::MAX
+        })
+        .source()
+        .unwrap()
+        .is::<InvalidArchive>());
+        assert!(Error::InvalidArchive(InvalidArchive::Header)
+            .source()
+            .unwrap()
+            .is::<InvalidArchive>());
+        assert!(Error::InvalidArchive(InvalidArchive::Folder)
+            .source()
+            .unwrap()
+            .is::<InvalidArchive>());
+        assert!(Error::InvalidArchive(InvalidArchive::DataCrc)
+            .source()
+            .unwrap()
+            .is::<InvalidArchive>());
+        assert!(Error::InvalidArchive(InvalidArchive::SymlinkTarget)
+            .source()
+            .unwrap()
+            .is::<InvalidArchive>());
     }
 
     #[test]
@@ -221,6 +512,11 @@ mod tests {
         assert!(Error::PasswordRequired.source().is_none());
     }
 
+    #[test]
+    fn source_wrong_password_error() {
+        assert!(Error::WrongPassword.source().is_none());
+    }
+
     #[test]
     fn source_unsupported_archive_error() {
         assert!(Error::UnsupportedArchive(UnsupportedArchive::Version {
@@ -236,6 +532,16 @@ mod tests {
                 .unwrap()
                 .is::<UnsupportedArchive>()
         );
+        assert!(
+            Error::UnsupportedArchive(UnsupportedArchive::UnknownMethod(vec![0x7f, 0x01]))
+                .source()
+                .unwrap()
+                .is::<UnsupportedArchive>()
+        );
+        assert!(Error::UnsupportedArchive(UnsupportedArchive::ExternalData)
+            .source()
+            .unwrap()
+            .is::<UnsupportedArchive>());
     }
 
     #[test]
@@ -264,6 +570,29 @@ mod tests {
             }),
             Error::InvalidArchive(InvalidArchive::EndProperty { .. })
         ));
+        assert!(matches!(
+            Error::from(InvalidArchive::UnexpectedProperty {
+                pos: Property::UnpackInfo,
+                id: u8::MAX
+            }),
+            Error::InvalidArchive(InvalidArchive::UnexpectedProperty { .. })
+        ));
+        assert!(matches!(
+            Error::from(InvalidArchive::Header),
+            Error::InvalidArchive(InvalidArchive::Header)
+        ));
+        assert!(matches!(
+            Error::from(InvalidArchive::Folder),
+            Error::InvalidArchive(InvalidArchive::Folder)
+        ));
+        assert!(matches!(
+            Error::from(InvalidArchive::DataCrc),
+            Error::InvalidArchive(InvalidArchive::DataCrc)
+        ));
+        assert!(matches!(
+            Error::from(InvalidArchive::SymlinkTarget),
+            Error::InvalidArchive(InvalidArchive::SymlinkTarget)
+        ));
     }
 
     #[test]
@@ -280,6 +609,14 @@ mod tests {
             Error::from(io::Error::from(io::ErrorKind::NotFound)),
             Error::Io(_)
         ));
+        assert!(matches!(
+            Error::from(io::Error::from(Error::WrongPassword)),
+            Error::WrongPassword
+        ));
+        assert!(matches!(
+            Error::from(io::Error::new(io::ErrorKind::Other, "error")),
+            Error::Io(_)
+        ));
     }
 
     #[test]
@@ -295,6 +632,14 @@ mod tests {
             Error::from(UnsupportedArchive::CompressionMethod("LZMA".to_string())),
             Error::UnsupportedArchive(UnsupportedArchive::CompressionMethod(_))
         ));
+        assert!(matches!(
+            Error::from(UnsupportedArchive::UnknownMethod(vec![0x7f, 0x01])),
+            Error::UnsupportedArchive(UnsupportedArchive::UnknownMethod(_))
+        ));
+        assert!(matches!(
+            Error::from(UnsupportedArchive::ExternalData),
+            Error::UnsupportedArchive(UnsupportedArchive::ExternalData)
+        ));
     }
 
     #[test]
@@ -0,0 +1,699 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Filters which transform data without compressing it.
+
+use std::io::{self, Read, Write};
+
+use crate::method::{Method, Sz};
+
+/// The size of the internal buffer of the readers.
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// A reader which reverses the byte order of each `width`-byte word.
+///
+/// The trailing bytes which do not form a whole word are left as they are.
+/// Since the byte swap is its own inverse, this is used for both Swap2 and
+/// Swap4 decoding.
+pub struct SwapReader<R> {
+    inner: R,
+    width: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    ready: usize,
+}
+
+impl<R: Read> SwapReader<R> {
+    /// Creates a new `SwapReader`.
+    pub fn new(inner: R, width: usize) -> Self {
+        Self {
+            inner,
+            width,
+            buf: Vec::with_capacity(BUFFER_SIZE),
+            pos: usize::default(),
+            ready: usize::default(),
+        }
+    }
+
+    /// Reads the next words into the internal buffer.
+    fn fill_buf(&mut self) -> io::Result<()> {
+        self.buf.drain(..self.ready);
+        self.pos = usize::default();
+        let mut is_eof = false;
+        while !is_eof && self.buf.len() < self.width {
+            let len = self.buf.len();
+            self.buf.resize(BUFFER_SIZE, u8::default());
+            let n = self.inner.read(&mut self.buf[len..]);
+            self.buf.truncate(len + n.as_ref().map_or(0, |&n| n));
+            is_eof = n? == 0;
+        }
+        self.ready = if is_eof {
+            self.buf.len()
+        } else {
+            self.buf.len() - self.buf.len() % self.width
+        };
+        for word in self.buf[..self.ready].chunks_exact_mut(self.width) {
+            word.reverse();
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SwapReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.ready {
+            self.fill_buf()?;
+        }
+        let n = buf.len().min(self.ready - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A writer which reverses the byte order of each `width`-byte word.
+///
+/// The trailing bytes which do not form a whole word are written as they are
+/// by [`SwapWriter::finish`].
+pub struct SwapWriter<W> {
+    inner: W,
+    width: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> SwapWriter<W> {
+    /// Creates a new `SwapWriter`.
+    pub const fn new(inner: W, width: usize) -> Self {
+        Self {
+            inner,
+            width,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Writes the remaining data, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SwapWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        let len = self.buf.len() - self.buf.len() % self.width;
+        for word in self.buf[..len].chunks_exact_mut(self.width) {
+            word.reverse();
+        }
+        self.inner.write_all(&self.buf[..len])?;
+        self.buf.drain(..len);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The state of the Delta filter.
+struct Delta {
+    history: [u8; 256],
+    distance: usize,
+    index: usize,
+}
+
+impl Delta {
+    /// Creates a new `Delta` with the distance from 1 to 256.
+    const fn new(distance: usize) -> Self {
+        Self {
+            history: [0; 256],
+            distance,
+            index: 0,
+        }
+    }
+
+    /// Decodes the byte.
+    fn decode(&mut self, byte: u8) -> u8 {
+        let byte = byte.wrapping_add(self.history[self.index]);
+        self.push(byte);
+        byte
+    }
+
+    /// Encodes the byte.
+    fn encode(&mut self, byte: u8) -> u8 {
+        let delta = byte.wrapping_sub(self.history[self.index]);
+        self.push(byte);
+        delta
+    }
+
+    /// Records the decoded byte.
+    fn push(&mut self, byte: u8) {
+        self.history[self.index] = byte;
+        self.index = (self.index + 1) % self.distance;
+    }
+}
+
+/// A reader which decodes the Delta filter.
+pub struct DeltaReader<R> {
+    inner: R,
+    delta: Delta,
+}
+
+impl<R: Read> DeltaReader<R> {
+    /// Creates a new `DeltaReader` with the distance from 1 to 256.
+    pub const fn new(inner: R, distance: usize) -> Self {
+        Self {
+            inner,
+            delta: Delta::new(distance),
+        }
+    }
+}
+
+impl<R: Read> Read for DeltaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for byte in &mut buf[..n] {
+            *byte = self.delta.decode(*byte);
+        }
+        Ok(n)
+    }
+}
+
+/// A writer which encodes the Delta filter.
+pub struct DeltaWriter<W> {
+    inner: W,
+    delta: Delta,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DeltaWriter<W> {
+    /// Creates a new `DeltaWriter` with the distance from 1 to 256.
+    pub const fn new(inner: W, distance: usize) -> Self {
+        Self {
+            inner,
+            delta: Delta::new(distance),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Flushes the underlying writer, returning it.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for DeltaWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.clear();
+        self.buf
+            .extend(buf.iter().map(|&byte| self.delta.encode(byte)));
+        self.inner.write_all(&self.buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The architecture of a branch converter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Arch {
+    /// x86 (BCJ).
+    X86,
+    /// ARM little-endian.
+    Arm,
+    /// ARM Thumb little-endian.
+    ArmThumb,
+    /// ARM64.
+    Arm64,
+    /// PowerPC big-endian.
+    Ppc,
+    /// SPARC.
+    Sparc,
+}
+
+impl Arch {
+    /// Returns the architecture of the branch converter of the method.
+    pub const fn from_method(method: &Method) -> Option<Self> {
+        match method {
+            Method::Sz(Sz::Bcj) => Some(Self::X86),
+            Method::Sz(Sz::Arm) => Some(Self::Arm),
+            Method::Sz(Sz::ArmT) => Some(Self::ArmThumb),
+            Method::Arm64 => Some(Self::Arm64),
+            Method::Sz(Sz::Ppc) => Some(Self::Ppc),
+            Method::Sz(Sz::Sparc) => Some(Self::Sparc),
+            _ => None,
+        }
+    }
+}
+
+/// A branch converter, which converts the relative addresses of the branch
+/// instructions to the absolute addresses so that they are compressed better.
+///
+/// This is the same algorithm as the filters of 7-Zip and XZ Utils.
+struct Branch {
+    arch: Arch,
+    is_encoder: bool,
+    pos: u32,
+    prev_mask: u32,
+    prev_pos: u32,
+}
+
+impl Branch {
+    /// Creates a new `Branch` which starts at `pos`.
+    const fn new(arch: Arch, is_encoder: bool, pos: u32) -> Self {
+        Self {
+            arch,
+            is_encoder,
+            pos,
+            prev_mask: 0,
+            prev_pos: 0_u32.wrapping_sub(5),
+        }
+    }
+
+    /// Returns the offset which is added to the address by encoding.
+    const fn offset(&self, pos: u32) -> u32 {
+        if self.is_encoder {
+            pos
+        } else {
+            0_u32.wrapping_sub(pos)
+        }
+    }
+
+    /// Converts the instructions in `buf`, returning the number of the bytes
+    /// which are converted.
+    ///
+    /// The rest of the bytes are converted with the following data.
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert(&mut self, buf: &mut [u8]) -> usize {
+        let n = match self.arch {
+            Arch::X86 => self.convert_x86(buf),
+            Arch::Arm => self.convert_arm(buf),
+            Arch::ArmThumb => self.convert_arm_thumb(buf),
+            Arch::Arm64 => self.convert_arm64(buf),
+            Arch::Ppc => self.convert_ppc(buf),
+            Arch::Sparc => self.convert_sparc(buf),
+        };
+        self.pos = self.pos.wrapping_add(n as u32);
+        n
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_x86(&mut self, buf: &mut [u8]) -> usize {
+        const MASK_TO_ALLOWED: [bool; 8] = [true, true, true, false, true, false, false, false];
+        const MASK_TO_BIT_NUMBER: [u32; 8] = [0, 1, 2, 2, 3, 3, 3, 3];
+        let is_ms_byte = |b: u8| b == 0x00 || b == 0xff;
+
+        if buf.len() < 5 {
+            return 0;
+        }
+        if self.pos.wrapping_sub(self.prev_pos) > 5 {
+            self.prev_pos = self.pos.wrapping_sub(5);
+        }
+        let mut i = 0;
+        while i <= buf.len() - 5 {
+            if buf[i] != 0xe8 && buf[i] != 0xe9 {
+                i += 1;
+                continue;
+            }
+            let pos = self.pos.wrapping_add(i as u32);
+            let offset = pos.wrapping_sub(self.prev_pos);
+            self.prev_pos = pos;
+            if offset > 5 {
+                self.prev_mask = 0;
+            } else {
+                for _ in 0..offset {
+                    self.prev_mask &= 0x77;
+                    self.prev_mask <<= 1;
+                }
+            }
+            let b = buf[i + 4];
+            let mask = (self.prev_mask >> 1) as usize;
+            if is_ms_byte(b) && mask < 0x10 && MASK_TO_ALLOWED[mask & 0x07] {
+                let mut src = u32::from_le_bytes([buf[i + 1], buf[i + 2], buf[i + 3], b]);
+                let mut dest;
+                loop {
+                    dest = src.wrapping_add(self.offset(pos.wrapping_add(5)));
+                    if self.prev_mask == 0 {
+                        break;
+                    }
+                    let bit = MASK_TO_BIT_NUMBER[(self.prev_mask >> 1) as usize] * 8;
+                    if !is_ms_byte((dest >> (24 - bit)) as u8) {
+                        break;
+                    }
+                    src = dest ^ ((1 << (32 - bit)) - 1);
+                }
+                let dest = (dest & 0x01ff_ffff) | (((dest >> 24) & 1).wrapping_neg() << 24);
+                buf[i + 1..i + 5].copy_from_slice(&dest.to_le_bytes());
+                i += 5;
+                self.prev_mask = 0;
+            } else {
+                i += 1;
+                self.prev_mask |= 1;
+                if is_ms_byte(b) {
+                    self.prev_mask |= 0x10;
+                }
+            }
+        }
+        i
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_arm(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len() & !3;
+        for (i, word) in buf[..len].chunks_exact_mut(4).enumerate() {
+            if word[3] == 0xeb {
+                let src = u32::from_le_bytes([word[0], word[1], word[2], 0]) << 2;
+                let pos = self.pos.wrapping_add((i * 4) as u32 + 8);
+                let dest = src.wrapping_add(self.offset(pos)) >> 2;
+                word[..3].copy_from_slice(&dest.to_le_bytes()[..3]);
+            }
+        }
+        len
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_arm_thumb(&self, buf: &mut [u8]) -> usize {
+        if buf.len() < 4 {
+            return 0;
+        }
+        let mut i = 0;
+        while i <= buf.len() - 4 {
+            if buf[i + 1] & 0xf8 == 0xf0 && buf[i + 3] & 0xf8 == 0xf8 {
+                let src = (u32::from(buf[i + 1] & 0x07) << 19)
+                    | (u32::from(buf[i]) << 11)
+                    | (u32::from(buf[i + 3] & 0x07) << 8)
+                    | u32::from(buf[i + 2]);
+                let pos = self.pos.wrapping_add(i as u32 + 4);
+                let dest = (src << 1).wrapping_add(self.offset(pos)) >> 1;
+                buf[i + 1] = 0xf0 | ((dest >> 19) & 0x07) as u8;
+                buf[i] = (dest >> 11) as u8;
+                buf[i + 3] = 0xf8 | ((dest >> 8) & 0x07) as u8;
+                buf[i + 2] = dest as u8;
+                i += 2;
+            }
+            i += 2;
+        }
+        i
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_arm64(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len() & !3;
+        for (i, word) in buf[..len].chunks_exact_mut(4).enumerate() {
+            let pos = self.pos.wrapping_add((i * 4) as u32);
+            let instr = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            let instr = if instr >> 26 == 0x25 {
+                // BL
+                let dest = instr.wrapping_add(self.offset(pos >> 2));
+                0x9400_0000 | (dest & 0x03ff_ffff)
+            } else if instr & 0x9f00_0000 == 0x9000_0000 {
+                // ADRP
+                let src = ((instr >> 29) & 0x03) | ((instr >> 3) & 0x001f_fffc);
+                if src.wrapping_add(0x0002_0000) & 0x001c_0000 != 0 {
+                    continue;
+                }
+                let dest = src.wrapping_add(self.offset(pos >> 12));
+                (instr & 0x9000_001f)
+                    | ((dest & 0x03) << 29)
+                    | ((dest & 0x0003_fffc) << 3)
+                    | ((dest & 0x0002_0000).wrapping_neg() & 0x00e0_0000)
+            } else {
+                continue;
+            };
+            word.copy_from_slice(&instr.to_le_bytes());
+        }
+        len
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_ppc(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len() & !3;
+        for (i, word) in buf[..len].chunks_exact_mut(4).enumerate() {
+            if word[0] >> 2 == 0x12 && word[3] & 0x03 == 0x01 {
+                let src = u32::from_be_bytes([word[0] & 0x03, word[1], word[2], word[3] & !0x03]);
+                let pos = self.pos.wrapping_add((i * 4) as u32);
+                let dest = src.wrapping_add(self.offset(pos));
+                let dest = 0x4800_0000 | (dest & 0x03ff_ffff) | u32::from(word[3] & 0x03);
+                word.copy_from_slice(&dest.to_be_bytes());
+            }
+        }
+        len
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn convert_sparc(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len() & !3;
+        for (i, word) in buf[..len].chunks_exact_mut(4).enumerate() {
+            if (word[0] == 0x40 && word[1] & 0xc0 == 0x00)
+                || (word[0] == 0x7f && word[1] & 0xc0 == 0xc0)
+            {
+                let src = u32::from_be_bytes([word[0], word[1], word[2], word[3]]) << 2;
+                let pos = self.pos.wrapping_add((i * 4) as u32);
+                let dest = src.wrapping_add(self.offset(pos)) >> 2;
+                let dest = ((((dest >> 22) & 1).wrapping_neg() << 22) & 0x3fff_ffff)
+                    | (dest & 0x003f_ffff)
+                    | 0x4000_0000;
+                word.copy_from_slice(&dest.to_be_bytes());
+            }
+        }
+        len
+    }
+}
+
+/// A reader which decodes a branch converter.
+pub struct BranchReader<R> {
+    inner: R,
+    branch: Branch,
+    buf: Box<[u8]>,
+    pos: usize,
+    ready: usize,
+    filled: usize,
+}
+
+impl<R: Read> BranchReader<R> {
+    /// Creates a new `BranchReader` whose data start at `start`.
+    pub fn new(inner: R, arch: Arch, start: u32) -> Self {
+        Self {
+            inner,
+            branch: Branch::new(arch, false, start),
+            buf: vec![u8::default(); BUFFER_SIZE].into_boxed_slice(),
+            pos: usize::default(),
+            ready: usize::default(),
+            filled: usize::default(),
+        }
+    }
+
+    /// Reads the next instructions into the internal buffer.
+    fn fill_buf(&mut self) -> io::Result<()> {
+        // The bytes which are not converted yet are at most 4 bytes.
+        self.buf.copy_within(self.ready..self.filled, 0);
+        self.filled -= self.ready;
+        self.pos = usize::default();
+        loop {
+            let n = self.inner.read(&mut self.buf[self.filled..])?;
+            self.filled += n;
+            self.ready = self.branch.convert(&mut self.buf[..self.filled]);
+            if n == 0 {
+                // The trailing bytes are not converted.
+                self.ready = self.filled;
+            }
+            if n == 0 || self.ready != 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for BranchReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.ready {
+            self.fill_buf()?;
+        }
+        let n = buf.len().min(self.ready - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A writer which encodes a branch converter.
+///
+/// The trailing bytes which are not converted are written as they are by
+/// [`BranchWriter::finish`].
+pub struct BranchWriter<W> {
+    inner: W,
+    branch: Branch,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BranchWriter<W> {
+    /// Creates a new `BranchWriter` whose data start at `start`.
+    pub const fn new(inner: W, arch: Arch, start: u32) -> Self {
+        Self {
+            inner,
+            branch: Branch::new(arch, true, start),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Writes the remaining data, returning the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BranchWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        let n = self.branch.convert(&mut self.buf);
+        self.inner.write_all(&self.buf[..n])?;
+        self.buf.drain(..n);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader which returns at most one byte at a time.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn read_all(mut reader: impl Read) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn swap() {
+        assert_eq!(read_all(SwapReader::new(&b"abcde"[..], 2)), b"badce");
+        assert_eq!(
+            read_all(SwapReader::new(&b"abcdefghi"[..], 4)),
+            b"dcbahgfei"
+        );
+        assert_eq!(
+            read_all(SwapReader::new(OneByte(b"abcdefghi"), 4)),
+            b"dcbahgfei"
+        );
+        assert!(read_all(SwapReader::new(&b""[..], 4)).is_empty());
+    }
+
+    #[test]
+    fn swap_round_trip() {
+        let data = (0..=u8::MAX)
+            .cycle()
+            .take(BUFFER_SIZE * 2 + 3)
+            .collect::<Vec<_>>();
+        for width in [2, 4] {
+            let mut writer = SwapWriter::new(Vec::new(), width);
+            for chunk in data.chunks(7) {
+                writer.write_all(chunk).unwrap();
+            }
+            let swapped = writer.finish().unwrap();
+            assert_eq!(
+                swapped[..width],
+                data[..width].iter().rev().copied().collect::<Vec<_>>()
+            );
+            assert_eq!(read_all(SwapReader::new(&swapped[..], width)), data);
+        }
+    }
+
+    #[test]
+    fn delta() {
+        assert_eq!(
+            read_all(DeltaReader::new(&[1, 1, 1, 2, 3][..], 1)),
+            [1, 2, 3, 5, 8]
+        );
+        assert_eq!(
+            read_all(DeltaReader::new(&[1, 2, 1, 2, 1][..], 2)),
+            [1, 2, 2, 4, 3]
+        );
+    }
+
+    #[test]
+    fn branch() {
+        // `call +0x10` at 0x100 and `bl +0x10` at 0x100.
+        let mut buf = vec![0xe8, 0x10, 0x00, 0x00, 0x00, 0x90];
+        assert_eq!(Branch::new(Arch::X86, true, 0x100).convert(&mut buf), 5);
+        assert_eq!(buf, [0xe8, 0x15, 0x01, 0x00, 0x00, 0x90]);
+        let mut buf = vec![0x04, 0x00, 0x00, 0xeb];
+        assert_eq!(Branch::new(Arch::Arm, true, 0x100).convert(&mut buf), 4);
+        assert_eq!(buf, [0x46, 0x00, 0x00, 0xeb]);
+        let mut buf = vec![0x04, 0x00, 0x00, 0x94];
+        Branch::new(Arch::Arm64, true, 0x100).convert(&mut buf);
+        assert_eq!(buf, [0x44, 0x00, 0x00, 0x94]);
+        let mut buf = vec![0x48, 0x00, 0x00, 0x11];
+        Branch::new(Arch::Ppc, true, 0x100).convert(&mut buf);
+        assert_eq!(buf, [0x48, 0x00, 0x01, 0x11]);
+    }
+
+    #[test]
+    fn branch_round_trip() {
+        // Pseudo-random data contain the branch instructions of each
+        // architecture by chance.
+        let mut state = 1_u32;
+        let data = (0..BUFFER_SIZE * 2 + 3)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                state.to_be_bytes()[0]
+            })
+            .collect::<Vec<_>>();
+        for arch in [
+            Arch::X86,
+            Arch::Arm,
+            Arch::ArmThumb,
+            Arch::Arm64,
+            Arch::Ppc,
+            Arch::Sparc,
+        ] {
+            let mut writer = BranchWriter::new(Vec::new(), arch, 0);
+            for chunk in data.chunks(7) {
+                writer.write_all(chunk).unwrap();
+            }
+            let encoded = writer.finish().unwrap();
+            assert_eq!(encoded.len(), data.len());
+            assert!(encoded != data, "{arch:?}");
+            assert_eq!(read_all(BranchReader::new(&encoded[..], arch, 0)), data);
+            assert_eq!(
+                read_all(BranchReader::new(OneByte(&encoded), arch, 0)),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let data = (0..=u8::MAX).cycle().take(1024).collect::<Vec<_>>();
+        for distance in [1, 4, 256] {
+            let mut writer = DeltaWriter::new(Vec::new(), distance);
+            for chunk in data.chunks(7) {
+                writer.write_all(chunk).unwrap();
+            }
+            let encoded = writer.finish().unwrap();
+            assert_eq!(read_all(DeltaReader::new(&encoded[..], distance)), data);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Layouts of the coders of folders.
+
+use crate::{
+    error::BuildFolderError,
+    method::{Method, MethodChain, MethodSpec},
+};
+
+/// A coder of a [`FolderLayout`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoderSpec {
+    /// The method with the parameters.
+    pub method: MethodSpec,
+
+    /// The number of the input streams of the decoder.
+    pub num_in_streams: usize,
+
+    /// The number of the output streams of the decoder.
+    pub num_out_streams: usize,
+}
+
+impl CoderSpec {
+    /// Creates a new `CoderSpec` which has an input stream and an output
+    /// stream.
+    pub fn new(method: impl Into<MethodSpec>) -> Self {
+        Self::with_streams(method, 1, 1)
+    }
+
+    /// Creates a new `CoderSpec` with the numbers of the streams.
+    pub fn with_streams(
+        method: impl Into<MethodSpec>,
+        num_in_streams: usize,
+        num_out_streams: usize,
+    ) -> Self {
+        Self {
+            method: method.into(),
+            num_in_streams,
+            num_out_streams,
+        }
+    }
+
+    /// Returns `true` if this coder has an input stream and an output stream.
+    #[must_use]
+    pub const fn is_simple(&self) -> bool {
+        self.num_in_streams == 1 && self.num_out_streams == 1
+    }
+}
+
+/// A builder of a [`FolderLayout`].
+///
+/// The coders are added in the order of encoding, which is the order in which
+/// 7-Zip stores them, so `FolderBuilder::new().filter(Method::Sz(Sz::Bcj))
+/// .coder(Method::Lzma2, &props)` applies the BCJ filter before LZMA2. If
+/// neither bind pairs nor packed streams are added, the input of each coder is
+/// bound to the output of the next coder, and the input of the last coder is
+/// the packed stream.
+///
+/// The streams are numbered across the coders, as the bind pairs of the 7z
+/// format do.
+///
+/// Any valid graph can be built, but [`ArchiveWriter`] writes only the layouts
+/// whose coders form a chain, as [`FolderLayout::is_chain`] returns. The
+/// coders with more than one stream, such as BCJ2, are not supported for
+/// encoding.
+///
+/// [`ArchiveWriter`]: crate::ArchiveWriter
+#[derive(Clone, Debug, Default)]
+pub struct FolderBuilder {
+    coders: Vec<CoderSpec>,
+    bind_pairs: Vec<(usize, usize)>,
+    packed_streams: Vec<usize>,
+}
+
+impl FolderBuilder {
+    /// Creates a new empty `FolderBuilder`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a filter with the default parameters.
+    #[must_use]
+    pub fn filter(self, method: Method) -> Self {
+        self.add_coder(CoderSpec::new(method))
+    }
+
+    /// Adds a coder which is configured by the properties as they are stored
+    /// in an archive, such as the bytes of [`Lzma2Props`].
+    ///
+    /// The properties are converted to the parameters of the method, and the
+    /// ones which are invalid are ignored. The properties which are written
+    /// into the archive are the ones of the encoder.
+    ///
+    /// [`Lzma2Props`]: crate::method::Lzma2Props
+    #[must_use]
+    pub fn coder(self, method: Method, properties: &[u8]) -> Self {
+        self.add_coder(CoderSpec::new(MethodSpec::from_properties(
+            method, properties,
+        )))
+    }
+
+    /// Adds a coder.
+    #[must_use]
+    pub fn add_coder(mut self, coder: CoderSpec) -> Self {
+        self.coders.push(coder);
+        self
+    }
+
+    /// Binds the input stream `in_index` to the output stream `out_index`.
+    #[must_use]
+    pub fn bind_pair(mut self, in_index: usize, out_index: usize) -> Self {
+        self.bind_pairs.push((in_index, out_index));
+        self
+    }
+
+    /// Adds the input stream `in_index` as the next packed stream.
+    #[must_use]
+    pub fn packed_stream(mut self, in_index: usize) -> Self {
+        self.packed_streams.push(in_index);
+        self
+    }
+
+    /// Validates the graph of the coders and builds a [`FolderLayout`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if there is no coder, if a stream is not connected
+    /// exactly once, or if the coders are not connected to the main output
+    /// stream without a cycle.
+    pub fn build(mut self) -> Result<FolderLayout, BuildFolderError> {
+        if self.coders.is_empty() {
+            return Err(BuildFolderError::Empty);
+        }
+        if self.bind_pairs.is_empty()
+            && self.packed_streams.is_empty()
+            && self.coders.iter().all(CoderSpec::is_simple)
+        {
+            let num_coders = self.coders.len();
+            self.bind_pairs = (1..num_coders).map(|i| (i - 1, i)).collect();
+            self.packed_streams = vec![num_coders - 1];
+        }
+        let layout = FolderLayout {
+            coders: self.coders,
+            bind_pairs: self.bind_pairs,
+            packed_streams: self.packed_streams,
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+/// A validated layout of the coders of a folder.
+///
+/// This is built by [`FolderBuilder`], or converted from a [`MethodChain`]
+/// with the same layout as 7-Zip.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FolderLayout {
+    coders: Vec<CoderSpec>,
+    bind_pairs: Vec<(usize, usize)>,
+    packed_streams: Vec<usize>,
+}
+
+impl FolderLayout {
+    /// Returns the coders in the order they are stored.
+    #[must_use]
+    pub fn coders(&self) -> &[CoderSpec] {
+        &self.coders
+    }
+
+    /// Returns the bind pairs of an input stream and an output stream.
+    #[must_use]
+    pub fn bind_pairs(&self) -> &[(usize, usize)] {
+        &self.bind_pairs
+    }
+
+    /// Returns the input streams which read the packed streams.
+    #[must_use]
+    pub fn packed_streams(&self) -> &[usize] {
+        &self.packed_streams
+    }
+
+    /// Returns `true` if each coder has an input stream and an output stream,
+    /// so that the coders form a chain.
+    #[must_use]
+    pub fn is_chain(&self) -> bool {
+        self.coders.iter().all(CoderSpec::is_simple)
+    }
+
+    /// Returns the indices of the coders in the order of decoding, from the
+    /// ones which read the packed streams to the one of the main output
+    /// stream.
+    #[must_use]
+    pub fn decoding_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.coders.len());
+        // The layout is validated, so there is no cycle.
+        let _ = self.visit(self.main_coder(), &mut Vec::new(), &mut order);
+        order
+    }
+
+    /// Returns the methods in the order of decoding.
+    #[must_use]
+    pub fn methods(&self) -> MethodChain {
+        MethodChain(
+            self.decoding_order()
+                .into_iter()
+                .map(|i| self.coders[i].method.clone())
+                .collect(),
+        )
+    }
+
+    /// Returns the index of the first input stream and the first output stream
+    /// of the coder.
+    fn first_streams(&self, coder: usize) -> (usize, usize) {
+        self.coders[..coder].iter().fold((0, 0), |(i, o), c| {
+            (i + c.num_in_streams, o + c.num_out_streams)
+        })
+    }
+
+    /// Returns the index of the coder of the output stream.
+    fn coder_of_out_stream(&self, out_index: usize) -> Option<usize> {
+        let mut first = 0;
+        self.coders.iter().position(|c| {
+            first += c.num_out_streams;
+            out_index < first
+        })
+    }
+
+    /// Returns the index of the coder of the output stream which is not
+    /// bound.
+    fn main_coder(&self) -> usize {
+        let (_, num_out_streams) = self.first_streams(self.coders.len());
+        (0..num_out_streams)
+            .find(|o| {
+                self.bind_pairs
+                    .iter()
+                    .all(|&(_, out_index)| out_index != *o)
+            })
+            .and_then(|o| self.coder_of_out_stream(o))
+            .unwrap_or_default()
+    }
+
+    /// Visits the coders which the coder reads in post-order.
+    fn visit(
+        &self,
+        coder: usize,
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), BuildFolderError> {
+        if path.contains(&coder) {
+            return Err(BuildFolderError::Cycle);
+        }
+        if order.contains(&coder) {
+            return Ok(());
+        }
+        path.push(coder);
+        let (first_in_stream, _) = self.first_streams(coder);
+        for in_index in first_in_stream..first_in_stream + self.coders[coder].num_in_streams {
+            if let Some(input) = self
+                .bind_pairs
+                .iter()
+                .find(|&&(i, _)| i == in_index)
+                .and_then(|&(_, out_index)| self.coder_of_out_stream(out_index))
+            {
+                self.visit(input, path, order)?;
+            }
+        }
+        path.pop();
+        order.push(coder);
+        Ok(())
+    }
+
+    /// Checks that each stream is connected exactly once and that all the
+    /// coders are connected to the main output stream.
+    fn validate(&self) -> Result<(), BuildFolderError> {
+        let (num_in_streams, num_out_streams) = self.first_streams(self.coders.len());
+        for &(in_index, out_index) in &self.bind_pairs {
+            if in_index >= num_in_streams || out_index >= num_out_streams {
+                return Err(BuildFolderError::BindPair(in_index, out_index));
+            }
+        }
+        if let Some(&in_index) = self.packed_streams.iter().find(|&&i| i >= num_in_streams) {
+            return Err(BuildFolderError::InStream(in_index));
+        }
+        if let Some(in_index) = (0..num_in_streams).find(|&i| {
+            let bound = self.bind_pairs.iter().filter(|&&(j, _)| i == j).count();
+            let packed = self.packed_streams.iter().filter(|&&j| i == j).count();
+            bound + packed != 1
+        }) {
+            return Err(BuildFolderError::InStream(in_index));
+        }
+        if let Some(out_index) = (0..num_out_streams).find(|&o| {
+            self.bind_pairs
+                .iter()
+                .filter(|&&(_, out_index)| o == out_index)
+                .count()
+                > 1
+        }) {
+            return Err(BuildFolderError::OutStream(out_index));
+        }
+        if num_out_streams != self.bind_pairs.len() + 1 {
+            return Err(BuildFolderError::MainStream);
+        }
+
+        let mut order = Vec::with_capacity(self.coders.len());
+        self.visit(self.main_coder(), &mut Vec::new(), &mut order)?;
+        if let Some(coder) = (0..self.coders.len()).find(|i| !order.contains(i)) {
+            return Err(BuildFolderError::UnusedCoder(coder));
+        }
+        Ok(())
+    }
+}
+
+impl From<Method> for FolderLayout {
+    fn from(method: Method) -> Self {
+        MethodChain::from(method).into()
+    }
+}
+
+impl From<MethodChain> for FolderLayout {
+    /// Converts the methods in the order of decoding to a chain of coders.
+    ///
+    /// An empty chain is converted to Copy.
+    fn from(methods: MethodChain) -> Self {
+        let mut coders = methods
+            .0
+            .into_iter()
+            .rev()
+            .map(CoderSpec::new)
+            .collect::<Vec<_>>();
+        if coders.is_empty() {
+            coders.push(CoderSpec::new(Method::Copy));
+        }
+        let num_coders = coders.len();
+        Self {
+            coders,
+            bind_pairs: (1..num_coders).map(|i| (i - 1, i)).collect(),
+            packed_streams: vec![num_coders - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::{Lzma2Props, Sz};
+
+    #[test]
+    fn build_chain() {
+        let props = Lzma2Props { dict_size: 1 << 24 }.to_bytes();
+        let layout = FolderBuilder::new()
+            .filter(Method::Sz(Sz::Bcj))
+            .coder(Method::Lzma2, &props)
+            .build()
+            .unwrap();
+        assert_eq!(layout.bind_pairs(), [(0, 1)]);
+        assert_eq!(layout.packed_streams(), [1]);
+        assert!(layout.is_chain());
+        assert_eq!(layout.decoding_order(), [1, 0]);
+        assert_eq!(layout.methods().to_string(), "LZMA2:24 BCJ");
+        assert_eq!(
+            FolderLayout::from("LZMA2:24 BCJ".parse::<MethodChain>().unwrap()),
+            layout
+        );
+        assert_eq!(
+            FolderLayout::from(MethodChain::default()),
+            FolderLayout::from(Method::Copy)
+        );
+    }
+
+    #[test]
+    fn build_graph() {
+        // The layout of BCJ2 which 7-Zip uses.
+        let layout = FolderBuilder::new()
+            .add_coder(CoderSpec::with_streams(Method::Sz(Sz::Bcj2), 4, 1))
+            .filter(Method::Lzma2)
+            .filter(Method::Sz(Sz::Lzma))
+            .filter(Method::Sz(Sz::Lzma))
+            .bind_pair(0, 1)
+            .bind_pair(1, 2)
+            .bind_pair(2, 3)
+            .packed_stream(6)
+            .packed_stream(5)
+            .packed_stream(4)
+            .packed_stream(3)
+            .build()
+            .unwrap();
+        assert!(!layout.is_chain());
+        assert_eq!(layout.decoding_order(), [1, 2, 3, 0]);
+        assert_eq!(layout.methods().to_string(), "LZMA2 LZMA LZMA BCJ2");
+    }
+
+    #[test]
+    fn build_invalid_graph() {
+        let chain = || {
+            FolderBuilder::new()
+                .filter(Method::Delta)
+                .filter(Method::Lzma2)
+        };
+        assert!(matches!(
+            FolderBuilder::new().build().unwrap_err(),
+            BuildFolderError::Empty
+        ));
+        assert!(matches!(
+            chain()
+                .bind_pair(0, 2)
+                .packed_stream(1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::BindPair(0, 2)
+        ));
+        assert!(matches!(
+            chain().bind_pair(0, 1).build().unwrap_err(),
+            BuildFolderError::InStream(1)
+        ));
+        assert!(matches!(
+            chain()
+                .bind_pair(0, 1)
+                .packed_stream(0)
+                .packed_stream(1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::InStream(0)
+        ));
+        assert!(matches!(
+            chain()
+                .packed_stream(0)
+                .packed_stream(1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::MainStream
+        ));
+        assert!(matches!(
+            chain().bind_pair(0, 1).bind_pair(1, 1).build().unwrap_err(),
+            BuildFolderError::OutStream(1)
+        ));
+        assert!(matches!(
+            chain()
+                .filter(Method::Copy)
+                .bind_pair(0, 1)
+                .bind_pair(1, 2)
+                .bind_pair(2, 1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::OutStream(1)
+        ));
+        assert!(matches!(
+            FolderBuilder::new()
+                .add_coder(CoderSpec::with_streams(Method::Copy, 1, 2))
+                .bind_pair(0, 1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::Cycle
+        ));
+        assert!(matches!(
+            chain()
+                .filter(Method::Copy)
+                .bind_pair(1, 2)
+                .bind_pair(2, 1)
+                .packed_stream(0)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::UnusedCoder(1)
+        ));
+        assert!(matches!(
+            FolderBuilder::new()
+                .add_coder(CoderSpec::with_streams(Method::Copy, 1, 0))
+                .filter(Method::Copy)
+                .packed_stream(0)
+                .packed_stream(1)
+                .build()
+                .unwrap_err(),
+            BuildFolderError::UnusedCoder(0)
+        ));
+    }
+}
@@ -0,0 +1,1349 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! 7z headers.
+
+use std::io::{self, Read, Write};
+
+use crate::{
+    attributes::WinAttributes,
+    error::{InvalidArchive, UnsupportedArchive},
+    property::{Method, Property, SIGNATURE},
+    Entry, FileTime, Result,
+};
+
+/// The size of the signature header.
+pub const SIGNATURE_HEADER_SIZE: u64 = 32;
+
+/// Extension methods for reading the primitive types of the 7z format.
+pub trait ReadExt: Read {
+    /// Reads a `BYTE`.
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [u8::default(); 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Reads a `UINT32`.
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [u8::default(); 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Reads a `REAL_UINT64`.
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [u8::default(); 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// Reads a `UINT64`.
+    fn read_number(&mut self) -> io::Result<u64> {
+        let first = self.read_u8()?;
+        let mut mask = 0x80;
+        let mut value = u64::default();
+        for i in 0..8 {
+            if first & mask == 0 {
+                let high = u64::from(first & (mask - 1));
+                return Ok(value | (high << (8 * i)));
+            }
+            value |= u64::from(self.read_u8()?) << (8 * i);
+            mask >>= 1;
+        }
+        Ok(value)
+    }
+
+    /// Reads `len` bits.
+    fn read_bits(&mut self, len: usize) -> io::Result<Vec<bool>> {
+        let mut bits = Vec::with_capacity(len);
+        let mut byte = u8::default();
+        for i in 0..len {
+            if i % 8 == 0 {
+                byte = self.read_u8()?;
+            }
+            bits.push(byte & (0x80 >> (i % 8)) != 0);
+        }
+        Ok(bits)
+    }
+
+    /// Reads `len` bits preceded by the `AllAreDefined` flag.
+    fn read_defined(&mut self, len: usize) -> io::Result<Vec<bool>> {
+        if self.read_u8()? == 0 {
+            self.read_bits(len)
+        } else {
+            Ok(vec![true; len])
+        }
+    }
+
+    /// Reads the digests of `len` streams.
+    fn read_digests(&mut self, len: usize) -> io::Result<Vec<Option<u32>>> {
+        self.read_defined(len)?
+            .into_iter()
+            .map(|defined| defined.then(|| self.read_u32()).transpose())
+            .collect()
+    }
+}
+
+impl<R: Read + ?Sized> ReadExt for R {}
+
+/// Extension methods for writing the primitive types of the 7z format.
+pub trait WriteExt: Write {
+    /// Writes a `BYTE`.
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    /// Writes a `UINT32`.
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    /// Writes a `REAL_UINT64`.
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    /// Writes a `UINT64`.
+    fn write_number(&mut self, value: u64) -> io::Result<()> {
+        let mut first = u8::default();
+        let mut mask = 0x80;
+        let mut len = 0;
+        while len < 8 {
+            if value < (1 << (7 * (len + 1))) {
+                first |= u8::try_from(value >> (8 * len)).unwrap_or_default();
+                break;
+            }
+            first |= mask;
+            mask >>= 1;
+            len += 1;
+        }
+        self.write_u8(first)?;
+        self.write_all(&value.to_le_bytes()[..len])
+    }
+
+    /// Writes the bits.
+    fn write_bits(&mut self, bits: &[bool]) -> io::Result<()> {
+        for chunk in bits.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, &bit)| bit)
+                .fold(u8::default(), |byte, (i, _)| byte | (0x80 >> i));
+            self.write_u8(byte)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the bits preceded by the `AllAreDefined` flag.
+    fn write_defined(&mut self, bits: &[bool]) -> io::Result<()> {
+        if bits.iter().all(|&bit| bit) {
+            self.write_u8(1)
+        } else {
+            self.write_u8(0)?;
+            self.write_bits(bits)
+        }
+    }
+
+    /// Writes the digests of the streams.
+    fn write_digests(&mut self, digests: &[Option<u32>]) -> io::Result<()> {
+        let defined = digests.iter().map(Option::is_some).collect::<Vec<_>>();
+        self.write_defined(&defined)?;
+        digests
+            .iter()
+            .flatten()
+            .try_for_each(|&digest| self.write_u32(digest))
+    }
+}
+
+impl<W: Write + ?Sized> WriteExt for W {}
+
+/// Writes a property of `FilesInfo` with its size.
+fn write_file_property(writer: &mut Vec<u8>, id: Property, data: &[u8]) {
+    writer.push(id.into());
+    // Writing into a `Vec` never fails.
+    let _ = writer.write_number(data.len() as u64);
+    writer.extend_from_slice(data);
+}
+
+/// Converts a number read from the header to `usize`, checking that it does
+/// not exceed `max`.
+fn to_count(n: u64, max: usize) -> Result<usize> {
+    match usize::try_from(n) {
+        Ok(n) if n <= max => Ok(n),
+        _ => Err(InvalidArchive::Header.into()),
+    }
+}
+
+/// Reads the next property ID and checks that it is the expected one.
+fn expect_property(reader: &mut &[u8], pos: Property, expected: Property) -> Result<()> {
+    match reader.read_u8()? {
+        id if id == u8::from(expected) => Ok(()),
+        id => Err(InvalidArchive::UnexpectedProperty { pos, id }.into()),
+    }
+}
+
+/// Checks that `id` is the end property.
+fn expect_end(id: u8, pos: Property) -> Result<()> {
+    if id == u8::from(Property::End) {
+        Ok(())
+    } else {
+        Err(InvalidArchive::EndProperty { pos, id }.into())
+    }
+}
+
+/// Skips the data of a property.
+fn skip_data(reader: &mut &[u8]) -> Result<()> {
+    let size = to_count(reader.read_number()?, reader.len())?;
+    *reader = &reader[size..];
+    Ok(())
+}
+
+/// Represents the start header.
+#[derive(Debug)]
+pub struct StartHeader {
+    pub offset: u64,
+    pub size: u64,
+    pub crc: u32,
+}
+
+impl StartHeader {
+    /// Reads the signature header and returns the start header.
+    ///
+    /// The fields of the start header describe the next header.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut signature = [u8::default(); 6];
+        reader.read_exact(&mut signature)?;
+        if signature != SIGNATURE {
+            return Err(InvalidArchive::Signature(signature).into());
+        }
+        let (major, minor) = (reader.read_u8()?, reader.read_u8()?);
+        if major != 0 {
+            return Err(UnsupportedArchive::Version { major, minor }.into());
+        }
+        let start_header_crc = reader.read_u32()?;
+        let mut buf = [u8::default(); 20];
+        reader.read_exact(&mut buf)?;
+        if crc32fast::hash(&buf) != start_header_crc {
+            return Err(InvalidArchive::StartHeaderCrc.into());
+        }
+        let mut buf = &buf[..];
+        Ok(Self {
+            offset: buf.read_u64()?,
+            size: buf.read_u64()?,
+            crc: buf.read_u32()?,
+        })
+    }
+}
+
+impl StartHeader {
+    /// Writes the signature header with this start header.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut buf = Vec::with_capacity(20);
+        buf.write_u64(self.offset)?;
+        buf.write_u64(self.size)?;
+        buf.write_u32(self.crc)?;
+        writer.write_all(&SIGNATURE)?;
+        writer.write_all(&[0, 4])?;
+        writer.write_u32(crc32fast::hash(&buf))?;
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// Represents a coder in a folder.
+#[derive(Debug)]
+pub struct Coder {
+    pub method: Method,
+    pub num_in_streams: usize,
+    pub num_out_streams: usize,
+    pub properties: Vec<u8>,
+}
+
+/// Represents a bind pair in a folder.
+#[derive(Debug)]
+pub struct BindPair {
+    pub in_index: usize,
+    pub out_index: usize,
+}
+
+/// Represents a folder.
+#[derive(Debug)]
+pub struct Folder {
+    pub coders: Vec<Coder>,
+    pub bind_pairs: Vec<BindPair>,
+    pub packed_streams: Vec<usize>,
+    pub unpack_sizes: Vec<u64>,
+    pub crc: Option<u32>,
+}
+
+impl Folder {
+    /// Reads a folder.
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let num_coders = to_count(reader.read_number()?, reader.len())?;
+        if num_coders == 0 {
+            return Err(InvalidArchive::Folder.into());
+        }
+        let mut coders = Vec::with_capacity(num_coders);
+        for _ in 0..num_coders {
+            let flags = reader.read_u8()?;
+            if flags & 0x80 != 0 {
+                return Err(UnsupportedArchive::CompressionMethod(
+                    "alternative methods".to_string(),
+                )
+                .into());
+            }
+            let id_size = usize::from(flags & 0x0f);
+            if reader.len() < id_size {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let (id, rest) = reader.split_at(id_size);
+            *reader = rest;
+            let method = Method::from_id(id);
+            let (num_in_streams, num_out_streams) = if flags & 0x10 == 0 {
+                (1, 1)
+            } else {
+                (
+                    to_count(reader.read_number()?, reader.len())?,
+                    to_count(reader.read_number()?, reader.len())?,
+                )
+            };
+            let properties = if flags & 0x20 == 0 {
+                Vec::new()
+            } else {
+                let size = to_count(reader.read_number()?, reader.len())?;
+                let (properties, rest) = reader.split_at(size);
+                *reader = rest;
+                properties.to_vec()
+            };
+            coders.push(Coder {
+                method,
+                num_in_streams,
+                num_out_streams,
+                properties,
+            });
+        }
+
+        let num_in_streams_total = coders.iter().map(|c| c.num_in_streams).sum::<usize>();
+        let num_out_streams_total = coders.iter().map(|c| c.num_out_streams).sum::<usize>();
+        if num_out_streams_total == 0 || num_in_streams_total < num_out_streams_total - 1 {
+            return Err(InvalidArchive::Folder.into());
+        }
+        let mut bind_pairs = Vec::with_capacity(num_out_streams_total - 1);
+        for _ in 0..num_out_streams_total - 1 {
+            let in_index = to_count(reader.read_number()?, num_in_streams_total - 1)?;
+            let out_index = to_count(reader.read_number()?, num_out_streams_total - 1)?;
+            bind_pairs.push(BindPair {
+                in_index,
+                out_index,
+            });
+        }
+        let num_packed_streams = num_in_streams_total - bind_pairs.len();
+        let packed_streams = if num_packed_streams == 1 {
+            (0..num_in_streams_total)
+                .filter(|&i| bind_pairs.iter().all(|bp| bp.in_index != i))
+                .take(1)
+                .collect()
+        } else {
+            (0..num_packed_streams)
+                .map(|_| to_count(reader.read_number()?, num_in_streams_total - 1))
+                .collect::<Result<_>>()?
+        };
+
+        let folder = Self {
+            coders,
+            bind_pairs,
+            packed_streams,
+            unpack_sizes: Vec::new(),
+            crc: None,
+        };
+        folder.validate()?;
+        Ok(folder)
+    }
+
+    /// Writes this folder.
+    fn write(&self, writer: &mut Vec<u8>) -> Result<()> {
+        writer.write_number(self.coders.len() as u64)?;
+        for coder in &self.coders {
+            let id = coder.method.id();
+            let is_complex = coder.num_in_streams != 1 || coder.num_out_streams != 1;
+            let mut flags = u8::try_from(id.len())
+                .ok()
+                .filter(|&len| len <= 0x0f)
+                .ok_or(InvalidArchive::Folder)?;
+            if is_complex {
+                flags |= 0x10;
+            }
+            if !coder.properties.is_empty() {
+                flags |= 0x20;
+            }
+            writer.write_u8(flags)?;
+            writer.write_all(id)?;
+            if is_complex {
+                writer.write_number(coder.num_in_streams as u64)?;
+                writer.write_number(coder.num_out_streams as u64)?;
+            }
+            if !coder.properties.is_empty() {
+                writer.write_number(coder.properties.len() as u64)?;
+                writer.write_all(&coder.properties)?;
+            }
+        }
+        for bp in &self.bind_pairs {
+            writer.write_number(bp.in_index as u64)?;
+            writer.write_number(bp.out_index as u64)?;
+        }
+        if self.packed_streams.len() > 1 {
+            for &i in &self.packed_streams {
+                writer.write_number(i as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the coders of this folder form a tree.
+    fn validate(&self) -> Result<()> {
+        let num_in_streams_total = self.coders.iter().map(|c| c.num_in_streams).sum();
+        let num_out_streams_total = self.coders.iter().map(|c| c.num_out_streams).sum();
+        let mut in_bound = vec![false; num_in_streams_total];
+        let mut out_bound = vec![false; num_out_streams_total];
+        for bp in &self.bind_pairs {
+            if in_bound[bp.in_index] || out_bound[bp.out_index] {
+                return Err(InvalidArchive::Folder.into());
+            }
+            in_bound[bp.in_index] = true;
+            out_bound[bp.out_index] = true;
+        }
+        for &i in &self.packed_streams {
+            if in_bound[i] {
+                return Err(InvalidArchive::Folder.into());
+            }
+            in_bound[i] = true;
+        }
+        if in_bound.contains(&false) || self.packed_streams.len() != self.num_packed_streams() {
+            return Err(InvalidArchive::Folder.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of the packed streams of this folder.
+    fn num_packed_streams(&self) -> usize {
+        self.coders.iter().map(|c| c.num_in_streams).sum::<usize>() - self.bind_pairs.len()
+    }
+
+    /// Returns the index of the first in stream of the coder.
+    pub fn first_in_stream(&self, coder: usize) -> usize {
+        self.coders[..coder].iter().map(|c| c.num_in_streams).sum()
+    }
+
+    /// Returns the index of the first out stream of the coder.
+    pub fn first_out_stream(&self, coder: usize) -> usize {
+        self.coders[..coder].iter().map(|c| c.num_out_streams).sum()
+    }
+
+    /// Returns the index of the coder which has the out stream.
+    pub fn coder_of_out_stream(&self, out_index: usize) -> Option<usize> {
+        let mut first = 0;
+        for (i, coder) in self.coders.iter().enumerate() {
+            if out_index < first + coder.num_out_streams {
+                return Some(i);
+            }
+            first += coder.num_out_streams;
+        }
+        None
+    }
+
+    /// Returns the index of the coder which has the in stream.
+    pub fn coder_of_in_stream(&self, in_index: usize) -> Option<usize> {
+        let mut first = 0;
+        for (i, coder) in self.coders.iter().enumerate() {
+            if in_index < first + coder.num_in_streams {
+                return Some(i);
+            }
+            first += coder.num_in_streams;
+        }
+        None
+    }
+
+    /// Returns the index of the out stream which is not bound to any coder.
+    pub fn main_out_stream(&self) -> Option<usize> {
+        (0..self.unpack_sizes.len()).find(|&i| self.bind_pairs.iter().all(|bp| bp.out_index != i))
+    }
+
+    /// Returns the indices of the coders in the order of decoding, from the
+    /// ones which read the packed streams to the one of the main out stream.
+    pub fn decoding_order(&self) -> Vec<usize> {
+        fn visit(folder: &Folder, coder: usize, depth: usize, order: &mut Vec<usize>) {
+            if depth > folder.coders.len() || order.contains(&coder) {
+                return;
+            }
+            let first_in_stream = folder.first_in_stream(coder);
+            for in_index in first_in_stream..first_in_stream + folder.coders[coder].num_in_streams {
+                if let Some(input) = folder
+                    .bind_pairs
+                    .iter()
+                    .find(|bp| bp.in_index == in_index)
+                    .and_then(|bp| folder.coder_of_out_stream(bp.out_index))
+                {
+                    visit(folder, input, depth + 1, order);
+                }
+            }
+            if !order.contains(&coder) {
+                order.push(coder);
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.coders.len());
+        if let Some(main) = self
+            .main_out_stream()
+            .and_then(|i| self.coder_of_out_stream(i))
+        {
+            visit(self, main, 0, &mut order);
+        }
+        order
+    }
+
+    /// Returns the size of the unpacked data of this folder.
+    pub fn unpack_size(&self) -> u64 {
+        self.main_out_stream()
+            .map_or_else(u64::default, |i| self.unpack_sizes[i])
+    }
+
+    /// Returns `true` if any coder of this folder uses the method.
+    pub fn uses(&self, method: &Method) -> bool {
+        self.coders.iter().any(|c| &c.method == method)
+    }
+}
+
+/// Represents the information about the packed streams.
+#[derive(Debug, Default)]
+pub struct PackInfo {
+    pub pack_pos: u64,
+    pub pack_sizes: Vec<u64>,
+    pub crcs: Vec<Option<u32>>,
+}
+
+impl PackInfo {
+    /// Reads `PackInfo`.
+    fn read(reader: &mut &[u8]) -> Result<Self> {
+        let pack_pos = reader.read_number()?;
+        let num_pack_streams = to_count(reader.read_number()?, reader.len())?;
+        let mut pack_info = Self {
+            pack_pos,
+            pack_sizes: Vec::new(),
+            crcs: vec![None; num_pack_streams],
+        };
+        loop {
+            let id = reader.read_u8()?;
+            match Property::by_id(id) {
+                Some(Property::End) => break,
+                Some(Property::Size) => {
+                    pack_info.pack_sizes = (0..num_pack_streams)
+                        .map(|_| reader.read_number())
+                        .collect::<io::Result<_>>()?;
+                }
+                Some(Property::Crc) => pack_info.crcs = reader.read_digests(num_pack_streams)?,
+                _ => skip_data(reader)?,
+            }
+        }
+        if pack_info.pack_sizes.len() != num_pack_streams {
+            return Err(InvalidArchive::Header.into());
+        }
+        Ok(pack_info)
+    }
+}
+
+impl PackInfo {
+    /// Writes `PackInfo`.
+    fn write(&self, writer: &mut Vec<u8>) -> Result<()> {
+        writer.write_number(self.pack_pos)?;
+        writer.write_number(self.pack_sizes.len() as u64)?;
+        writer.write_u8(Property::Size.into())?;
+        for &size in &self.pack_sizes {
+            writer.write_number(size)?;
+        }
+        if self.crcs.iter().any(Option::is_some) {
+            writer.write_u8(Property::Crc.into())?;
+            writer.write_digests(&self.crcs)?;
+        }
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+}
+
+/// Represents the information about the unpacked streams in folders.
+#[derive(Debug, Default)]
+pub struct SubStreamsInfo {
+    pub num_unpack_streams: Vec<usize>,
+    pub unpack_sizes: Vec<u64>,
+    pub crcs: Vec<Option<u32>>,
+}
+
+impl SubStreamsInfo {
+    /// Returns `SubStreamsInfo` in which each folder has one stream.
+    fn single(folders: &[Folder]) -> Self {
+        Self {
+            num_unpack_streams: vec![1; folders.len()],
+            unpack_sizes: folders.iter().map(Folder::unpack_size).collect(),
+            crcs: folders.iter().map(|f| f.crc).collect(),
+        }
+    }
+
+    /// Reads `SubStreamsInfo`.
+    fn read(reader: &mut &[u8], folders: &[Folder]) -> Result<Self> {
+        let mut num_unpack_streams = vec![1; folders.len()];
+        let mut id = reader.read_u8()?;
+        if id == u8::from(Property::NumUnpackStream) {
+            for n in &mut num_unpack_streams {
+                *n = to_count(reader.read_number()?, reader.len())?;
+            }
+            id = reader.read_u8()?;
+        }
+
+        let mut unpack_sizes = Vec::new();
+        for (folder, &n) in folders.iter().zip(&num_unpack_streams) {
+            if n == 0 {
+                continue;
+            }
+            let mut sum = u64::default();
+            if id == u8::from(Property::Size) {
+                for _ in 1..n {
+                    let size = reader.read_number()?;
+                    sum = sum.checked_add(size).ok_or(InvalidArchive::Header)?;
+                    unpack_sizes.push(size);
+                }
+            } else if n > 1 {
+                return Err(InvalidArchive::Header.into());
+            }
+            let last = folder
+                .unpack_size()
+                .checked_sub(sum)
+                .ok_or(InvalidArchive::Header)?;
+            unpack_sizes.push(last);
+        }
+        if id == u8::from(Property::Size) {
+            id = reader.read_u8()?;
+        }
+
+        let num_unknown_crcs = folders
+            .iter()
+            .zip(&num_unpack_streams)
+            .map(|(f, &n)| if n == 1 && f.crc.is_some() { 0 } else { n })
+            .sum();
+        let mut unknown_crcs = vec![None; num_unknown_crcs];
+        loop {
+            match Property::by_id(id) {
+                Some(Property::End) => break,
+                Some(Property::Crc) => unknown_crcs = reader.read_digests(num_unknown_crcs)?,
+                _ => skip_data(reader)?,
+            }
+            id = reader.read_u8()?;
+        }
+        let mut unknown_crcs = unknown_crcs.into_iter();
+        let mut crcs = Vec::with_capacity(unpack_sizes.len());
+        for (folder, &n) in folders.iter().zip(&num_unpack_streams) {
+            if n == 1 && folder.crc.is_some() {
+                crcs.push(folder.crc);
+            } else {
+                crcs.extend(unknown_crcs.by_ref().take(n));
+            }
+        }
+
+        Ok(Self {
+            num_unpack_streams,
+            unpack_sizes,
+            crcs,
+        })
+    }
+}
+
+impl SubStreamsInfo {
+    /// Writes `SubStreamsInfo`.
+    fn write(&self, writer: &mut Vec<u8>, folders: &[Folder]) -> Result<()> {
+        if self.num_unpack_streams.iter().any(|&n| n != 1) {
+            writer.write_u8(Property::NumUnpackStream.into())?;
+            for &n in &self.num_unpack_streams {
+                writer.write_number(n as u64)?;
+            }
+        }
+
+        if self.num_unpack_streams.iter().any(|&n| n > 1) {
+            writer.write_u8(Property::Size.into())?;
+            let mut sizes = self.unpack_sizes.iter();
+            for &n in &self.num_unpack_streams {
+                // The size of the last stream is implied by the folder.
+                for (i, &size) in sizes.by_ref().take(n).enumerate() {
+                    if i + 1 < n {
+                        writer.write_number(size)?;
+                    }
+                }
+            }
+        }
+
+        let mut crcs = self.crcs.iter();
+        let mut unknown_crcs = Vec::new();
+        for (folder, &n) in folders.iter().zip(&self.num_unpack_streams) {
+            if n == 1 && folder.crc.is_some() {
+                crcs.next();
+            } else {
+                unknown_crcs.extend(crcs.by_ref().take(n));
+            }
+        }
+        if unknown_crcs.iter().any(Option::is_some) {
+            writer.write_u8(Property::Crc.into())?;
+            writer.write_digests(&unknown_crcs)?;
+        }
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+}
+
+/// Represents the information about streams.
+#[derive(Debug, Default)]
+pub struct StreamsInfo {
+    pub pack_info: PackInfo,
+    pub folders: Vec<Folder>,
+    pub substreams: SubStreamsInfo,
+}
+
+impl StreamsInfo {
+    /// Reads `StreamsInfo`.
+    pub fn read(reader: &mut &[u8], pos: Property) -> Result<Self> {
+        let mut streams_info = Self::default();
+        let mut id = reader.read_u8()?;
+        if id == u8::from(Property::PackInfo) {
+            streams_info.pack_info = PackInfo::read(reader)?;
+            id = reader.read_u8()?;
+        }
+        if id == u8::from(Property::UnpackInfo) {
+            streams_info.folders = Self::read_unpack_info(reader)?;
+            id = reader.read_u8()?;
+        }
+        if id == u8::from(Property::SubStreamsInfo) {
+            streams_info.substreams = SubStreamsInfo::read(reader, &streams_info.folders)?;
+            id = reader.read_u8()?;
+        } else {
+            streams_info.substreams = SubStreamsInfo::single(&streams_info.folders);
+        }
+        expect_end(id, pos)?;
+
+        let num_packed_streams = streams_info
+            .folders
+            .iter()
+            .map(Folder::num_packed_streams)
+            .sum::<usize>();
+        if num_packed_streams > streams_info.pack_info.pack_sizes.len() {
+            return Err(InvalidArchive::Header.into());
+        }
+        Ok(streams_info)
+    }
+
+    /// Writes `StreamsInfo`.
+    pub fn write(&self, writer: &mut Vec<u8>) -> Result<()> {
+        if !self.pack_info.pack_sizes.is_empty() {
+            writer.write_u8(Property::PackInfo.into())?;
+            self.pack_info.write(writer)?;
+        }
+        if !self.folders.is_empty() {
+            writer.write_u8(Property::UnpackInfo.into())?;
+            self.write_unpack_info(writer)?;
+            writer.write_u8(Property::SubStreamsInfo.into())?;
+            self.substreams.write(writer, &self.folders)?;
+        }
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+
+    /// Writes `CodersInfo`.
+    fn write_unpack_info(&self, writer: &mut Vec<u8>) -> Result<()> {
+        writer.write_u8(Property::Folder.into())?;
+        writer.write_number(self.folders.len() as u64)?;
+        writer.write_u8(0)?;
+        for folder in &self.folders {
+            folder.write(writer)?;
+        }
+
+        writer.write_u8(Property::CodersUnpackSize.into())?;
+        for &size in self.folders.iter().flat_map(|f| &f.unpack_sizes) {
+            writer.write_number(size)?;
+        }
+
+        if self.folders.iter().any(|f| f.crc.is_some()) {
+            writer.write_u8(Property::Crc.into())?;
+            let crcs = self.folders.iter().map(|f| f.crc).collect::<Vec<_>>();
+            writer.write_digests(&crcs)?;
+        }
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+
+    /// Reads `CodersInfo`.
+    fn read_unpack_info(reader: &mut &[u8]) -> Result<Vec<Folder>> {
+        expect_property(reader, Property::UnpackInfo, Property::Folder)?;
+        let num_folders = to_count(reader.read_number()?, reader.len())?;
+        if reader.read_u8()? != 0 {
+            return Err(UnsupportedArchive::ExternalData.into());
+        }
+        let mut folders = (0..num_folders)
+            .map(|_| Folder::read(reader))
+            .collect::<Result<Vec<_>>>()?;
+
+        expect_property(reader, Property::UnpackInfo, Property::CodersUnpackSize)?;
+        for folder in &mut folders {
+            let num_out_streams = folder.coders.iter().map(|c| c.num_out_streams).sum();
+            folder.unpack_sizes = (0..num_out_streams)
+                .map(|_| reader.read_number())
+                .collect::<io::Result<_>>()?;
+        }
+
+        loop {
+            let id = reader.read_u8()?;
+            match Property::by_id(id) {
+                Some(Property::End) => break,
+                Some(Property::Crc) => {
+                    let crcs = reader.read_digests(folders.len())?;
+                    for (folder, crc) in folders.iter_mut().zip(crcs) {
+                        folder.crc = crc;
+                    }
+                }
+                _ => skip_data(reader)?,
+            }
+        }
+        Ok(folders)
+    }
+
+    /// Returns the offsets of the packed streams from the end of the
+    /// signature header.
+    pub fn pack_stream_offsets(&self) -> Vec<u64> {
+        self.pack_info
+            .pack_sizes
+            .iter()
+            .scan(self.pack_info.pack_pos, |offset, size| {
+                let current = *offset;
+                *offset = offset.saturating_add(*size);
+                Some(current)
+            })
+            .collect()
+    }
+
+    /// Returns the index of the first packed stream of each folder.
+    pub fn first_pack_streams(&self) -> Vec<usize> {
+        self.folders
+            .iter()
+            .scan(0, |first, folder| {
+                let current = *first;
+                *first += folder.num_packed_streams();
+                Some(current)
+            })
+            .collect()
+    }
+}
+
+/// Represents the header.
+#[derive(Debug, Default)]
+pub struct Header {
+    pub main_streams_info: StreamsInfo,
+    pub entries: Vec<Entry>,
+}
+
+impl Header {
+    /// Reads `Header`.
+    ///
+    /// The `kHeader` ID is assumed to have been read.
+    pub fn read(reader: &mut &[u8]) -> Result<Self> {
+        let mut header = Self::default();
+        let mut id = reader.read_u8()?;
+        if id == u8::from(Property::ArchiveProperties) {
+            loop {
+                if reader.read_u8()? == 0 {
+                    break;
+                }
+                skip_data(reader)?;
+            }
+            id = reader.read_u8()?;
+        }
+        if id == u8::from(Property::AdditionalStreamsInfo) {
+            StreamsInfo::read(reader, Property::AdditionalStreamsInfo)?;
+            id = reader.read_u8()?;
+        }
+        if id == u8::from(Property::MainStreamsInfo) {
+            header.main_streams_info = StreamsInfo::read(reader, Property::MainStreamsInfo)?;
+            id = reader.read_u8()?;
+        }
+        if id == u8::from(Property::FilesInfo) {
+            header.entries = Self::read_files_info(reader, &header.main_streams_info)?;
+            id = reader.read_u8()?;
+        }
+        expect_end(id, Property::Header)?;
+        Ok(header)
+    }
+
+    /// Writes `Header`, including the `kHeader` ID.
+    pub fn write(&self, writer: &mut Vec<u8>) -> Result<()> {
+        writer.write_u8(Property::Header.into())?;
+        if !self.main_streams_info.folders.is_empty() {
+            writer.write_u8(Property::MainStreamsInfo.into())?;
+            self.main_streams_info.write(writer)?;
+        }
+        if !self.entries.is_empty() {
+            writer.write_u8(Property::FilesInfo.into())?;
+            self.write_files_info(writer)?;
+        }
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+
+    /// Writes `FilesInfo`.
+    fn write_files_info(&self, writer: &mut Vec<u8>) -> Result<()> {
+        let entries = &self.entries;
+        writer.write_number(entries.len() as u64)?;
+
+        let empty_streams = entries.iter().map(|e| !e.has_stream).collect::<Vec<_>>();
+        if empty_streams.contains(&true) {
+            let mut data = Vec::new();
+            data.write_bits(&empty_streams)?;
+            write_file_property(writer, Property::EmptyStream, &data);
+
+            let empty_entries = entries.iter().filter(|e| !e.has_stream);
+            let empty_files = empty_entries.clone().map(|e| !e.is_dir).collect::<Vec<_>>();
+            if empty_files.contains(&true) {
+                let mut data = Vec::new();
+                data.write_bits(&empty_files)?;
+                write_file_property(writer, Property::EmptyFile, &data);
+            }
+            let antis = empty_entries.map(|e| e.is_anti).collect::<Vec<_>>();
+            if antis.contains(&true) {
+                let mut data = Vec::new();
+                data.write_bits(&antis)?;
+                write_file_property(writer, Property::Anti, &data);
+            }
+        }
+
+        let mut data = vec![0];
+        for entry in entries {
+            for unit in entry.name.encode_utf16().chain([0]) {
+                data.extend(unit.to_le_bytes());
+            }
+        }
+        write_file_property(writer, Property::Name, &data);
+
+        for (id, times) in [
+            (
+                Property::CTime,
+                entries.iter().map(|e| e.ctime).collect::<Vec<_>>(),
+            ),
+            (Property::ATime, entries.iter().map(|e| e.atime).collect()),
+            (Property::MTime, entries.iter().map(|e| e.mtime).collect()),
+        ] {
+            if times.iter().any(Option::is_some) {
+                let mut data = Vec::new();
+                data.write_defined(&times.iter().map(Option::is_some).collect::<Vec<_>>())?;
+                data.write_u8(0)?;
+                for &time in times.iter().flatten() {
+                    data.write_u64(time.into())?;
+                }
+                write_file_property(writer, id, &data);
+            }
+        }
+
+        let attributes = entries.iter().map(|e| e.attributes).collect::<Vec<_>>();
+        if attributes.iter().any(Option::is_some) {
+            let mut data = Vec::new();
+            data.write_defined(&attributes.iter().map(Option::is_some).collect::<Vec<_>>())?;
+            data.write_u8(0)?;
+            for &attributes in attributes.iter().flatten() {
+                data.write_u32(attributes.bits())?;
+            }
+            write_file_property(writer, Property::WinAttributes, &data);
+        }
+
+        writer.write_u8(Property::End.into())?;
+        Ok(())
+    }
+
+    /// Reads `FilesInfo`.
+    fn read_files_info(reader: &mut &[u8], streams_info: &StreamsInfo) -> Result<Vec<Entry>> {
+        let num_files = to_count(reader.read_number()?, reader.len())?;
+        let mut entries = vec![Entry::default(); num_files];
+        let mut empty_streams = vec![false; num_files];
+        let mut empty_files = Vec::new();
+        let mut antis = Vec::new();
+        loop {
+            let id = reader.read_u8()?;
+            if id == u8::from(Property::End) {
+                break;
+            }
+            let size = to_count(reader.read_number()?, reader.len())?;
+            let (mut data, rest) = reader.split_at(size);
+            *reader = rest;
+            match Property::by_id(id) {
+                Some(Property::EmptyStream) => empty_streams = data.read_bits(num_files)?,
+                Some(Property::EmptyFile) => {
+                    let num_empty_streams = empty_streams.iter().filter(|&&e| e).count();
+                    empty_files = data.read_bits(num_empty_streams)?;
+                }
+                Some(Property::Anti) => {
+                    let num_empty_streams = empty_streams.iter().filter(|&&e| e).count();
+                    antis = data.read_bits(num_empty_streams)?;
+                }
+                Some(Property::Name) => {
+                    if data.read_u8()? != 0 {
+                        return Err(UnsupportedArchive::ExternalData.into());
+                    }
+                    Self::read_names(data, &mut entries)?;
+                }
+                Some(pos @ (Property::CTime | Property::ATime | Property::MTime)) => {
+                    let defined = data.read_defined(num_files)?;
+                    if data.read_u8()? != 0 {
+                        return Err(UnsupportedArchive::ExternalData.into());
+                    }
+                    for (entry, defined) in entries.iter_mut().zip(defined) {
+                        let time = defined
+                            .then(|| data.read_u64().map(FileTime::from))
+                            .transpose()?;
+                        match pos {
+                            Property::CTime => entry.ctime = time,
+                            Property::ATime => entry.atime = time,
+                            _ => entry.mtime = time,
+                        }
+                    }
+                }
+                Some(Property::WinAttributes) => {
+                    let defined = data.read_defined(num_files)?;
+                    if data.read_u8()? != 0 {
+                        return Err(UnsupportedArchive::ExternalData.into());
+                    }
+                    for (entry, defined) in entries.iter_mut().zip(defined) {
+                        entry.attributes = defined
+                            .then(|| data.read_u32().map(WinAttributes::from_bits))
+                            .transpose()?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut empty_index = 0;
+        let mut sizes = streams_info.substreams.unpack_sizes.iter();
+        let mut crcs = streams_info.substreams.crcs.iter();
+        let mut folders = streams_info
+            .substreams
+            .num_unpack_streams
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &n)| (0..n).map(move |j| (i, j == 0)));
+        let mut offset = u64::default();
+        for (entry, empty_stream) in entries.iter_mut().zip(empty_streams) {
+            entry.has_stream = !empty_stream;
+            if empty_stream {
+                entry.is_dir = !empty_files.get(empty_index).copied().unwrap_or_default();
+                entry.is_anti = antis.get(empty_index).copied().unwrap_or_default();
+                empty_index += 1;
+                continue;
+            }
+            let (folder, first) = folders.next().ok_or(InvalidArchive::Header)?;
+            if first {
+                offset = u64::default();
+            }
+            entry.folder = Some(folder);
+            entry.offset = offset;
+            entry.size = *sizes.next().ok_or(InvalidArchive::Header)?;
+            entry.crc = crcs.next().copied().flatten();
+            offset += entry.size;
+        }
+        Ok(entries)
+    }
+
+    /// Reads the names of the entries.
+    fn read_names(data: &[u8], entries: &mut [Entry]) -> Result<()> {
+        if data.len() % 2 != 0 {
+            return Err(InvalidArchive::Header.into());
+        }
+        let units = data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        let mut names = units.split(|&u| u == 0);
+        for entry in entries {
+            let name = names.next().ok_or(InvalidArchive::Header)?;
+            entry.name = String::from_utf16(name).map_err(|_| InvalidArchive::Header)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_number() {
+        assert_eq!((&[0x00][..]).read_number().unwrap(), 0x00);
+        assert_eq!((&[0x7f][..]).read_number().unwrap(), 0x7f);
+        assert_eq!((&[0x80, 0x80][..]).read_number().unwrap(), 0x80);
+        assert_eq!((&[0xbf, 0xff][..]).read_number().unwrap(), 0x3fff);
+        assert_eq!((&[0xc0, 0x00, 0x40][..]).read_number().unwrap(), 0x4000);
+        assert_eq!(
+            (&[0xfe, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07][..])
+                .read_number()
+                .unwrap(),
+            0x0007_0605_0403_0201
+        );
+        assert_eq!(
+            (&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff][..])
+                .read_number()
+                .unwrap(),
+            u64::MAX
+        );
+        assert!((&[0x80][..]).read_number().is_err());
+    }
+
+    #[test]
+    fn read_bits() {
+        assert_eq!(
+            (&[0b1010_0000][..]).read_bits(3).unwrap(),
+            [true, false, true]
+        );
+        assert_eq!((&[0xff, 0x80][..]).read_bits(9).unwrap(), [true; 9]);
+        assert!((&[0xff][..]).read_bits(9).is_err());
+    }
+
+    #[test]
+    fn read_digests() {
+        assert_eq!(
+            (&[0x01, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00][..])
+                .read_digests(2)
+                .unwrap(),
+            [Some(1), Some(2)]
+        );
+        assert_eq!(
+            (&[0x00, 0b0100_0000, 0x02, 0x00, 0x00, 0x00][..])
+                .read_digests(2)
+                .unwrap(),
+            [None, Some(2)]
+        );
+    }
+
+    #[test]
+    fn write_number() {
+        for (value, expected) in [
+            (0x00, &[0x00][..]),
+            (0x7f, &[0x7f]),
+            (0x80, &[0x80, 0x80]),
+            (0x3fff, &[0xbf, 0xff]),
+            (0x4000, &[0xc0, 0x00, 0x40]),
+            (
+                0x0007_0605_0403_0201,
+                &[0xfe, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07],
+            ),
+            (
+                u64::MAX,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ] {
+            let mut buf = Vec::new();
+            buf.write_number(value).unwrap();
+            assert_eq!(buf, expected);
+            assert_eq!((&buf[..]).read_number().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn write_bits_and_digests() {
+        let mut buf = Vec::new();
+        buf.write_bits(&[true, false, true]).unwrap();
+        buf.write_bits(&[true; 9]).unwrap();
+        assert_eq!(buf, [0b1010_0000, 0xff, 0x80]);
+
+        let mut buf = Vec::new();
+        buf.write_digests(&[Some(1), Some(2)]).unwrap();
+        buf.write_digests(&[None, Some(2)]).unwrap();
+        assert_eq!(
+            buf,
+            [
+                0x01,
+                0x01,
+                0x00,
+                0x00,
+                0x00,
+                0x02,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0b0100_0000,
+                0x02,
+                0x00,
+                0x00,
+                0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn write_start_header() {
+        let start_header = StartHeader {
+            offset: 0x10,
+            size: 0x20,
+            crc: 0x1234_5678,
+        };
+        let mut buf = Vec::new();
+        start_header.write(&mut buf).unwrap();
+        assert_eq!(buf.len() as u64, SIGNATURE_HEADER_SIZE);
+        let start_header = StartHeader::read(&mut &buf[..]).unwrap();
+        assert_eq!(start_header.offset, 0x10);
+        assert_eq!(start_header.size, 0x20);
+        assert_eq!(start_header.crc, 0x1234_5678);
+    }
+
+    #[test]
+    fn write_header() {
+        // A solid folder with 2 streams and a folder with a known CRC.
+        let coder = || Coder {
+            method: Method::Copy,
+            num_in_streams: 1,
+            num_out_streams: 1,
+            properties: Vec::new(),
+        };
+        let folder = |size, crc| Folder {
+            coders: vec![coder()],
+            bind_pairs: Vec::new(),
+            packed_streams: vec![0],
+            unpack_sizes: vec![size],
+            crc,
+        };
+        let mut header = Header::default();
+        let streams_info = &mut header.main_streams_info;
+        streams_info.pack_info.pack_sizes = vec![5, 2];
+        streams_info.pack_info.crcs = vec![None; 2];
+        streams_info.folders = vec![folder(5, None), folder(2, Some(7))];
+        streams_info.substreams = SubStreamsInfo {
+            num_unpack_streams: vec![2, 1],
+            unpack_sizes: vec![3, 2, 2],
+            crcs: vec![Some(1), None, Some(7)],
+        };
+        for (name, has_stream, is_dir) in [
+            ("a", true, false),
+            ("dir", false, true),
+            ("b", true, false),
+            ("empty", false, false),
+            ("c", true, false),
+        ] {
+            let mut entry = Entry::new(name);
+            entry.has_stream = has_stream;
+            entry.is_dir = is_dir;
+            header.entries.push(entry);
+        }
+        header.entries[2].mtime = Some(FileTime::from(1));
+        header.entries[4].attributes = Some(WinAttributes::ARCHIVE);
+
+        let mut buf = Vec::new();
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf[0], u8::from(Property::Header));
+        let header = Header::read(&mut &buf[1..]).unwrap();
+        let substreams = &header.main_streams_info.substreams;
+        assert_eq!(substreams.num_unpack_streams, [2, 1]);
+        assert_eq!(substreams.unpack_sizes, [3, 2, 2]);
+        assert_eq!(substreams.crcs, [Some(1), None, Some(7)]);
+        let entries = &header.entries;
+        assert_eq!(
+            entries.iter().map(Entry::name).collect::<Vec<_>>(),
+            ["a", "dir", "b", "empty", "c"]
+        );
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.folder, e.offset, e.size))
+                .collect::<Vec<_>>(),
+            [
+                (Some(0), 0, 3),
+                (None, 0, 0),
+                (Some(0), 3, 2),
+                (None, 0, 0),
+                (Some(1), 0, 2)
+            ]
+        );
+        assert!(entries[1].is_dir());
+        assert!(!entries[3].is_dir() && !entries[3].has_stream());
+        assert_eq!(entries[2].modified(), Some(FileTime::from(1)));
+        assert_eq!(entries[0].modified(), None);
+        assert_eq!(entries[4].attributes(), Some(WinAttributes::ARCHIVE));
+        assert_eq!(entries[4].crc(), Some(7));
+    }
+
+    #[test]
+    fn read_start_header() {
+        let mut buf = SIGNATURE.to_vec();
+        buf.extend([0x00, 0x04]);
+        let start_header = [
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x78, 0x56, 0x34, 0x12,
+        ];
+        buf.extend(crc32fast::hash(&start_header).to_le_bytes());
+        buf.extend(start_header);
+        let start_header = StartHeader::read(&mut &buf[..]).unwrap();
+        assert_eq!(start_header.offset, 0x10);
+        assert_eq!(start_header.size, 0x20);
+        assert_eq!(start_header.crc, 0x1234_5678);
+
+        buf[8] ^= 0xff;
+        assert!(matches!(
+            StartHeader::read(&mut &buf[..]).unwrap_err(),
+            crate::Error::InvalidArchive(InvalidArchive::StartHeaderCrc)
+        ));
+        buf[6] = 0x01;
+        assert!(matches!(
+            StartHeader::read(&mut &buf[..]).unwrap_err(),
+            crate::Error::UnsupportedArchive(UnsupportedArchive::Version { major: 1, minor: 4 })
+        ));
+        buf[0] = 0x00;
+        assert!(matches!(
+            StartHeader::read(&mut &buf[..]).unwrap_err(),
+            crate::Error::InvalidArchive(InvalidArchive::Signature(_))
+        ));
+    }
+
+    #[test]
+    fn read_folder() {
+        // BCJ2 with LZMA for the main stream: 4 coders, 3 bind pairs.
+        let data = [
+            0x04, // NumCoders
+            0x14, 0x03, 0x03, 0x01, 0x1b, 0x04, 0x01, // BCJ2
+            0x23, 0x03, 0x01, 0x01, 0x05, 0x5d, 0x00, 0x00, 0x10, 0x00, // LZMA
+            0x23, 0x03, 0x01, 0x01, 0x05, 0x5d, 0x00, 0x00, 0x10, 0x00, // LZMA
+            0x23, 0x03, 0x01, 0x01, 0x05, 0x5d, 0x00, 0x00, 0x10, 0x00, // LZMA
+            0x00, 0x01, 0x01, 0x02, 0x02, 0x03, // BindPairs
+            0x04, 0x05, 0x06, 0x03, // PackedStreams
+        ];
+        let mut folder = Folder::read(&mut &data[..]).unwrap();
+        assert_eq!(folder.coders.len(), 4);
+        assert_eq!(
+            folder.coders[0].method,
+            Method::Sz(crate::property::Sz::Bcj2)
+        );
+        assert_eq!(folder.coders[0].num_in_streams, 4);
+        assert_eq!(folder.coders[1].properties, [0x5d, 0x00, 0x00, 0x10, 0x00]);
+        assert_eq!(folder.bind_pairs.len(), 3);
+        assert_eq!(folder.packed_streams, [4, 5, 6, 3]);
+        assert_eq!(folder.first_in_stream(2), 5);
+        assert_eq!(folder.coder_of_out_stream(3), Some(3));
+        folder.unpack_sizes = vec![u64::default(); 4];
+        assert_eq!(folder.decoding_order(), [1, 2, 3, 0]);
+
+        // The same in stream is bound twice.
+        let mut data = data;
+        data[40] = 0x00;
+        assert!(matches!(
+            Folder::read(&mut &data[..]).unwrap_err(),
+            crate::Error::InvalidArchive(InvalidArchive::Folder)
+        ));
+    }
+
+    #[test]
+    fn read_folder_with_unknown_method() {
+        let folder = Folder::read(&mut &[0x01, 0x01, 0xff, 0x00][..]).unwrap();
+        assert_eq!(folder.coders[0].method, Method::Unknown(vec![0xff]));
+    }
+}
@@ -0,0 +1,224 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Compression levels of 7-Zip.
+
+use std::fmt;
+
+use crate::{
+    method::{Method, MethodSpec, Param},
+    writer::SolidOptions,
+};
+
+/// The minimum size of a solid block.
+const SOLID_SIZE_MIN: u64 = 1 << 24;
+
+/// The maximum size of a solid block.
+const SOLID_SIZE_MAX: u64 = 1 << 32;
+
+/// A compression level from 0 to 9, as the `-mx` switch of 7-Zip.
+///
+/// A level expands into the settings which 7-Zip uses for it: the dictionary
+/// size, the number of fast bytes and the match finder of LZMA2, the size of
+/// solid blocks and the number of threads. Level 0 stores the files with Copy.
+/// The default is [`CompressionLevel::NORMAL`].
+///
+/// | Level | Dictionary | Fast bytes | Match finder | Solid block |
+/// | ----- | ---------- | ---------- | ------------ | ----------- |
+/// | 0     | -          | -          | -            | -           |
+/// | 1     | 256 KiB    | 32         | HC4          | 32 MiB      |
+/// | 3     | 4 MiB      | 32         | HC4          | 512 MiB     |
+/// | 5     | 16 MiB     | 32         | BT4          | 2 GiB       |
+/// | 7     | 32 MiB     | 64         | BT4          | 4 GiB       |
+/// | 9     | 64 MiB     | 64         | BT4          | 4 GiB       |
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CompressionLevel(u8);
+
+impl CompressionLevel {
+    /// Level 0, which stores the files without compression.
+    pub const STORE: Self = Self(0);
+
+    /// Level 1.
+    pub const FASTEST: Self = Self(1);
+
+    /// Level 3.
+    pub const FAST: Self = Self(3);
+
+    /// Level 5, which is the default of 7-Zip.
+    pub const NORMAL: Self = Self(5);
+
+    /// Level 7.
+    pub const MAXIMUM: Self = Self(7);
+
+    /// Level 9.
+    pub const ULTRA: Self = Self(9);
+
+    /// Creates a new `CompressionLevel`.
+    ///
+    /// Returns [`None`] if `level` is greater than 9.
+    #[must_use]
+    pub const fn new(level: u8) -> Option<Self> {
+        if level <= 9 {
+            Some(Self(level))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the level as a number.
+    #[must_use]
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the dictionary size of LZMA2 in bytes.
+    ///
+    /// Returns 0 for level 0.
+    #[must_use]
+    pub const fn dict_size(self) -> u32 {
+        match self.0 {
+            0 => 0,
+            level @ 1..=3 => 1 << (level * 2 + 16),
+            level @ 4..=6 => 1 << (level + 19),
+            7 => 1 << 25,
+            _ => 1 << 26,
+        }
+    }
+
+    /// Returns the number of fast bytes of LZMA2.
+    #[must_use]
+    pub const fn fast_bytes(self) -> u32 {
+        if self.0 < 7 {
+            32
+        } else {
+            64
+        }
+    }
+
+    /// Returns the name of the match finder of LZMA2 in lowercase, which is
+    /// either `hc4` or `bt4`.
+    #[must_use]
+    pub const fn match_finder(self) -> &'static str {
+        if self.0 < 5 {
+            "hc4"
+        } else {
+            "bt4"
+        }
+    }
+
+    /// Returns the number of threads which 7-Zip uses for an LZMA2 stream.
+    ///
+    /// The binary tree match finder runs in its own thread. This is for
+    /// reference, since [`ArchiveWriter`](crate::ArchiveWriter) compresses on
+    /// the calling thread.
+    #[must_use]
+    pub const fn threads(self) -> usize {
+        if self.0 < 5 {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Returns the method with the parameters of this level, such as
+    /// `LZMA2:d24:fb32:mf=bt4`.
+    #[must_use]
+    pub fn method(self) -> MethodSpec {
+        if self == Self::STORE {
+            return MethodSpec::new(Method::Copy);
+        }
+        let mut spec = MethodSpec::new(Method::Lzma2);
+        spec.params = vec![
+            Param::new("d", Param::format_size(self.dict_size().into())),
+            Param::new("fb", self.fast_bytes().to_string()),
+            Param::new("mf", self.match_finder()),
+        ];
+        spec
+    }
+
+    /// Returns the options of solid blocks.
+    ///
+    /// The size of a solid block is 128 times the dictionary size, between 16
+    /// MiB and 4 GiB. Level 0 puts each file into its own block.
+    #[must_use]
+    pub fn solid_options(self) -> SolidOptions {
+        if self == Self::STORE {
+            return SolidOptions::off();
+        }
+        let size = (u64::from(self.dict_size()) << 7).clamp(SOLID_SIZE_MIN, SOLID_SIZE_MAX);
+        SolidOptions::on().max_size(size)
+    }
+}
+
+impl Default for CompressionLevel {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+impl fmt::Display for CompressionLevel {
+    /// Formats this level as the switch of 7-Zip, such as `mx5`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mx{}", self.0)
+    }
+}
+
+impl From<CompressionLevel> for u8 {
+    fn from(level: CompressionLevel) -> Self {
+        level.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_level() {
+        assert_eq!(CompressionLevel::new(0), Some(CompressionLevel::STORE));
+        assert_eq!(CompressionLevel::new(9), Some(CompressionLevel::ULTRA));
+        assert_eq!(CompressionLevel::new(10), None);
+        assert_eq!(CompressionLevel::default().get(), 5);
+        assert_eq!(u8::from(CompressionLevel::FAST), 3);
+        assert_eq!(CompressionLevel::MAXIMUM.to_string(), "mx7");
+    }
+
+    #[test]
+    fn expand_level() {
+        let dict_sizes = (0..=9)
+            .map(|level| CompressionLevel(level).dict_size() >> 10)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dict_sizes,
+            [0, 256, 1024, 4096, 8192, 16384, 32768, 32768, 65536, 65536]
+        );
+        let methods = [0, 1, 3, 5, 7, 9].map(|level| CompressionLevel(level).method().to_string());
+        assert_eq!(
+            methods,
+            [
+                "Copy",
+                "LZMA2:d18:fb32:mf=hc4",
+                "LZMA2:d22:fb32:mf=hc4",
+                "LZMA2:d24:fb32:mf=bt4",
+                "LZMA2:d25:fb64:mf=bt4",
+                "LZMA2:d26:fb64:mf=bt4"
+            ]
+        );
+        assert_eq!(CompressionLevel::FASTEST.threads(), 1);
+        assert_eq!(CompressionLevel::ULTRA.threads(), 2);
+    }
+
+    #[test]
+    fn solid_options() {
+        assert_eq!(CompressionLevel::STORE.solid_options(), SolidOptions::off());
+        for (level, size) in [(1, 32 << 20), (3, 512 << 20), (5, 2 << 30), (9, 4 << 30)] {
+            assert_eq!(
+                CompressionLevel(level).solid_options(),
+                SolidOptions::on().max_size(size)
+            );
+        }
+    }
+}
@@ -17,10 +17,14 @@
 //!
 //! ## Optional features
 //!
+//! - `brotli`: Enable the Brotli method (`04 F7 11 02`) of [7-Zip-zstd].
 //! - `large-dates`: Enable the `large-dates` feature of the [`time`][time]
 //!   crate.
+//! - `lz4`: Enable the LZ4 method (`04 F7 11 04`) of [7-Zip-zstd].
+//! - `zstd`: Enable the Zstandard method (`04 F7 11 01`) of [7-Zip-zstd].
 //!
 //! [7z]: https://www.7-zip.org/7z.html
+//! [7-Zip-zstd]: https://github.com/mcmilk/7-Zip-zstd
 //! [sdk]: https://www.7-zip.org/sdk.html
 //! [time]: https://docs.rs/time
 
@@ -30,14 +34,43 @@
 #![forbid(unsafe_code)]
 #![deny(missing_debug_implementations, missing_docs)]
 #![warn(rust_2018_idioms)]
+// `doc_cfg` is set only by docs.rs. `unexpected_cfgs` is unknown before Rust
+// 1.80.
+#![allow(unknown_lints, unexpected_cfgs)]
 // Lint levels of Clippy.
 #![warn(clippy::cargo, clippy::nursery, clippy::pedantic)]
 
+mod analysis;
+mod archive;
+mod attributes;
+#[cfg(feature = "brotli")]
+mod brotli_mt;
+pub mod codec;
+mod crypto;
+mod decoder;
+mod encoder;
+mod entry;
 pub mod error;
 pub mod filetime;
+mod filter;
+mod folder;
+mod header;
+mod level;
+pub mod method;
+mod password;
 mod property;
+mod writer;
 
 pub use crate::{
+    archive::{Archive, Extractability, OpenOptions},
+    attributes::{FileType, WinAttributes},
+    codec::{Codec, CodecRegistry},
+    crypto::KeyCache,
+    entry::{Entry, PermissionPolicy},
     error::{Error, Result},
     filetime::FileTime,
+    folder::{CoderSpec, FolderBuilder, FolderLayout},
+    level::CompressionLevel,
+    password::PasswordProvider,
+    writer::{ArchiveWriter, EntryWriter, MethodRule, SolidOptions, Timestamps},
 };
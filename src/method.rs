@@ -0,0 +1,871 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Methods and the properties of coders.
+//!
+//! Each coder of a folder stores the properties of its method as bytes. The
+//! types in this module are typed views of them, each of which can be parsed
+//! from and converted back to the bytes.
+//!
+//! Methods can also be written in the notation of 7-Zip, such as
+//! `LZMA2:d=64m:fb=64` for the `-m` switch and `LZMA2:24 BCJ` for the output
+//! of `7z l -slt`. See [`MethodSpec`] and [`MethodChain`].
+
+use std::{fmt, str::FromStr};
+
+pub use crate::property::{Crypto, Method, Misc, Sz};
+use crate::{
+    error::{InvalidArchive, ParseMethodError},
+    Result,
+};
+
+/// The properties of LZMA.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LzmaProps {
+    /// The number of literal context bits (0 to 8).
+    pub lc: u8,
+
+    /// The number of literal position bits (0 to 4).
+    pub lp: u8,
+
+    /// The number of position bits (0 to 4).
+    pub pb: u8,
+
+    /// The dictionary size.
+    pub dict_size: u32,
+}
+
+impl LzmaProps {
+    /// Parses the properties of LZMA.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        match *properties {
+            [byte, d0, d1, d2, d3] if byte < 9 * 5 * 5 => Ok(Self {
+                lc: byte % 9,
+                lp: byte / 9 % 5,
+                pb: byte / 9 / 5,
+                dict_size: u32::from_le_bytes([d0, d1, d2, d3]),
+            }),
+            _ => Err(InvalidArchive::Folder.into()),
+        }
+    }
+
+    /// Converts the properties to the bytes.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5);
+        buf.push((self.pb * 5 + self.lp) * 9 + self.lc);
+        buf.extend(self.dict_size.to_le_bytes());
+        buf
+    }
+}
+
+impl Default for LzmaProps {
+    fn default() -> Self {
+        Self {
+            lc: 3,
+            lp: 0,
+            pb: 2,
+            dict_size: 1 << 24,
+        }
+    }
+}
+
+/// The properties of LZMA2.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Lzma2Props {
+    /// The dictionary size.
+    ///
+    /// Only sizes of the form `2^n` or `3 * 2^n` from 4 KiB to 3 GiB and
+    /// [`u32::MAX`] can be stored, so other sizes are rounded up to them by
+    /// [`Lzma2Props::to_bytes`].
+    pub dict_size: u32,
+}
+
+impl Lzma2Props {
+    /// The largest encoded dictionary size, which means [`u32::MAX`].
+    const MAX_DICT_SIZE: u8 = 40;
+
+    /// Parses the properties of LZMA2.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        match *properties {
+            [byte] if byte <= Self::MAX_DICT_SIZE => Ok(Self {
+                dict_size: Self::decode_dict_size(byte),
+            }),
+            _ => Err(InvalidArchive::Folder.into()),
+        }
+    }
+
+    /// Converts the properties to the bytes.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let byte = (0..Self::MAX_DICT_SIZE)
+            .find(|&byte| Self::decode_dict_size(byte) >= self.dict_size)
+            .unwrap_or(Self::MAX_DICT_SIZE);
+        vec![byte]
+    }
+
+    /// Decodes the dictionary size.
+    const fn decode_dict_size(byte: u8) -> u32 {
+        if byte == Self::MAX_DICT_SIZE {
+            u32::MAX
+        } else {
+            (2 | (byte as u32 & 1)) << (byte / 2 + 11)
+        }
+    }
+}
+
+impl Default for Lzma2Props {
+    fn default() -> Self {
+        Self { dict_size: 1 << 24 }
+    }
+}
+
+/// The properties of the Delta filter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeltaProps {
+    /// The distance in bytes (1 to 256).
+    pub distance: u16,
+}
+
+impl DeltaProps {
+    /// Parses the properties of the Delta filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        match *properties {
+            [byte] => Ok(Self {
+                distance: u16::from(byte) + 1,
+            }),
+            _ => Err(InvalidArchive::Folder.into()),
+        }
+    }
+
+    /// Converts the properties to the bytes.
+    ///
+    /// The distance is clamped to the range from 1 to 256.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![u8::try_from(self.distance.clamp(1, 256) - 1).unwrap_or(u8::MAX)]
+    }
+}
+
+impl Default for DeltaProps {
+    fn default() -> Self {
+        Self { distance: 1 }
+    }
+}
+
+/// The properties of 7zAES.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AesProps {
+    /// The base 2 logarithm of the number of the SHA-256 rounds to derive the
+    /// key, or `0x3f` for no key stretching.
+    pub cycles_power: u8,
+
+    /// The salt (up to 16 bytes).
+    pub salt: Vec<u8>,
+
+    /// The initialization vector (up to 16 bytes), which is padded with zeros.
+    pub iv: Vec<u8>,
+}
+
+impl AesProps {
+    /// The largest size of the salt and the initialization vector.
+    const MAX_SIZE: usize = 16;
+
+    /// Parses the properties of 7zAES.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        let (&first, rest) = properties.split_first().ok_or(InvalidArchive::Folder)?;
+        let cycles_power = first & 0x3f;
+        if first & 0xc0 == 0 {
+            return Ok(Self {
+                cycles_power,
+                ..Self::default()
+            });
+        }
+        let (&second, rest) = rest.split_first().ok_or(InvalidArchive::Folder)?;
+        let salt_size = usize::from((first >> 7) & 1) + usize::from(second >> 4);
+        let iv_size = usize::from((first >> 6) & 1) + usize::from(second & 0x0f);
+        if rest.len() != salt_size + iv_size {
+            return Err(InvalidArchive::Folder.into());
+        }
+        let (salt, iv) = rest.split_at(salt_size);
+        Ok(Self {
+            cycles_power,
+            salt: salt.to_vec(),
+            iv: iv.to_vec(),
+        })
+    }
+
+    /// Converts the properties to the bytes.
+    ///
+    /// The salt and the initialization vector are truncated to 16 bytes.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let salt = &self.salt[..self.salt.len().min(Self::MAX_SIZE)];
+        let iv = &self.iv[..self.iv.len().min(Self::MAX_SIZE)];
+        let mut first = self.cycles_power & 0x3f;
+        if salt.is_empty() && iv.is_empty() {
+            return vec![first];
+        }
+        let (salt_size, iv_size) = (salt.len().saturating_sub(1), iv.len().saturating_sub(1));
+        first |= u8::from(!salt.is_empty()) << 7 | u8::from(!iv.is_empty()) << 6;
+        let second = u8::try_from(salt_size << 4 | iv_size).unwrap_or(u8::MAX);
+        let mut buf = vec![first, second];
+        buf.extend(salt);
+        buf.extend(iv);
+        buf
+    }
+}
+
+/// The properties of `PPMd`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PpmdProps {
+    /// The model order (2 to 64).
+    pub order: u8,
+
+    /// The memory size.
+    pub mem_size: u32,
+}
+
+impl PpmdProps {
+    /// The smallest memory size.
+    const MIN_MEM_SIZE: u32 = 1 << 11;
+
+    /// The largest memory size.
+    const MAX_MEM_SIZE: u32 = u32::MAX - 12 * 3;
+
+    /// Parses the properties of `PPMd`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        match *properties {
+            [order, m0, m1, m2, m3] => {
+                let mem_size = u32::from_le_bytes([m0, m1, m2, m3]);
+                if !(2..=64).contains(&order)
+                    || !(Self::MIN_MEM_SIZE..=Self::MAX_MEM_SIZE).contains(&mem_size)
+                {
+                    return Err(InvalidArchive::Folder.into());
+                }
+                Ok(Self { order, mem_size })
+            }
+            _ => Err(InvalidArchive::Folder.into()),
+        }
+    }
+
+    /// Converts the properties to the bytes.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5);
+        buf.push(self.order);
+        buf.extend(self.mem_size.to_le_bytes());
+        buf
+    }
+}
+
+impl Default for PpmdProps {
+    fn default() -> Self {
+        Self {
+            order: 6,
+            mem_size: 1 << 24,
+        }
+    }
+}
+
+/// The properties of BCJ and the other branch converters.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BcjProps {
+    /// The start offset of the converted addresses.
+    pub start_offset: u32,
+}
+
+impl BcjProps {
+    /// Parses the properties of a branch converter.
+    ///
+    /// The empty properties mean the start offset of 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the properties are invalid.
+    pub fn parse(properties: &[u8]) -> Result<Self> {
+        match *properties {
+            [] => Ok(Self::default()),
+            [b0, b1, b2, b3] => Ok(Self {
+                start_offset: u32::from_le_bytes([b0, b1, b2, b3]),
+            }),
+            _ => Err(InvalidArchive::Folder.into()),
+        }
+    }
+
+    /// Converts the properties to the bytes.
+    ///
+    /// The start offset of 0 is converted to the empty properties as 7-Zip
+    /// does.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.start_offset == 0 {
+            Vec::new()
+        } else {
+            self.start_offset.to_le_bytes().to_vec()
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    /// Formats the name of this method, or the ID in hexadecimal as 7-Zip does
+    /// if this method is not known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Custom(id) | Self::Unknown(id) => {
+                id.iter().try_for_each(|b| write!(f, "{b:02X}"))
+            }
+            method => f.write_str(method.name()),
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = ParseMethodError;
+
+    /// Parses the name of a method ignoring case, or the ID in hexadecimal.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseMethodError::Empty);
+        }
+        if let Some(method) = Self::by_name(s) {
+            return Ok(method);
+        }
+        let unknown = || ParseMethodError::UnknownMethod(s.to_string());
+        if s.len() % 2 != 0 || s.len() > 30 {
+            return Err(unknown());
+        }
+        let id = (0..s.len())
+            .step_by(2)
+            .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(unknown)?;
+        Ok(Self::from_id(&id))
+    }
+}
+
+/// A parameter of a method, such as `d=64m` in `LZMA2:d=64m`.
+///
+/// The name is the leading letters and the value is the rest, so `d64m` and
+/// `d=64m` are the same parameter. A parameter without the name, such as `24`
+/// in `LZMA2:24`, is the main parameter of the method.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Param {
+    /// The name in lowercase.
+    pub name: String,
+
+    /// The value.
+    pub value: String,
+}
+
+impl Param {
+    /// Creates a new `Param`.
+    ///
+    /// The name is converted to lowercase.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        let mut name = name.into();
+        name.make_ascii_lowercase();
+        Self {
+            name,
+            value: value.into(),
+        }
+    }
+
+    /// Returns the value as a size in the notation of 7-Zip.
+    ///
+    /// A number with the suffix `b`, `k`, `m` or `g` is the size in bytes,
+    /// KiB, MiB or GiB. A number without the suffix is the base 2 logarithm of
+    /// the size.
+    #[must_use]
+    pub fn size(&self) -> Option<u64> {
+        let value = self.value.to_ascii_lowercase();
+        let (number, shift) = match value.as_bytes().last()? {
+            b'b' => (&value[..value.len() - 1], 0),
+            b'k' => (&value[..value.len() - 1], 10),
+            b'm' => (&value[..value.len() - 1], 20),
+            b'g' => (&value[..value.len() - 1], 30),
+            _ => return value.parse().ok().and_then(|n| 1_u64.checked_shl(n)),
+        };
+        let number = number.parse::<u64>().ok()?;
+        number.checked_mul(1 << shift)
+    }
+
+    /// Formats the size in the notation of 7-Zip.
+    ///
+    /// A power of two is formatted as its base 2 logarithm.
+    #[must_use]
+    pub fn format_size(size: u64) -> String {
+        if size.is_power_of_two() {
+            size.trailing_zeros().to_string()
+        } else if size.trailing_zeros() >= 20 {
+            format!("{}m", size >> 20)
+        } else if size.trailing_zeros() >= 10 {
+            format!("{}k", size >> 10)
+        } else {
+            format!("{size}b")
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    /// Formats this parameter, omitting `=` if the value starts with a digit.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if !self.name.is_empty()
+            && !self.value.is_empty()
+            && !self.value.starts_with(|c: char| c.is_ascii_digit())
+        {
+            f.write_str("=")?;
+        }
+        f.write_str(&self.value)
+    }
+}
+
+impl FromStr for Param {
+    type Err = ParseMethodError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || ParseMethodError::InvalidParam(s.to_string());
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, value),
+            None => s.split_at(
+                s.find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(s.len()),
+            ),
+        };
+        if s.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphabetic())
+            || value.contains(|c: char| c == ':' || c.is_whitespace())
+        {
+            return Err(invalid());
+        }
+        Ok(Self::new(name, value))
+    }
+}
+
+/// A method with the parameters in the notation of 7-Zip.
+///
+/// This is formatted as the name of the method followed by the parameters
+/// separated by `:`, such as `LZMA2:d=64m:fb=64` or `PPMD:o32:mem192m`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MethodSpec {
+    /// The method.
+    pub method: Method,
+
+    /// The parameters.
+    pub params: Vec<Param>,
+}
+
+impl MethodSpec {
+    /// Creates a new `MethodSpec` without the parameters.
+    #[must_use]
+    pub const fn new(method: Method) -> Self {
+        Self {
+            method,
+            params: Vec::new(),
+        }
+    }
+
+    /// Creates a new `MethodSpec` from the properties of a coder as `7z l
+    /// -slt` shows it.
+    ///
+    /// The properties which are invalid are ignored.
+    #[must_use]
+    pub fn from_properties(method: Method, properties: &[u8]) -> Self {
+        let dict = |size: u32| Param::new("", Param::format_size(size.into()));
+        let params = match method {
+            Method::Lzma2 => Lzma2Props::parse(properties)
+                .map(|props| vec![dict(props.dict_size)])
+                .unwrap_or_default(),
+            Method::Sz(Sz::Lzma) => LzmaProps::parse(properties)
+                .map(|props| {
+                    let default = LzmaProps::default();
+                    let mut params = vec![dict(props.dict_size)];
+                    for (name, value, default) in [
+                        ("lc", props.lc, default.lc),
+                        ("lp", props.lp, default.lp),
+                        ("pb", props.pb, default.pb),
+                    ] {
+                        if value != default {
+                            params.push(Param::new(name, value.to_string()));
+                        }
+                    }
+                    params
+                })
+                .unwrap_or_default(),
+            Method::Sz(Sz::Ppmd) => PpmdProps::parse(properties)
+                .map(|props| {
+                    vec![
+                        Param::new("o", props.order.to_string()),
+                        Param::new("mem", Param::format_size(props.mem_size.into())),
+                    ]
+                })
+                .unwrap_or_default(),
+            Method::Delta => DeltaProps::parse(properties)
+                .map(|props| vec![Param::new("", props.distance.to_string())])
+                .unwrap_or_default(),
+            Method::Crypto(Crypto::SzAes) => AesProps::parse(properties)
+                .map(|props| vec![Param::new("", props.cycles_power.to_string())])
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        Self { method, params }
+    }
+
+    /// Returns the parameter with the name, ignoring case.
+    ///
+    /// The empty name returns the main parameter.
+    #[must_use]
+    pub fn param(&self, name: &str) -> Option<&Param> {
+        self.params
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for MethodSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.method)?;
+        self.params
+            .iter()
+            .try_for_each(|param| write!(f, ":{param}"))
+    }
+}
+
+impl FromStr for MethodSpec {
+    type Err = ParseMethodError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let method = parts.next().unwrap_or_default().parse()?;
+        let params = parts
+            .map(str::parse)
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self { method, params })
+    }
+}
+
+impl From<Method> for MethodSpec {
+    fn from(method: Method) -> Self {
+        Self::new(method)
+    }
+}
+
+/// A chain of methods in the notation of 7-Zip.
+///
+/// This is formatted as the methods separated by a space, in the order of
+/// decoding, such as `LZMA2:24 BCJ`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MethodChain(pub Vec<MethodSpec>);
+
+impl fmt::Display for MethodChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, spec) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{spec}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Method> for MethodChain {
+    fn from(method: Method) -> Self {
+        Self(vec![method.into()])
+    }
+}
+
+impl From<MethodSpec> for MethodChain {
+    fn from(spec: MethodSpec) -> Self {
+        Self(vec![spec])
+    }
+}
+
+impl FromStr for MethodChain {
+    type Err = ParseMethodError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let specs = s
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if specs.is_empty() {
+            return Err(ParseMethodError::Empty);
+        }
+        Ok(Self(specs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lzma_props() {
+        let props = LzmaProps::parse(&[0x5d, 0x00, 0x00, 0x00, 0x01]).unwrap();
+        assert_eq!(props, LzmaProps::default());
+        assert_eq!(props.to_bytes(), [0x5d, 0x00, 0x00, 0x00, 0x01]);
+        let props = LzmaProps {
+            lc: 8,
+            lp: 4,
+            pb: 4,
+            dict_size: u32::MAX,
+        };
+        assert_eq!(LzmaProps::parse(&props.to_bytes()).unwrap(), props);
+
+        assert!(LzmaProps::parse(&[0x5d]).is_err());
+        assert!(LzmaProps::parse(&[0xe1, 0x00, 0x00, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn lzma2_props() {
+        for (byte, dict_size) in [
+            (0, 4 << 10),
+            (1, 6 << 10),
+            (18, 2 << 20),
+            (24, 16 << 20),
+            (39, 3 << 30),
+            (40, u32::MAX),
+        ] {
+            let props = Lzma2Props::parse(&[byte]).unwrap();
+            assert_eq!(props.dict_size, dict_size);
+            assert_eq!(props.to_bytes(), [byte]);
+        }
+        assert_eq!(Lzma2Props { dict_size: 0 }.to_bytes(), [0]);
+        assert_eq!(Lzma2Props { dict_size: 5 << 20 }.to_bytes(), [21]);
+        assert_eq!(
+            Lzma2Props {
+                dict_size: (3 << 30) + 1
+            }
+            .to_bytes(),
+            [40]
+        );
+
+        assert!(Lzma2Props::parse(&[]).is_err());
+        assert!(Lzma2Props::parse(&[41]).is_err());
+    }
+
+    #[test]
+    fn delta_props() {
+        assert_eq!(DeltaProps::parse(&[0]).unwrap(), DeltaProps::default());
+        let props = DeltaProps::parse(&[u8::MAX]).unwrap();
+        assert_eq!(props.distance, 256);
+        assert_eq!(props.to_bytes(), [u8::MAX]);
+        assert_eq!(DeltaProps { distance: 0 }.to_bytes(), [0]);
+        assert_eq!(DeltaProps { distance: 1024 }.to_bytes(), [u8::MAX]);
+
+        assert!(DeltaProps::parse(&[]).is_err());
+        assert!(DeltaProps::parse(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn aes_props() {
+        let props = AesProps::parse(&[0x13]).unwrap();
+        assert_eq!(props.cycles_power, 19);
+        assert!(props.salt.is_empty());
+        assert!(props.iv.is_empty());
+        assert_eq!(props.to_bytes(), [0x13]);
+
+        let mut buf = vec![0xd3, 0x1f];
+        buf.extend([0xaa; 2]);
+        buf.extend([0xbb; 16]);
+        let props = AesProps::parse(&buf).unwrap();
+        assert_eq!(props.cycles_power, 19);
+        assert_eq!(props.salt, [0xaa; 2]);
+        assert_eq!(props.iv, [0xbb; 16]);
+        assert_eq!(props.to_bytes(), buf);
+
+        let buf = [0x53, 0x07, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        let props = AesProps::parse(&buf).unwrap();
+        assert!(props.salt.is_empty());
+        assert_eq!(props.iv, [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        assert_eq!(props.to_bytes(), buf);
+
+        assert!(AesProps::parse(&[]).is_err());
+        assert!(AesProps::parse(&[0xd3, 0x1f, 0xaa]).is_err());
+    }
+
+    #[test]
+    fn ppmd_props() {
+        let props = PpmdProps::parse(&[0x06, 0x00, 0x00, 0x00, 0x01]).unwrap();
+        assert_eq!(props, PpmdProps::default());
+        assert_eq!(props.to_bytes(), [0x06, 0x00, 0x00, 0x00, 0x01]);
+
+        assert!(PpmdProps::parse(&[0x06]).is_err());
+        assert!(PpmdProps::parse(&[0x01, 0x00, 0x00, 0x00, 0x01]).is_err());
+        assert!(PpmdProps::parse(&[0x41, 0x00, 0x00, 0x00, 0x01]).is_err());
+        assert!(PpmdProps::parse(&[0x06, 0xff, 0x07, 0x00, 0x00]).is_err());
+        assert!(PpmdProps::parse(&[0x06, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn bcj_props() {
+        let props = BcjProps::parse(&[]).unwrap();
+        assert_eq!(props, BcjProps::default());
+        assert!(props.to_bytes().is_empty());
+        let props = BcjProps::parse(&[0x00, 0x10, 0x00, 0x00]).unwrap();
+        assert_eq!(props.start_offset, 0x1000);
+        assert_eq!(props.to_bytes(), [0x00, 0x10, 0x00, 0x00]);
+
+        assert!(BcjProps::parse(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn format_method() {
+        assert_eq!(Method::Lzma2.to_string(), "LZMA2");
+        assert_eq!(Method::Sz(Sz::Ppmd).to_string(), "PPMD");
+        assert_eq!(Method::Crypto(Crypto::SzAes).to_string(), "7zAES");
+        assert_eq!(Method::Unknown(vec![0x7f, 0x01]).to_string(), "7F01");
+    }
+
+    #[test]
+    fn parse_method() {
+        assert_eq!("LZMA2".parse::<Method>().unwrap(), Method::Lzma2);
+        assert_eq!("PPMd".parse::<Method>().unwrap(), Method::Sz(Sz::Ppmd));
+        assert_eq!("bcj".parse::<Method>().unwrap(), Method::Sz(Sz::Bcj));
+        assert_eq!("21".parse::<Method>().unwrap(), Method::Lzma2);
+        assert_eq!(
+            "7F01".parse::<Method>().unwrap(),
+            Method::Unknown(vec![0x7f, 0x01])
+        );
+        let custom = Method::Custom(vec![0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(custom.to_string().parse::<Method>().unwrap(), custom);
+
+        assert!(matches!(
+            "".parse::<Method>().unwrap_err(),
+            ParseMethodError::Empty
+        ));
+        assert!(matches!(
+            "LZMA3".parse::<Method>().unwrap_err(),
+            ParseMethodError::UnknownMethod(name) if name == "LZMA3"
+        ));
+        assert!("7F0".parse::<Method>().is_err());
+    }
+
+    #[test]
+    fn param_size() {
+        assert_eq!(Param::new("d", "24").size(), Some(1 << 24));
+        assert_eq!(Param::new("d", "64m").size(), Some(64 << 20));
+        assert_eq!(Param::new("d", "1536K").size(), Some(1536 << 10));
+        assert_eq!(Param::new("d", "2g").size(), Some(2 << 30));
+        assert_eq!(Param::new("d", "100b").size(), Some(100));
+        assert_eq!(Param::new("d", "64").size(), None);
+        assert_eq!(Param::new("d", "m").size(), None);
+        assert_eq!(Param::new("d", "").size(), None);
+
+        assert_eq!(Param::format_size(1 << 24), "24");
+        assert_eq!(Param::format_size(192 << 20), "192m");
+        assert_eq!(Param::format_size(1536 << 10), "1536k");
+        assert_eq!(Param::format_size(100), "100b");
+    }
+
+    #[test]
+    fn parse_method_spec() {
+        let spec = "LZMA2:d=64m:fb=64".parse::<MethodSpec>().unwrap();
+        assert_eq!(spec.method, Method::Lzma2);
+        assert_eq!(
+            spec.params,
+            [Param::new("d", "64m"), Param::new("fb", "64")]
+        );
+        assert_eq!(spec.param("D").unwrap().size(), Some(64 << 20));
+        assert_eq!(spec.to_string(), "LZMA2:d64m:fb64");
+        assert_eq!(spec.to_string().parse::<MethodSpec>().unwrap(), spec);
+
+        let spec = "PPMd:o=32:mem=192m".parse::<MethodSpec>().unwrap();
+        assert_eq!(spec.to_string(), "PPMD:o32:mem192m");
+        assert_eq!("PPMD:o32:mem192m".parse::<MethodSpec>().unwrap(), spec);
+
+        let spec = "LZMA2:mf=bt4:x".parse::<MethodSpec>().unwrap();
+        assert_eq!(spec.params, [Param::new("mf", "bt4"), Param::new("x", "")]);
+        assert_eq!(spec.to_string(), "LZMA2:mf=bt4:x");
+
+        let spec = "LZMA2:24".parse::<MethodSpec>().unwrap();
+        assert_eq!(spec.param("").unwrap().size(), Some(1 << 24));
+
+        assert!(matches!(
+            "LZMA2:".parse::<MethodSpec>().unwrap_err(),
+            ParseMethodError::InvalidParam(_)
+        ));
+        assert!("LZMA2:1d=2".parse::<MethodSpec>().is_err());
+        assert!(":24".parse::<MethodSpec>().is_err());
+    }
+
+    #[test]
+    fn method_spec_from_properties() {
+        let spec = MethodSpec::from_properties(Method::Lzma2, &[24]);
+        assert_eq!(spec.to_string(), "LZMA2:24");
+        let spec = MethodSpec::from_properties(Method::Lzma2, &[0x1b]);
+        assert_eq!(spec.to_string(), "LZMA2:48m");
+        let spec =
+            MethodSpec::from_properties(Method::Sz(Sz::Lzma), &LzmaProps::default().to_bytes());
+        assert_eq!(spec.to_string(), "LZMA:24");
+        let props = LzmaProps {
+            lc: 4,
+            ..LzmaProps::default()
+        };
+        let spec = MethodSpec::from_properties(Method::Sz(Sz::Lzma), &props.to_bytes());
+        assert_eq!(spec.to_string(), "LZMA:24:lc4");
+        let props = PpmdProps {
+            order: 32,
+            mem_size: 192 << 20,
+        };
+        let spec = MethodSpec::from_properties(Method::Sz(Sz::Ppmd), &props.to_bytes());
+        assert_eq!(spec.to_string(), "PPMD:o32:mem192m");
+        let spec = MethodSpec::from_properties(Method::Delta, &[3]);
+        assert_eq!(spec.to_string(), "Delta:4");
+        let spec = MethodSpec::from_properties(Method::Crypto(Crypto::SzAes), &[0x13]);
+        assert_eq!(spec.to_string(), "7zAES:19");
+        let spec = MethodSpec::from_properties(Method::Sz(Sz::Bcj), &[]);
+        assert_eq!(spec.to_string(), "BCJ");
+        let spec = MethodSpec::from_properties(Method::Lzma2, &[]);
+        assert_eq!(spec.to_string(), "LZMA2");
+    }
+
+    #[test]
+    fn parse_method_chain() {
+        let chain = "LZMA2:24 BCJ".parse::<MethodChain>().unwrap();
+        assert_eq!(
+            chain.0,
+            [
+                MethodSpec {
+                    method: Method::Lzma2,
+                    params: vec![Param::new("", "24")]
+                },
+                MethodSpec::new(Method::Sz(Sz::Bcj))
+            ]
+        );
+        assert_eq!(chain.to_string(), "LZMA2:24 BCJ");
+        assert_eq!("  LZMA2:24\tBCJ ".parse::<MethodChain>().unwrap(), chain);
+
+        assert!(matches!(
+            " ".parse::<MethodChain>().unwrap_err(),
+            ParseMethodError::Empty
+        ));
+        assert!("LZMA2 LZMA3".parse::<MethodChain>().is_err());
+        assert_eq!(MethodChain::from(Method::Copy).to_string(), "Copy");
+    }
+}
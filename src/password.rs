@@ -0,0 +1,235 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! Passwords to decrypt archives.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{Read, Seek},
+    rc::Rc,
+    sync::Arc,
+};
+
+use zeroize::Zeroizing;
+
+use crate::{
+    codec::CodecRegistry,
+    crypto::{self, KeyCache, Secret},
+    decoder,
+    header::StreamsInfo,
+    property::{Crypto, Method},
+    Error, Result,
+};
+
+/// A trait for providing passwords to decrypt archives.
+///
+/// The password is requested lazily, only when an encrypted folder is
+/// actually decoded. If the password is detected to be wrong, it is requested
+/// again with the next attempt number.
+///
+/// This trait is implemented for closures of the form
+/// `FnMut(usize, usize) -> Option<String>`.
+pub trait PasswordProvider {
+    /// Returns the password to decrypt the folder at `folder`.
+    ///
+    /// `attempt` starts at 1 and is incremented each time the returned
+    /// password was wrong. Returns [`None`] to give up decrypting the folder.
+    ///
+    /// While the archive is being opened, `folder` is the index of a folder of
+    /// the encrypted header.
+    fn password(&mut self, folder: usize, attempt: usize) -> Option<String>;
+}
+
+impl<F: FnMut(usize, usize) -> Option<String>> PasswordProvider for F {
+    fn password(&mut self, folder: usize, attempt: usize) -> Option<String> {
+        self(folder, attempt)
+    }
+}
+
+/// A provider which returns the same password only once for each folder.
+///
+/// The password is zeroized on drop.
+#[derive(Debug)]
+pub struct FixedPassword(pub Zeroizing<String>);
+
+impl PasswordProvider for FixedPassword {
+    fn password(&mut self, _: usize, attempt: usize) -> Option<String> {
+        (attempt == 1).then(|| self.0.to_string())
+    }
+}
+
+/// The passwords of the folders in an archive.
+///
+/// The passwords and the keys are zeroized on drop.
+#[derive(Default)]
+pub struct Passwords {
+    provider: Option<Box<dyn PasswordProvider + Send>>,
+    key: Option<Secret>,
+    header: Option<Arc<Secret>>,
+    folders: HashMap<usize, Arc<Secret>>,
+    attempts: HashMap<usize, usize>,
+    confirmed: HashSet<usize>,
+    keys: KeyCache,
+}
+
+impl Passwords {
+    /// Sets the provider of the passwords.
+    pub fn set_provider(&mut self, provider: Box<dyn PasswordProvider + Send>) {
+        self.provider = Some(provider);
+    }
+
+    /// Sets the raw AES key which is used for all the folders.
+    pub fn set_key(&mut self, key: Zeroizing<[u8; 32]>) {
+        self.key = Some(Secret::Key(key));
+    }
+
+    /// Sets the cache of the keys.
+    pub fn set_keys(&mut self, keys: KeyCache) {
+        self.keys = keys;
+    }
+
+    /// Returns `true` if a provider or a raw key is set.
+    pub const fn has_secret(&self) -> bool {
+        self.provider.is_some() || self.key.is_some()
+    }
+
+    /// Forgets the passwords of the folders of the header, keeping the one
+    /// which decrypted the header to try it first for the other folders, as
+    /// 7-Zip does.
+    pub fn finish_header(&mut self) {
+        self.header = self
+            .folders
+            .drain()
+            .max_by_key(|(index, _)| *index)
+            .map(|(_, secret)| secret);
+        self.attempts.clear();
+        self.confirmed.clear();
+    }
+
+    /// Forgets the password of the folder which was detected to be wrong while
+    /// decoding, so that the next password is requested from the provider.
+    ///
+    /// Returns `true` if another password can be requested.
+    pub fn reject(&mut self, index: usize) -> bool {
+        self.key.is_none() && self.folders.remove(&index).is_some()
+    }
+
+    /// Caches the keys derived from the password of the folder, after the
+    /// data decrypted with it are verified.
+    pub fn confirm(&mut self, index: usize) {
+        if let Some(secret) = self.folders.get(&index) {
+            secret.commit(&self.keys);
+        }
+        self.confirmed.insert(index);
+    }
+
+    /// Returns `true` if the data of the folder were verified with the current
+    /// password.
+    pub fn is_confirmed(&self, index: usize) -> bool {
+        self.confirmed.contains(&index)
+    }
+
+    /// Returns the reader which decodes the unpacked data of the folder.
+    ///
+    /// If the folder is encrypted, the raw key is used if any. Otherwise, the
+    /// password of the header is tried first, and then the password is
+    /// requested from the provider until it is not detected to be wrong. The
+    /// password is remembered for later reads of the same folder until it is
+    /// rejected by [`Passwords::reject`], after which the provider is asked
+    /// with the next attempt number.
+    pub fn folder_reader<'a, R: Read + Seek>(
+        &mut self,
+        reader: &'a mut R,
+        streams_info: &StreamsInfo,
+        index: usize,
+        codecs: &CodecRegistry,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let reader = Rc::new(RefCell::new(reader));
+        let keys = &self.keys;
+        let folder_reader = |secret: Option<&Secret>| {
+            decoder::folder_reader(&reader, streams_info, index, secret, keys, codecs)
+        };
+        if !streams_info.folders[index].uses(&Method::Crypto(Crypto::SzAes)) {
+            return folder_reader(None);
+        }
+        if let Some(secret) = self
+            .key
+            .as_ref()
+            .or_else(|| self.folders.get(&index).map(AsRef::as_ref))
+        {
+            return folder_reader(Some(secret));
+        }
+
+        // The attempt 0 is the password of the header.
+        let mut attempt = self.attempts.get(&index).map_or(0, |attempt| attempt + 1);
+        if attempt == 0 {
+            if let Some(secret) = self.header.clone() {
+                match folder_reader(Some(&secret)) {
+                    Err(Error::WrongPassword) => {}
+                    result => {
+                        if result.is_ok() {
+                            self.folders.insert(index, secret);
+                            self.attempts.insert(index, attempt);
+                        }
+                        return result;
+                    }
+                }
+            }
+            attempt = 1;
+        }
+        let provider = self.provider.as_mut().ok_or(Error::PasswordRequired)?;
+        loop {
+            let secret = match provider.password(index, attempt).map(Zeroizing::new) {
+                Some(password) => Arc::new(Secret::password(crypto::encode_password(&password))),
+                None if attempt == 1 && self.header.is_none() => {
+                    return Err(Error::PasswordRequired)
+                }
+                None => return Err(Error::WrongPassword),
+            };
+            match folder_reader(Some(&secret)) {
+                Err(Error::WrongPassword) => attempt += 1,
+                result => {
+                    if result.is_ok() {
+                        self.folders.insert(index, secret);
+                        self.attempts.insert(index, attempt);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Passwords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Passwords")
+            .field("provider", &self.provider.as_ref().map(|_| ".."))
+            .field("key", &self.key.as_ref().map(|_| ".."))
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_provider_for_closure() {
+        let mut provider = |folder, attempt| Some(format!("{folder}-{attempt}"));
+        assert_eq!(provider.password(1, 2), Some(String::from("1-2")));
+    }
+
+    #[test]
+    fn fixed_password() {
+        let mut provider = FixedPassword(Zeroizing::new(String::from("password")));
+        assert_eq!(provider.password(0, 1), Some(String::from("password")));
+        assert_eq!(provider.password(1, 1), Some(String::from("password")));
+        assert_eq!(provider.password(0, 2), None);
+    }
+}
@@ -6,12 +6,13 @@
 
 //! 7z properties.
 
-#[allow(dead_code)]
+use crate::error::UnsupportedArchive;
+
 /// The signature of the 7z format.
 pub const SIGNATURE: [u8; 6] = [0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c];
 
 /// Represents 7z properties.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Property {
     End,
     Header,
@@ -41,70 +42,243 @@ pub enum Property {
     Dummy,
 }
 
+impl Property {
+    /// Gets the property associated with this ID.
+    pub const fn by_id(id: u8) -> Option<Self> {
+        match id {
+            0x00 => Some(Self::End),
+            0x01 => Some(Self::Header),
+            0x02 => Some(Self::ArchiveProperties),
+            0x03 => Some(Self::AdditionalStreamsInfo),
+            0x04 => Some(Self::MainStreamsInfo),
+            0x05 => Some(Self::FilesInfo),
+            0x06 => Some(Self::PackInfo),
+            0x07 => Some(Self::UnpackInfo),
+            0x08 => Some(Self::SubStreamsInfo),
+            0x09 => Some(Self::Size),
+            0x0a => Some(Self::Crc),
+            0x0b => Some(Self::Folder),
+            0x0c => Some(Self::CodersUnpackSize),
+            0x0d => Some(Self::NumUnpackStream),
+            0x0e => Some(Self::EmptyStream),
+            0x0f => Some(Self::EmptyFile),
+            0x10 => Some(Self::Anti),
+            0x11 => Some(Self::Name),
+            0x12 => Some(Self::CTime),
+            0x13 => Some(Self::ATime),
+            0x14 => Some(Self::MTime),
+            0x15 => Some(Self::WinAttributes),
+            0x16 => Some(Self::Comment),
+            0x17 => Some(Self::EncodedHeader),
+            0x18 => Some(Self::StartPos),
+            0x19 => Some(Self::Dummy),
+            _ => None,
+        }
+    }
+}
+
 impl From<Property> for u8 {
     fn from(property: Property) -> Self {
         property as Self
     }
 }
 
-#[allow(dead_code)]
 /// Represents 7z compression methods.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Method {
+    /// Copy (`00`).
     Copy,
+    /// Delta (`03`).
     Delta,
+    /// LZMA2 (`21`).
     Lzma2,
+    /// ARM64 (`0A`).
+    Arm64,
+    /// Swap2 (`02 03 02`).
+    Swap2,
+    /// Swap4 (`02 03 04`).
+    Swap4,
+    /// A method of 7z (`03`).
     Sz(Sz),
+    /// A misc method (`04`).
     Misc(Misc),
+    /// A crypto method (`06`).
     Crypto(Crypto),
+    /// A method with a random ID (`3F ZZ ZZ ZZ ZZ ZZ MM MM`).
+    Custom(Vec<u8>),
+    /// A method which is not known.
+    Unknown(Vec<u8>),
 }
 
 impl Method {
-    #[allow(dead_code)]
     /// Gets the ID of this method.
-    pub const fn id(&self) -> &'static [u8] {
+    #[must_use]
+    pub fn id(&self) -> &[u8] {
         match self {
             Self::Copy => &[0x00],
             Self::Delta => &[0x03],
             Self::Lzma2 => &[0x21],
+            Self::Arm64 => &[0x0a],
+            Self::Swap2 => &[0x02, 0x03, 0x02],
+            Self::Swap4 => &[0x02, 0x03, 0x04],
             Self::Sz(sz) => sz.id(),
             Self::Misc(misc) => misc.id(),
             Self::Crypto(crypto) => crypto.id(),
+            Self::Custom(id) | Self::Unknown(id) => id,
+        }
+    }
+
+    /// Gets the name of this method.
+    ///
+    /// This is the same as the name used by 7-Zip.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Copy => "Copy",
+            Self::Delta => "Delta",
+            Self::Lzma2 => "LZMA2",
+            Self::Arm64 => "ARM64",
+            Self::Swap2 => "Swap2",
+            Self::Swap4 => "Swap4",
+            Self::Sz(sz) => sz.name(),
+            Self::Misc(misc) => misc.name(),
+            Self::Crypto(crypto) => crypto.name(),
+            Self::Custom(_) => "Custom",
+            Self::Unknown(_) => "Unknown",
+        }
+    }
+
+    /// Returns `true` if this method can be decoded by this build.
+    ///
+    /// The Zstandard, Brotli and LZ4 methods depend on the features of the
+    /// same names. The codecs registered in a [`CodecRegistry`] are not
+    /// considered.
+    ///
+    /// [`CodecRegistry`]: crate::CodecRegistry
+    #[must_use]
+    pub const fn can_decode(&self) -> bool {
+        self.can_encode() || matches!(self, Self::Crypto(Crypto::SzAes))
+    }
+
+    /// Returns `true` if this method can be encoded by this build.
+    ///
+    /// The Zstandard, Brotli and LZ4 methods depend on the features of the
+    /// same names. The codecs registered in a [`CodecRegistry`] are not
+    /// considered.
+    ///
+    /// [`CodecRegistry`]: crate::CodecRegistry
+    #[must_use]
+    pub const fn can_encode(&self) -> bool {
+        matches!(
+            self,
+            Self::Copy
+                | Self::Delta
+                | Self::Lzma2
+                | Self::Arm64
+                | Self::Swap2
+                | Self::Swap4
+                | Self::Sz(Sz::Lzma | Sz::Bcj | Sz::Ppc | Sz::Arm | Sz::ArmT | Sz::Sparc)
+        ) || self.is_enabled_by_feature()
+    }
+
+    /// Returns `true` if this method is supported by an enabled feature.
+    const fn is_enabled_by_feature(&self) -> bool {
+        (cfg!(feature = "zstd") && matches!(self, Self::Misc(Misc::Zstd)))
+            || (cfg!(feature = "brotli") && matches!(self, Self::Misc(Misc::Brotli)))
+            || (cfg!(feature = "lz4") && matches!(self, Self::Misc(Misc::Lz4)))
+    }
+
+    /// Returns the error that this method is not supported.
+    ///
+    /// The error of a method with a random ID or an unknown method carries the
+    /// ID instead of the name.
+    pub(crate) fn unsupported(&self) -> UnsupportedArchive {
+        match self {
+            Self::Custom(id) | Self::Unknown(id) => UnsupportedArchive::UnknownMethod(id.clone()),
+            method => UnsupportedArchive::CompressionMethod(method.name().to_string()),
         }
     }
 
-    #[allow(dead_code)]
     /// Gets the method associated with this ID.
     pub fn by_id(id: &[u8]) -> Option<Self> {
         match id {
             [0x00] => Some(Self::Copy),
             [0x03] => Some(Self::Delta),
             [0x21] => Some(Self::Lzma2),
+            [0x0a] => Some(Self::Arm64),
+            [0x02, 0x03, 0x02] => Some(Self::Swap2),
+            [0x02, 0x03, 0x04] => Some(Self::Swap4),
             sz @ [0x03, ..] => Sz::by_id(sz).map(Self::Sz),
             misc @ [0x04, ..] => Misc::by_id(misc).map(Self::Misc),
             crypto @ [0x06, ..] => Crypto::by_id(crypto).map(Self::Crypto),
+            custom @ [0x3f, _, _, _, _, _, _, _] => Some(Self::Custom(custom.to_vec())),
             _ => None,
         }
     }
+
+    /// Gets the method associated with this name, ignoring case.
+    #[must_use]
+    pub fn by_name(name: &str) -> Option<Self> {
+        [
+            Self::Copy,
+            Self::Delta,
+            Self::Lzma2,
+            Self::Arm64,
+            Self::Swap2,
+            Self::Swap4,
+            Self::Sz(Sz::Lzma),
+            Self::Sz(Sz::Bcj),
+            Self::Sz(Sz::Bcj2),
+            Self::Sz(Sz::Ppc),
+            Self::Sz(Sz::Ia64),
+            Self::Sz(Sz::Arm),
+            Self::Sz(Sz::ArmT),
+            Self::Sz(Sz::Sparc),
+            Self::Sz(Sz::Ppmd),
+            Self::Misc(Misc::Deflate),
+            Self::Misc(Misc::Deflate64),
+            Self::Misc(Misc::Bzip2),
+            Self::Misc(Misc::Zstd),
+            Self::Misc(Misc::Brotli),
+            Self::Misc(Misc::Lz4),
+            Self::Crypto(Crypto::SzAes),
+        ]
+        .into_iter()
+        .find(|method| method.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Gets the method associated with this ID, or [`Method::Unknown`] if the
+    /// ID is not known.
+    #[must_use]
+    pub fn from_id(id: &[u8]) -> Self {
+        Self::by_id(id).unwrap_or_else(|| Self::Unknown(id.to_vec()))
+    }
 }
 
-#[allow(dead_code)]
 /// Represents 7z methods.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Sz {
+    /// LZMA (`03 01 01`).
     Lzma,
+    /// x86 BCJ (`03 03 01 03`).
     Bcj,
+    /// x86 BCJ2 (`03 03 01 1B`).
     Bcj2,
+    /// PowerPC big-endian (`03 03 02 05`).
     Ppc,
+    /// IA-64 (`03 03 04 01`).
     Ia64,
+    /// ARM little-endian (`03 03 05 01`).
     Arm,
+    /// ARM Thumb little-endian (`03 03 07 01`).
     ArmT,
+    /// SPARC (`03 03 08 05`).
     Sparc,
+    /// `PPMd` (`03 04 01`).
     Ppmd,
 }
 
 impl Sz {
-    #[allow(dead_code)]
     /// Gets the ID of this method.
     const fn id(&self) -> &'static [u8] {
         match self {
@@ -120,7 +294,21 @@ impl Sz {
         }
     }
 
-    #[allow(dead_code)]
+    /// Gets the name of this method.
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Lzma => "LZMA",
+            Self::Bcj => "BCJ",
+            Self::Bcj2 => "BCJ2",
+            Self::Ppc => "PPC",
+            Self::Ia64 => "IA64",
+            Self::Arm => "ARM",
+            Self::ArmT => "ARMT",
+            Self::Sparc => "SPARC",
+            Self::Ppmd => "PPMD",
+        }
+    }
+
     /// Gets the method associated with this ID.
     const fn by_id(id: &[u8]) -> Option<Self> {
         match id {
@@ -138,47 +326,70 @@ impl Sz {
     }
 }
 
-#[allow(dead_code)]
 /// Represents misc methods.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Misc {
+    /// Deflate (`04 01 08`).
     Deflate,
+    /// Deflate64 (`04 01 09`).
     Deflate64,
+    /// `BZip2` (`04 02 02`).
     Bzip2,
+    /// Zstandard of 7-Zip-zstd (`04 F7 11 01`).
+    Zstd,
+    /// Brotli of 7-Zip-zstd (`04 F7 11 02`).
+    Brotli,
+    /// LZ4 of 7-Zip-zstd (`04 F7 11 04`).
+    Lz4,
 }
 
 impl Misc {
-    #[allow(dead_code)]
     /// Gets the ID of this method.
     const fn id(&self) -> &'static [u8] {
         match self {
             Self::Deflate => &[0x04, 0x01, 0x08],
             Self::Deflate64 => &[0x04, 0x01, 0x09],
             Self::Bzip2 => &[0x04, 0x02, 0x02],
+            Self::Zstd => &[0x04, 0xf7, 0x11, 0x01],
+            Self::Brotli => &[0x04, 0xf7, 0x11, 0x02],
+            Self::Lz4 => &[0x04, 0xf7, 0x11, 0x04],
+        }
+    }
+
+    /// Gets the name of this method.
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Deflate => "Deflate",
+            Self::Deflate64 => "Deflate64",
+            Self::Bzip2 => "BZip2",
+            Self::Zstd => "ZSTD",
+            Self::Brotli => "BROTLI",
+            Self::Lz4 => "LZ4",
         }
     }
 
-    #[allow(dead_code)]
     /// Gets the method associated with this ID.
     const fn by_id(id: &[u8]) -> Option<Self> {
         match id {
             [0x04, 0x01, 0x08] => Some(Self::Deflate),
             [0x04, 0x01, 0x09] => Some(Self::Deflate64),
             [0x04, 0x02, 0x02] => Some(Self::Bzip2),
+            [0x04, 0xf7, 0x11, 0x01] => Some(Self::Zstd),
+            [0x04, 0xf7, 0x11, 0x02] => Some(Self::Brotli),
+            [0x04, 0xf7, 0x11, 0x04] => Some(Self::Lz4),
             _ => None,
         }
     }
 }
 
-#[allow(dead_code)]
 /// Represents crypto methods.
-#[derive(Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Crypto {
+    /// 7zAES (`06 F1 07 01`).
     SzAes,
 }
 
 impl Crypto {
-    #[allow(dead_code)]
     /// Gets the ID of this method.
     const fn id(&self) -> &'static [u8] {
         match self {
@@ -186,7 +397,13 @@ impl Crypto {
         }
     }
 
-    #[allow(dead_code)]
+    /// Gets the name of this method.
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::SzAes => "7zAES",
+        }
+    }
+
     /// Gets the method associated with this ID.
     const fn by_id(id: &[u8]) -> Option<Self> {
         match id {
@@ -236,11 +453,23 @@ mod tests {
         assert_eq!(u8::from(Property::Dummy), 0x19);
     }
 
+    #[test]
+    fn get_property_by_id() {
+        for id in 0x00..=0x19 {
+            assert_eq!(u8::from(Property::by_id(id).unwrap()), id);
+        }
+        assert!(Property::by_id(0x1a).is_none());
+        assert!(Property::by_id(u8::MAX).is_none());
+    }
+
     #[test]
     fn get_method_id() {
         assert_eq!(Method::Copy.id(), [0x00]);
         assert_eq!(Method::Delta.id(), [0x03]);
         assert_eq!(Method::Lzma2.id(), [0x21]);
+        assert_eq!(Method::Arm64.id(), [0x0a]);
+        assert_eq!(Method::Swap2.id(), [0x02, 0x03, 0x02]);
+        assert_eq!(Method::Swap4.id(), [0x02, 0x03, 0x04]);
         assert_eq!(Method::Sz(Sz::Lzma).id(), [0x03, 0x01, 0x01]);
         assert_eq!(Method::Sz(Sz::Bcj).id(), [0x03, 0x03, 0x01, 0x03]);
         assert_eq!(Method::Sz(Sz::Bcj2).id(), [0x03, 0x03, 0x01, 0x1b]);
@@ -253,6 +482,9 @@ mod tests {
         assert_eq!(Method::Misc(Misc::Deflate).id(), [0x04, 0x01, 0x08]);
         assert_eq!(Method::Misc(Misc::Deflate64).id(), [0x04, 0x01, 0x09]);
         assert_eq!(Method::Misc(Misc::Bzip2).id(), [0x04, 0x02, 0x02]);
+        assert_eq!(Method::Misc(Misc::Zstd).id(), [0x04, 0xf7, 0x11, 0x01]);
+        assert_eq!(Method::Misc(Misc::Brotli).id(), [0x04, 0xf7, 0x11, 0x02]);
+        assert_eq!(Method::Misc(Misc::Lz4).id(), [0x04, 0xf7, 0x11, 0x04]);
         assert_eq!(Method::Crypto(Crypto::SzAes).id(), [0x06, 0xf1, 0x07, 0x01]);
     }
 
@@ -262,6 +494,15 @@ mod tests {
         assert!(matches!(Method::by_id(&[0x00]).unwrap(), Method::Copy));
         assert!(matches!(Method::by_id(&[0x03]).unwrap(), Method::Delta));
         assert!(matches!(Method::by_id(&[0x21]).unwrap(), Method::Lzma2));
+        assert!(matches!(Method::by_id(&[0x0a]).unwrap(), Method::Arm64));
+        assert!(matches!(
+            Method::by_id(&[0x02, 0x03, 0x02]).unwrap(),
+            Method::Swap2
+        ));
+        assert!(matches!(
+            Method::by_id(&[0x02, 0x03, 0x04]).unwrap(),
+            Method::Swap4
+        ));
         assert!(matches!(
             Method::by_id(&[0x03, 0x01, 0x01]).unwrap(),
             Method::Sz(Sz::Lzma)
@@ -310,14 +551,119 @@ mod tests {
             Method::by_id(&[0x04, 0x02, 0x02]).unwrap(),
             Method::Misc(Misc::Bzip2)
         ));
+        assert!(matches!(
+            Method::by_id(&[0x04, 0xf7, 0x11, 0x01]).unwrap(),
+            Method::Misc(Misc::Zstd)
+        ));
+        assert!(matches!(
+            Method::by_id(&[0x04, 0xf7, 0x11, 0x02]).unwrap(),
+            Method::Misc(Misc::Brotli)
+        ));
+        assert!(matches!(
+            Method::by_id(&[0x04, 0xf7, 0x11, 0x04]).unwrap(),
+            Method::Misc(Misc::Lz4)
+        ));
         assert!(matches!(
             Method::by_id(&[0x06, 0xf1, 0x07, 0x01]).unwrap(),
             Method::Crypto(Crypto::SzAes)
         ));
     }
 
+    #[test]
+    fn get_method_name() {
+        assert_eq!(Method::Copy.name(), "Copy");
+        assert_eq!(Method::Delta.name(), "Delta");
+        assert_eq!(Method::Lzma2.name(), "LZMA2");
+        assert_eq!(Method::Arm64.name(), "ARM64");
+        assert_eq!(Method::Swap2.name(), "Swap2");
+        assert_eq!(Method::Swap4.name(), "Swap4");
+        assert_eq!(Method::Sz(Sz::Lzma).name(), "LZMA");
+        assert_eq!(Method::Sz(Sz::Bcj).name(), "BCJ");
+        assert_eq!(Method::Sz(Sz::Bcj2).name(), "BCJ2");
+        assert_eq!(Method::Sz(Sz::Ppc).name(), "PPC");
+        assert_eq!(Method::Sz(Sz::Ia64).name(), "IA64");
+        assert_eq!(Method::Sz(Sz::Arm).name(), "ARM");
+        assert_eq!(Method::Sz(Sz::ArmT).name(), "ARMT");
+        assert_eq!(Method::Sz(Sz::Sparc).name(), "SPARC");
+        assert_eq!(Method::Sz(Sz::Ppmd).name(), "PPMD");
+        assert_eq!(Method::Misc(Misc::Deflate).name(), "Deflate");
+        assert_eq!(Method::Misc(Misc::Deflate64).name(), "Deflate64");
+        assert_eq!(Method::Misc(Misc::Bzip2).name(), "BZip2");
+        assert_eq!(Method::Misc(Misc::Zstd).name(), "ZSTD");
+        assert_eq!(Method::Misc(Misc::Brotli).name(), "BROTLI");
+        assert_eq!(Method::Misc(Misc::Lz4).name(), "LZ4");
+        assert_eq!(Method::Crypto(Crypto::SzAes).name(), "7zAES");
+    }
+
     #[test]
     fn get_method_by_id_with_bad_id() {
         assert!(Method::by_id(&[u8::MAX]).is_none());
+        assert!(Method::by_id(&[0x3f, 0x01, 0x02]).is_none());
+    }
+
+    #[test]
+    fn get_custom_method() {
+        let id = [0x3f, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x01];
+        let method = Method::by_id(&id).unwrap();
+        assert_eq!(method, Method::Custom(id.to_vec()));
+        assert_eq!(method.id(), id);
+        assert_eq!(method.name(), "Custom");
+    }
+
+    #[test]
+    fn get_method_by_name() {
+        assert_eq!(Method::by_name("LZMA2"), Some(Method::Lzma2));
+        assert_eq!(Method::by_name("lzma2"), Some(Method::Lzma2));
+        assert_eq!(Method::by_name("PPMd"), Some(Method::Sz(Sz::Ppmd)));
+        assert_eq!(
+            Method::by_name("7zAES"),
+            Some(Method::Crypto(Crypto::SzAes))
+        );
+        assert_eq!(Method::by_name("Custom"), None);
+        assert_eq!(Method::by_name("LZMA3"), None);
+    }
+
+    #[test]
+    fn method_capabilities() {
+        for method in [Method::Copy, Method::Lzma2, Method::Sz(Sz::Lzma)] {
+            assert!(method.can_decode());
+        }
+        assert!(Method::Crypto(Crypto::SzAes).can_decode());
+        assert!(!Method::Crypto(Crypto::SzAes).can_encode());
+        assert!(Method::Swap4.can_encode());
+        assert!(Method::Lzma2.can_encode());
+        assert!(Method::Sz(Sz::Bcj).can_encode());
+        assert!(Method::Arm64.can_decode());
+        assert!(!Method::Sz(Sz::Ia64).can_decode());
+        assert!(!Method::Sz(Sz::Bcj2).can_decode());
+        assert!(!Method::Misc(Misc::Bzip2).can_decode());
+        assert!(!Method::Custom(vec![0x3f; 8]).can_decode());
+        assert!(!Method::Unknown(vec![0x7f]).can_encode());
+        assert_eq!(
+            Method::Misc(Misc::Zstd).can_decode(),
+            cfg!(feature = "zstd")
+        );
+        assert_eq!(Method::Misc(Misc::Lz4).can_encode(), cfg!(feature = "lz4"));
+    }
+
+    #[test]
+    fn get_unknown_method() {
+        let method = Method::from_id(&[0x7f, 0x01]);
+        assert_eq!(method, Method::Unknown(vec![0x7f, 0x01]));
+        assert_eq!(method.id(), [0x7f, 0x01]);
+        assert_eq!(method.name(), "Unknown");
+        assert_eq!(Method::from_id(&[0x21]), Method::Lzma2);
+    }
+
+    #[test]
+    fn unsupported_method() {
+        assert!(matches!(
+            Method::Misc(Misc::Bzip2).unsupported(),
+            UnsupportedArchive::CompressionMethod(name) if name == "BZip2"
+        ));
+        assert!(matches!(
+            Method::Unknown(vec![0x7f, 0x01]).unsupported(),
+            UnsupportedArchive::UnknownMethod(id) if id == [0x7f, 0x01]
+        ));
     }
 }
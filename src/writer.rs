@@ -0,0 +1,1876 @@
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+// Copyright (C) 2021-2023 Shun Sakai
+//
+
+//! 7z archive writer.
+
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    path::Path,
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    analysis::{self, HEAD_SIZE},
+    attributes::{FileType, WinAttributes},
+    codec::CodecRegistry,
+    encoder::{self, Encoder},
+    error::UnsupportedArchive,
+    folder::FolderLayout,
+    header::{BindPair, Coder, Folder, Header, StartHeader, StreamsInfo, SIGNATURE_HEADER_SIZE},
+    method::{Method, MethodChain, MethodSpec, Param, Sz},
+    property::Property,
+    CompressionLevel, Entry, Result,
+};
+
+/// The options to group files into solid blocks.
+///
+/// A solid block is a folder which contains the data of many files, which are
+/// compressed together. These options correspond to the `-ms` switch of 7-Zip,
+/// and the limits can be combined. The default is [`SolidOptions::off`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SolidOptions {
+    max_size: Option<u64>,
+    max_files: Option<usize>,
+    per_extension: bool,
+}
+
+impl SolidOptions {
+    /// Returns the options which put each file into its own block, as
+    /// `-ms=off`.
+    #[must_use]
+    pub const fn off() -> Self {
+        Self::on().max_files(1)
+    }
+
+    /// Returns the options which put all the files into a block, as `-ms=on`.
+    #[must_use]
+    pub const fn on() -> Self {
+        Self {
+            max_size: None,
+            max_files: None,
+            per_extension: false,
+        }
+    }
+
+    /// Starts a new block after the data of a block reach `size` bytes, as
+    /// `-ms=64m`.
+    ///
+    /// Since the size of a file is not known in advance, the last file of a
+    /// block may exceed the limit.
+    #[must_use]
+    pub const fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Starts a new block after a block contains `count` files, as
+    /// `-ms=100f`.
+    ///
+    /// A block always contains at least 1 file.
+    #[must_use]
+    pub const fn max_files(mut self, count: usize) -> Self {
+        self.max_files = Some(count);
+        self
+    }
+
+    /// Starts a new block for each new file extension, as `-ms=e`.
+    ///
+    /// The files are written in the order they are added, so the files should
+    /// be added grouped by extension.
+    #[must_use]
+    pub const fn per_extension(mut self) -> Self {
+        self.per_extension = true;
+        self
+    }
+}
+
+impl Default for SolidOptions {
+    fn default() -> Self {
+        Self::off()
+    }
+}
+
+/// The kinds of timestamps to store.
+///
+/// These options correspond to the `-mtm`, `-mtc` and `-mta` switches of
+/// 7-Zip. The timestamps which are not stored are removed from the entries
+/// when they are added. The default is [`Timestamps::all`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timestamps {
+    modified: bool,
+    created: bool,
+    accessed: bool,
+}
+
+impl Timestamps {
+    /// Returns the options which store all the timestamps.
+    #[must_use]
+    pub const fn all() -> Self {
+        Self {
+            modified: true,
+            created: true,
+            accessed: true,
+        }
+    }
+
+    /// Returns the options which store no timestamps.
+    #[must_use]
+    pub const fn none() -> Self {
+        Self {
+            modified: false,
+            created: false,
+            accessed: false,
+        }
+    }
+
+    /// Sets whether to store the last modification time, as `-mtm`.
+    #[must_use]
+    pub const fn modified(mut self, enabled: bool) -> Self {
+        self.modified = enabled;
+        self
+    }
+
+    /// Sets whether to store the creation time, as `-mtc`.
+    #[must_use]
+    pub const fn created(mut self, enabled: bool) -> Self {
+        self.created = enabled;
+        self
+    }
+
+    /// Sets whether to store the last access time, as `-mta`.
+    #[must_use]
+    pub const fn accessed(mut self, enabled: bool) -> Self {
+        self.accessed = enabled;
+        self
+    }
+
+    /// Removes the timestamps which are not stored from `entry`.
+    fn apply(self, entry: &mut Entry) {
+        if !self.modified {
+            entry.mtime = None;
+        }
+        if !self.created {
+            entry.ctime = None;
+        }
+        if !self.accessed {
+            entry.atime = None;
+        }
+    }
+}
+
+impl Default for Timestamps {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Returns [`Err`] if the coders of `layout` do not form a chain, which is the
+/// only layout that can be written.
+fn check_chain(layout: &FolderLayout) -> Result<()> {
+    if layout.is_chain() {
+        Ok(())
+    } else {
+        Err(UnsupportedArchive::FolderLayout.into())
+    }
+}
+
+/// Returns the default layout to compress the header, which is the same as
+/// 7-Zip.
+fn default_header_layout() -> FolderLayout {
+    let mut spec = MethodSpec::new(Method::Sz(Sz::Lzma));
+    spec.params = vec![Param::new("", "20"), Param::new("fb", "254")];
+    MethodChain::from(spec).into()
+}
+
+/// Returns the extension of the file name in lowercase, if any.
+fn extension(name: &str) -> Option<String> {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    name.rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .map(|(_, extension)| extension.to_lowercase())
+}
+
+/// Returns `true` if `name` matches the glob `pattern`.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        ['*', '*', '/', rest @ ..] if glob_matches(rest, name) => true,
+        ['*', '*', rest @ ..] => (0..=name.len()).any(|i| glob_matches(rest, &name[i..])),
+        ['*', rest @ ..] => (0..=name.len())
+            .take_while(|&i| i == 0 || name[i - 1] != '/')
+            .any(|i| glob_matches(rest, &name[i..])),
+        ['?', rest @ ..] => {
+            matches!(name, [c, name @ ..] if *c != '/' && glob_matches(rest, name))
+        }
+        [p, rest @ ..] => matches!(name, [c, name @ ..] if c == p && glob_matches(rest, name)),
+    }
+}
+
+/// The condition of a [`MethodRule`].
+#[derive(Clone)]
+enum Condition {
+    Extensions(Vec<String>),
+    Glob(Vec<char>),
+    Filter(Arc<dyn Fn(&Entry) -> bool + Send + Sync>),
+}
+
+/// A rule to select the methods to compress a file.
+///
+/// The rules are added by [`ArchiveWriter::add_rule`]. The methods of the first
+/// rule which matches a file are used instead of the methods set by
+/// [`ArchiveWriter::set_method`]. The methods are given as a [`Method`], a
+/// [`MethodChain`] or a [`FolderLayout`].
+#[derive(Clone)]
+pub struct MethodRule {
+    condition: Condition,
+    layout: FolderLayout,
+}
+
+impl MethodRule {
+    /// Creates a new `MethodRule` which matches the files for which `filter`
+    /// returns `true`.
+    pub fn new<F>(filter: F, methods: impl Into<FolderLayout>) -> Self
+    where
+        F: Fn(&Entry) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            condition: Condition::Filter(Arc::new(filter)),
+            layout: methods.into(),
+        }
+    }
+
+    /// Creates a new `MethodRule` which matches the files with one of
+    /// `extensions`, ignoring case.
+    ///
+    /// The extensions are given without the leading dot, such as `jpg`.
+    pub fn extensions<I>(extensions: I, methods: impl Into<FolderLayout>) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let extensions = extensions
+            .into_iter()
+            .map(|extension| extension.as_ref().trim_start_matches('.').to_lowercase())
+            .collect();
+        Self {
+            condition: Condition::Extensions(extensions),
+            layout: methods.into(),
+        }
+    }
+
+    /// Creates a new `MethodRule` which matches the files whose path matches
+    /// the glob `pattern`.
+    ///
+    /// `*` matches any characters except `/`, `**` matches any characters and
+    /// `?` matches a character except `/`. If `pattern` does not contain `/`,
+    /// it is matched with the file name instead of the path.
+    pub fn glob(pattern: &str, methods: impl Into<FolderLayout>) -> Self {
+        Self {
+            condition: Condition::Glob(pattern.chars().collect()),
+            layout: methods.into(),
+        }
+    }
+
+    /// Returns `true` if this rule matches the file.
+    #[must_use]
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match &self.condition {
+            Condition::Extensions(extensions) => {
+                extension(entry.name()).map_or(false, |extension| extensions.contains(&extension))
+            }
+            Condition::Glob(pattern) => {
+                let name = if pattern.contains(&'/') {
+                    entry.name()
+                } else {
+                    entry.name().rsplit('/').next().unwrap_or_default()
+                };
+                glob_matches(pattern, &name.chars().collect::<Vec<_>>())
+            }
+            Condition::Filter(filter) => filter(entry),
+        }
+    }
+
+    /// Returns the layout of the coders to compress the files which match
+    /// this rule.
+    #[must_use]
+    pub const fn layout(&self) -> &FolderLayout {
+        &self.layout
+    }
+}
+
+impl fmt::Debug for MethodRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("MethodRule");
+        match &self.condition {
+            Condition::Extensions(extensions) => f.field("extensions", extensions),
+            Condition::Glob(pattern) => f.field("glob", &pattern.iter().collect::<String>()),
+            Condition::Filter(_) => f.field("filter", &format_args!("..")),
+        };
+        f.field("methods", &self.layout.methods().to_string())
+            .finish()
+    }
+}
+
+/// A buffer which receives the packed data from an encoder.
+///
+/// The data are moved to the underlying writer of the archive after each
+/// write, so the encoder does not borrow it.
+#[derive(Clone, Default)]
+struct PackBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl PackBuffer {
+    /// Takes the data written so far.
+    fn take(&self) -> Vec<u8> {
+        mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+impl Write for PackBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The state of a [`Stage`].
+struct StageState {
+    encoder: Option<Box<dyn Encoder>>,
+    size: u64,
+}
+
+/// A coder of the folder which is being written.
+///
+/// The encoder of each coder writes into the stage of the coder which is
+/// decoded before it, and the size of the data written into a stage is the
+/// unpack size of the coder.
+#[derive(Clone)]
+struct Stage(Rc<RefCell<StageState>>);
+
+impl Stage {
+    /// Creates a new `Stage` which writes the data into `encoder`.
+    fn new(encoder: Box<dyn Encoder>) -> Self {
+        Self(Rc::new(RefCell::new(StageState {
+            encoder: Some(encoder),
+            size: u64::default(),
+        })))
+    }
+
+    /// Returns the size of the data written so far.
+    fn size(&self) -> u64 {
+        self.0.borrow().size
+    }
+
+    /// Finishes the encoder.
+    fn finish(&self) -> io::Result<()> {
+        let encoder = self.0.borrow_mut().encoder.take();
+        encoder.map_or(Ok(()), Encoder::finish)
+    }
+}
+
+impl Write for Stage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let state = &mut *self.0.borrow_mut();
+        let encoder = state
+            .encoder
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "the coder is finished"))?;
+        let n = encoder.write(buf)?;
+        state.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .borrow_mut()
+            .encoder
+            .as_mut()
+            .map_or(Ok(()), Write::flush)
+    }
+}
+
+/// A folder which is being written.
+///
+/// The properties and the stages of the coders are in the order of decoding,
+/// and the data are written into the last stage.
+struct OpenFolder {
+    layout: FolderLayout,
+    order: Vec<usize>,
+    level: CompressionLevel,
+    extension: Option<String>,
+    properties: Vec<Vec<u8>>,
+    stages: Vec<Stage>,
+    buffer: PackBuffer,
+    pack_size: u64,
+    unpack_size: u64,
+    num_streams: usize,
+}
+
+impl fmt::Debug for OpenFolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenFolder")
+            .field("layout", &self.layout)
+            .field("level", &self.level)
+            .field("extension", &self.extension)
+            .field("pack_size", &self.pack_size)
+            .field("unpack_size", &self.unpack_size)
+            .field("num_streams", &self.num_streams)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A 7z archive writer.
+///
+/// The entries are written in the order they are added. Each file is
+/// compressed into its own folder with the method of
+/// [`CompressionLevel::NORMAL`] by default, which is the default of 7-Zip. The
+/// folders can be changed by [`ArchiveWriter::set_solid`]. The files added in a row which are
+/// compressed with the same methods can share a folder, so the files should be
+/// added grouped by the methods. The archive is not valid until
+/// [`ArchiveWriter::finish`] is called.
+#[derive(Debug)]
+pub struct ArchiveWriter<W> {
+    writer: W,
+    start: u64,
+    layout: FolderLayout,
+    rules: Vec<MethodRule>,
+    level: CompressionLevel,
+    codecs: CodecRegistry,
+    solid: SolidOptions,
+    detect_filters: bool,
+    detect_incompressible: bool,
+    header_layout: Option<FolderLayout>,
+    timestamps: Timestamps,
+    follow_symlinks: bool,
+    folder: Option<OpenFolder>,
+    streams_info: StreamsInfo,
+    entries: Vec<Entry>,
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    /// Creates a new `ArchiveWriter` which writes an archive into `writer`.
+    ///
+    /// The archive starts at the current position of `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs.
+    pub fn new(mut writer: W) -> Result<Self> {
+        let start = writer.stream_position()?;
+        // The signature header is written by `ArchiveWriter::finish`.
+        writer.write_all(&[u8::default(); 32])?;
+        Ok(Self {
+            writer,
+            start,
+            layout: MethodChain::from(CompressionLevel::NORMAL.method()).into(),
+            rules: Vec::new(),
+            level: CompressionLevel::NORMAL,
+            codecs: CodecRegistry::default(),
+            solid: SolidOptions::default(),
+            detect_filters: true,
+            detect_incompressible: true,
+            header_layout: Some(default_header_layout()),
+            timestamps: Timestamps::default(),
+            follow_symlinks: true,
+            folder: None,
+            streams_info: StreamsInfo::default(),
+            entries: Vec::new(),
+        })
+    }
+
+    /// Sets the method to compress the files added after this.
+    ///
+    /// The default is `LZMA2:d24:fb32:mf=bt4`, the method of
+    /// [`CompressionLevel::NORMAL`].
+    pub fn set_method(&mut self, method: Method) -> &mut Self {
+        self.layout = method.into();
+        self
+    }
+
+    /// Sets the methods to compress the files added after this.
+    ///
+    /// The methods are in the order of decoding, so `LZMA2 Delta:4` applies
+    /// the Delta filter before LZMA2. An empty chain is the same as Copy.
+    pub fn set_method_chain(&mut self, methods: MethodChain) -> &mut Self {
+        self.layout = methods.into();
+        self
+    }
+
+    /// Sets the layout of the coders to compress the files added after this.
+    ///
+    /// Only the layouts whose coders form a chain can be written. Whether the
+    /// methods are supported for encoding is checked when a file is added.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the coders of `layout` do not form a chain.
+    pub fn set_folder_layout(&mut self, layout: FolderLayout) -> Result<&mut Self> {
+        check_chain(&layout)?;
+        self.layout = layout;
+        Ok(self)
+    }
+
+    /// Adds a rule to select the methods to compress the files added after
+    /// this.
+    ///
+    /// The rules are checked in the order they are added, and the files which
+    /// match no rule are compressed with the methods set by
+    /// [`ArchiveWriter::set_method`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if the coders of the layout of `rule` do not form a
+    /// chain.
+    pub fn add_rule(&mut self, rule: MethodRule) -> Result<&mut Self> {
+        check_chain(rule.layout())?;
+        self.rules.push(rule);
+        Ok(self)
+    }
+
+    /// Sets the compression level which is passed to the encoders of the
+    /// methods, such as the preset of LZMA2 or the level of Zstandard.
+    ///
+    /// Unlike [`ArchiveWriter::set_compression_level`], this does not change
+    /// the methods and the solid blocks. The level is clamped to the range
+    /// supported by the method. The default is [`CompressionLevel::NORMAL`].
+    pub fn set_level(&mut self, level: CompressionLevel) -> &mut Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets the methods, the compression level and the solid blocks for the
+    /// files added after this as the `-mx` switch of 7-Zip does.
+    ///
+    /// This overrides the settings of [`ArchiveWriter::set_method`],
+    /// [`ArchiveWriter::set_level`] and [`ArchiveWriter::set_solid`], which can
+    /// be called after this to change them.
+    pub fn set_compression_level(&mut self, level: CompressionLevel) -> &mut Self {
+        self.level = level;
+        self.solid = level.solid_options();
+        self.set_method_chain(level.method().into())
+    }
+
+    /// Sets the codecs which are used in preference to the built-in methods.
+    pub fn set_codecs(&mut self, codecs: CodecRegistry) -> &mut Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Sets the options to group the files added after this into solid
+    /// blocks.
+    ///
+    /// The default is [`SolidOptions::off`].
+    pub fn set_solid(&mut self, solid: SolidOptions) -> &mut Self {
+        self.solid = solid;
+        self
+    }
+
+    /// Sets whether to select a filter for each file from the first bytes of
+    /// the data, as 7-Zip does.
+    ///
+    /// If enabled, a branch converter is applied to executables and the Delta
+    /// filter is applied to WAV files of PCM. The filter is only selected for
+    /// the files which are compressed with a single method other than Copy and
+    /// match no rule. The default is `true`.
+    pub fn set_filter_detection(&mut self, enabled: bool) -> &mut Self {
+        self.detect_filters = enabled;
+        self
+    }
+
+    /// Sets whether to store the files whose data seem not to be compressible
+    /// with Copy.
+    ///
+    /// If enabled, the entropy of the first bytes of each file is estimated,
+    /// and the files such as JPEG images and ZIP archives are not compressed
+    /// to save time. This only applies to the files which match no rule. The
+    /// default is `true`.
+    pub fn set_incompressible_detection(&mut self, enabled: bool) -> &mut Self {
+        self.detect_incompressible = enabled;
+        self
+    }
+
+    /// Sets the methods to compress the header, which contains the names and
+    /// the attributes of the files.
+    ///
+    /// The compressed header is written as an encoded header. [`None`] writes
+    /// the header without compression. The default is `LZMA:20:fb254`, as
+    /// 7-Zip does.
+    pub fn set_header_compression(&mut self, methods: Option<MethodChain>) -> &mut Self {
+        self.header_layout = methods.map(Into::into);
+        self
+    }
+
+    /// Sets the kinds of timestamps to store for the entries added after
+    /// this.
+    ///
+    /// The default is [`Timestamps::all`].
+    pub fn set_timestamps(&mut self, timestamps: Timestamps) -> &mut Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Sets whether [`ArchiveWriter::add_path`] follows symbolic links.
+    ///
+    /// If `false`, a symbolic link is stored as a symbolic link, as
+    /// [`ArchiveWriter::add_symlink`] does. The default is `true`, which stores
+    /// the file the link points to.
+    pub fn set_follow_symlinks(&mut self, follow: bool) -> &mut Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Starts a file whose data are written into the returned writer.
+    ///
+    /// The size and the CRC of the data are computed while they are written,
+    /// so they need not be known in advance. The file is added when
+    /// [`EntryWriter::finish`] is called or the writer is dropped. If no data
+    /// are written, the file is added as an empty file which has no stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if a method is not supported for encoding, or if a
+    /// parameter of a method is invalid.
+    pub fn start_entry(&mut self, mut entry: Entry) -> Result<EntryWriter<'_, W>> {
+        let rule = self.rules.iter().find(|rule| rule.matches(&entry));
+        let layout = rule.map_or(&self.layout, MethodRule::layout).clone();
+        let analyzes = rule.is_none()
+            && layout
+                .coders()
+                .iter()
+                .any(|coder| coder.method.method != Method::Copy)
+            && (self.detect_incompressible || (self.detect_filters && layout.coders().len() == 1));
+        self.check_layout(&layout)?;
+        self.timestamps.apply(&mut entry);
+        entry.is_dir = false;
+        Ok(EntryWriter {
+            archive: self,
+            entry: Some(entry),
+            layout,
+            head: analyzes.then(Vec::new),
+            hasher: crc32fast::Hasher::new(),
+            size: u64::default(),
+            failed: false,
+        })
+    }
+
+    /// Adds a file with the data read from `reader`.
+    ///
+    /// If `reader` has no data, the file is added as an empty file which has
+    /// no stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs, or if the method is not
+    /// supported for encoding.
+    pub fn add_file<R: Read>(&mut self, entry: Entry, mut reader: R) -> Result<()> {
+        let mut writer = self.start_entry(entry)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.finish()
+    }
+
+    /// Adds a directory.
+    ///
+    /// If the attributes of `entry` are not set, the directory attribute is
+    /// set.
+    pub fn add_dir(&mut self, mut entry: Entry) {
+        self.timestamps.apply(&mut entry);
+        entry.is_dir = true;
+        entry.has_stream = false;
+        if entry.attributes.is_none() {
+            entry.attributes = Some(WinAttributes::DIRECTORY);
+        }
+        self.entries.push(entry);
+    }
+
+    /// Adds a symbolic link to `target`.
+    ///
+    /// The link is stored as p7zip does: the file type of the Unix mode is a
+    /// symbolic link, and the data are the target. The permission bits of
+    /// `entry` are kept, and are `0o777` if it has no Unix mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs, or if the method is not
+    /// supported for encoding.
+    pub fn add_symlink(&mut self, mut entry: Entry, target: &str) -> Result<()> {
+        let permissions = entry.unix_mode().map_or(0o777, |mode| mode & 0o7777);
+        entry.set_unix_mode(Some(FileType::Symlink.unix_bits() | permissions));
+        entry.symlink_target = Some(target.into());
+        self.add_file(entry, target.as_bytes())
+    }
+
+    /// Adds the file or the directory at `path` with `name`.
+    ///
+    /// The timestamps and the attributes are read from the file system as
+    /// [`Entry::from_metadata`] does. A directory is added without its
+    /// contents. A symbolic link is followed unless
+    /// [`ArchiveWriter::set_follow_symlinks`] disabled it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs, or if the method is not
+    /// supported for encoding.
+    pub fn add_path(&mut self, name: impl Into<String>, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let metadata = if self.follow_symlinks {
+            fs::metadata(path)?
+        } else {
+            fs::symlink_metadata(path)?
+        };
+        let entry = Entry::from_metadata(name, &metadata);
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            let target = target.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the target of the symbolic link is not valid UTF-8",
+                )
+            })?;
+            self.add_symlink(entry, target)
+        } else if metadata.is_dir() {
+            self.add_dir(entry);
+            Ok(())
+        } else {
+            self.add_file(entry, File::open(path)?)
+        }
+    }
+
+    /// Writes the header and the signature header, returning the underlying
+    /// writer.
+    ///
+    /// The returned writer is positioned at the end of the archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs.
+    pub fn finish(mut self) -> Result<W> {
+        self.close_folder()?;
+        let mut buf = Vec::new();
+        if !self.entries.is_empty() {
+            let header = Header {
+                main_streams_info: mem::take(&mut self.streams_info),
+                entries: mem::take(&mut self.entries),
+            };
+            header.write(&mut buf)?;
+            if let Some(layout) = self.header_layout.take() {
+                buf = self.encode_header(&buf, &layout)?;
+            }
+        }
+        let header_pos = self.writer.stream_position()?;
+        self.writer.write_all(&buf)?;
+
+        let start_header = StartHeader {
+            offset: header_pos - self.start - SIGNATURE_HEADER_SIZE,
+            size: buf.len() as u64,
+            crc: crc32fast::hash(&buf),
+        };
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        start_header.write(&mut self.writer)?;
+        self.writer
+            .seek(SeekFrom::Start(header_pos + buf.len() as u64))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Returns [`Err`] if the coders of `layout` cannot be written.
+    fn check_layout(&self, layout: &FolderLayout) -> Result<()> {
+        layout.coders().iter().try_for_each(|coder| {
+            let spec = &coder.method;
+            if !self.codecs.can_encode(&spec.method) {
+                return Err(spec.method.unsupported().into());
+            }
+            encoder::check_params(spec, self.level.get().into(), &self.codecs)
+        })
+    }
+
+    /// Compresses the header into a folder, and returns the encoded header
+    /// which points to it.
+    fn encode_header(&mut self, header: &[u8], layout: &FolderLayout) -> Result<Vec<u8>> {
+        self.check_layout(layout)?;
+        let pack_pos = self.writer.stream_position()? - self.start - SIGNATURE_HEADER_SIZE;
+        // The header does not depend on the compression level of the files.
+        self.level = CompressionLevel::NORMAL;
+        self.open_folder(layout, None)?;
+        self.write_data(header)?;
+        self.close_folder()?;
+
+        let crc = crc32fast::hash(header);
+        let mut streams_info = mem::take(&mut self.streams_info);
+        streams_info.pack_info.pack_pos = pack_pos;
+        streams_info.folders[0].crc = Some(crc);
+        streams_info.substreams.num_unpack_streams = vec![1];
+        streams_info.substreams.unpack_sizes = vec![header.len() as u64];
+        streams_info.substreams.crcs = vec![Some(crc)];
+        let mut buf = vec![Property::EncodedHeader.into()];
+        streams_info.write(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Prepares the folder into which the data of the file are written.
+    ///
+    /// The folder which is being written is finished if the file does not fit
+    /// into it, and a new folder is opened if there is none.
+    fn start_stream(&mut self, entry: &Entry, layout: &FolderLayout) -> Result<()> {
+        if let Some(folder) = &self.folder {
+            let solid = &self.solid;
+            if folder.layout != *layout
+                || folder.level != self.level
+                || solid.max_files.map_or(false, |n| folder.num_streams >= n)
+                || solid.max_size.map_or(false, |n| folder.unpack_size >= n)
+                || (solid.per_extension && folder.extension != extension(&entry.name))
+            {
+                self.close_folder()?;
+            }
+        }
+        if self.folder.is_none() {
+            self.open_folder(layout, extension(&entry.name))?;
+        }
+        Ok(())
+    }
+
+    /// Opens a new folder which compresses the data with `layout`.
+    fn open_folder(&mut self, layout: &FolderLayout, extension: Option<String>) -> Result<()> {
+        let buffer = PackBuffer::default();
+        let order = layout.decoding_order();
+        let mut properties = Vec::with_capacity(order.len());
+        let mut stages = Vec::<Stage>::with_capacity(order.len());
+        let level = self.level.get().into();
+        for &i in &order {
+            let spec = &layout.coders()[i].method;
+            let (props, encoder) = match stages.last() {
+                Some(stage) => encoder::encoder(spec, level, stage.clone(), &self.codecs)?,
+                None => encoder::encoder(spec, level, buffer.clone(), &self.codecs)?,
+            };
+            properties.push(props);
+            stages.push(Stage::new(encoder));
+        }
+        self.folder = Some(OpenFolder {
+            layout: layout.clone(),
+            order,
+            level: self.level,
+            extension,
+            properties,
+            stages,
+            buffer,
+            pack_size: u64::default(),
+            unpack_size: u64::default(),
+            num_streams: usize::default(),
+        });
+        Ok(())
+    }
+
+    /// Writes the data into the folder which is being written.
+    fn write_data(&mut self, buf: &[u8]) -> Result<()> {
+        let folder = self.folder.as_mut().ok_or_else(no_folder_error)?;
+        folder
+            .stages
+            .last_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "the folder has no coder"))?
+            .write_all(buf)?;
+        let packed = folder.buffer.take();
+        folder.pack_size += packed.len() as u64;
+        folder.unpack_size += buf.len() as u64;
+        self.writer.write_all(&packed)?;
+        Ok(())
+    }
+
+    /// Adds the file whose data were written into the folder which is being
+    /// written.
+    fn add_stream(&mut self, mut entry: Entry, size: u64, crc: u32) -> Result<()> {
+        let folder = self.folder.as_mut().ok_or_else(no_folder_error)?;
+        entry.has_stream = true;
+        entry.size = size;
+        entry.crc = Some(crc);
+        entry.folder = Some(self.streams_info.folders.len());
+        entry.offset = folder.unpack_size - size;
+        folder.num_streams += 1;
+        let substreams = &mut self.streams_info.substreams;
+        substreams.unpack_sizes.push(size);
+        substreams.crcs.push(Some(crc));
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Finishes the folder which is being written, if any.
+    fn close_folder(&mut self) -> Result<()> {
+        let mut folder = match self.folder.take() {
+            Some(folder) => folder,
+            None => return Ok(()),
+        };
+        for stage in folder.stages.iter().rev() {
+            stage.finish()?;
+        }
+        let packed = folder.buffer.take();
+        folder.pack_size += packed.len() as u64;
+        self.writer.write_all(&packed)?;
+
+        let streams_info = &mut self.streams_info;
+        streams_info.pack_info.pack_sizes.push(folder.pack_size);
+        streams_info.pack_info.crcs.push(None);
+        // Each coder has an output stream, so the unpack sizes are in the
+        // order of the coders.
+        let layout = &folder.layout;
+        let mut coders = layout
+            .coders()
+            .iter()
+            .map(|coder| Coder {
+                method: coder.method.method.clone(),
+                num_in_streams: 1,
+                num_out_streams: 1,
+                properties: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut unpack_sizes = vec![u64::default(); coders.len()];
+        for ((&i, properties), stage) in folder
+            .order
+            .iter()
+            .zip(folder.properties)
+            .zip(&folder.stages)
+        {
+            coders[i].properties = properties;
+            unpack_sizes[i] = stage.size();
+        }
+        streams_info.folders.push(Folder {
+            coders,
+            bind_pairs: layout
+                .bind_pairs()
+                .iter()
+                .map(|&(in_index, out_index)| BindPair {
+                    in_index,
+                    out_index,
+                })
+                .collect(),
+            packed_streams: layout.packed_streams().to_vec(),
+            unpack_sizes,
+            crc: None,
+        });
+        streams_info
+            .substreams
+            .num_unpack_streams
+            .push(folder.num_streams);
+        Ok(())
+    }
+}
+
+/// A writer which writes the data of a file into an archive.
+///
+/// This is returned by [`ArchiveWriter::start_entry`]. The file is added when
+/// [`EntryWriter::finish`] is called. If this is dropped without calling it,
+/// the file is added but the errors are ignored. Once writing the data failed,
+/// the file is never added.
+#[derive(Debug)]
+pub struct EntryWriter<'a, W: Write + Seek> {
+    archive: &'a mut ArchiveWriter<W>,
+    entry: Option<Entry>,
+    layout: FolderLayout,
+    head: Option<Vec<u8>>,
+    hasher: crc32fast::Hasher,
+    size: u64,
+    failed: bool,
+}
+
+impl<W: Write + Seek> EntryWriter<'_, W> {
+    /// Adds the file with the data written so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if an I/O error occurs, or if writing the data failed
+    /// before.
+    pub fn finish(mut self) -> Result<()> {
+        self.close()?;
+        self.flush().map_err(Into::into)
+    }
+
+    /// Selects the methods from the first bytes of the data, and writes them
+    /// into the folder.
+    fn write_head(&mut self) -> Result<()> {
+        let head = match self.head.take() {
+            Some(head) if !head.is_empty() => head,
+            _ => return Ok(()),
+        };
+        if self.archive.detect_incompressible && analysis::is_incompressible(&head) {
+            self.layout = Method::Copy.into();
+        } else if let Some(filter) = analysis::detect_filter(&head)
+            .filter(|_| self.archive.detect_filters && self.layout.coders().len() == 1)
+            .filter(|filter| self.archive.codecs.can_encode(&filter.method))
+        {
+            let mut methods = self.layout.methods();
+            methods.0.push(filter);
+            self.layout = methods.into();
+        }
+        if let Some(entry) = &self.entry {
+            self.archive.start_stream(entry, &self.layout)?;
+        }
+        self.archive.write_data(&head)
+    }
+
+    /// Adds the file if it has not been added yet.
+    fn close(&mut self) -> Result<()> {
+        if self.failed {
+            return Err(failed_error().into());
+        }
+        let result = self.write_head();
+        self.check(result)?;
+        let entry = match self.entry.take() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        if self.size == 0 {
+            self.archive.entries.push(entry);
+            return Ok(());
+        }
+        let crc = mem::take(&mut self.hasher).finalize();
+        self.archive.add_stream(entry, self.size, crc)
+    }
+
+    /// Records that writing the data failed if `result` is [`Err`], so that
+    /// the file is not added.
+    fn check<T, E>(&mut self, result: std::result::Result<T, E>) -> std::result::Result<T, E> {
+        if result.is_err() {
+            self.failed = true;
+            self.entry = None;
+        }
+        result
+    }
+
+    /// Writes the data into the folder, without checking whether writing
+    /// failed before.
+    fn write_unchecked(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(head) = &mut self.head {
+            let n = buf.len().min(HEAD_SIZE - head.len());
+            head.extend_from_slice(&buf[..n]);
+            self.hasher.update(&buf[..n]);
+            self.size += n as u64;
+            if head.len() == HEAD_SIZE {
+                self.write_head()?;
+            }
+            return Ok(n);
+        }
+        if self.size == 0 {
+            if let Some(entry) = &self.entry {
+                self.archive.start_stream(entry, &self.layout)?;
+            }
+        }
+        self.archive.write_data(buf)?;
+        self.hasher.update(buf);
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+}
+
+impl<W: Write + Seek> Write for EntryWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.failed {
+            return Err(failed_error());
+        }
+        let result = self.write_unchecked(buf);
+        self.check(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.archive.writer.flush()
+    }
+}
+
+/// Returns the error that no folder is being written.
+fn no_folder_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "no folder is being written")
+}
+
+/// Returns the error that writing the data of a file failed before.
+fn failed_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "the data of the file could not be written",
+    )
+}
+
+impl<W: Write + Seek> Drop for EntryWriter<'_, W> {
+    fn drop(&mut self) {
+        if !self.failed {
+            let _ = self.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        error::InvalidArchive,
+        folder::{CoderSpec, FolderBuilder},
+        method::Misc,
+        Archive, Error, FileTime, FileType,
+    };
+
+    fn write_archive(method: Method) -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_method(method);
+        let mut entry = Entry::new("dir");
+        entry.set_modified(Some(FileTime::from(132_539_328_000_000_000)));
+        writer.add_dir(entry);
+        let mut entry = Entry::new("dir/hello.txt");
+        entry
+            .set_modified(Some(FileTime::from(132_539_328_000_000_000)))
+            .set_attributes(Some(WinAttributes::ARCHIVE));
+        writer.add_file(entry, &b"Hello, world!\n"[..]).unwrap();
+        writer
+            .add_file(Entry::new("lorem.txt"), &lorem()[..])
+            .unwrap();
+        writer
+            .add_file(Entry::new("empty.txt"), io::empty())
+            .unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn lorem() -> Vec<u8> {
+        b"Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n".repeat(64)
+    }
+
+    #[test]
+    fn write_archive_and_read_it() {
+        for method in [
+            Method::Copy,
+            Method::Lzma2,
+            Method::Sz(crate::method::Sz::Lzma),
+        ] {
+            let buf = write_archive(method);
+            let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            let entries = archive.entries();
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].name(), "dir");
+            assert!(entries[0].is_dir());
+            assert!(!entries[0].has_stream());
+            assert_eq!(entries[0].attributes(), Some(WinAttributes::DIRECTORY));
+            assert_eq!(
+                entries[0].modified(),
+                Some(FileTime::from(132_539_328_000_000_000))
+            );
+            assert_eq!(entries[1].name(), "dir/hello.txt");
+            assert!(!entries[1].is_dir());
+            assert_eq!(entries[1].size(), 14);
+            assert_eq!(entries[1].crc(), Some(crc32fast::hash(b"Hello, world!\n")));
+            assert_eq!(entries[1].attributes(), Some(WinAttributes::ARCHIVE));
+            assert_eq!(entries[1].created(), None);
+            assert_eq!(entries[2].name(), "lorem.txt");
+            assert_eq!(entries[2].modified(), None);
+            assert_eq!(entries[2].attributes(), None);
+            assert_eq!(entries[3].name(), "empty.txt");
+            assert!(!entries[3].is_dir());
+            assert!(!entries[3].has_stream());
+            assert_eq!(entries[3].size(), 0);
+
+            let mut data = Vec::new();
+            archive
+                .for_each_entry(|entry, reader| {
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf)?;
+                    data.push((entry.name().to_string(), buf));
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(
+                data,
+                [
+                    ("dir".to_string(), Vec::new()),
+                    ("dir/hello.txt".to_string(), b"Hello, world!\n".to_vec()),
+                    ("lorem.txt".to_string(), lorem()),
+                    ("empty.txt".to_string(), Vec::new())
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn write_entry_from_stream() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut entry = writer.start_entry(Entry::new("lorem.txt")).unwrap();
+        for chunk in lorem().chunks(100) {
+            entry.write_all(chunk).unwrap();
+        }
+        entry.finish().unwrap();
+        // The entry is added when the writer is dropped.
+        writer
+            .start_entry(Entry::new("hello.txt"))
+            .unwrap()
+            .write_all(b"Hello, world!\n")
+            .unwrap();
+        drop(writer.start_entry(Entry::new("empty.txt")).unwrap());
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        let entries = archive.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].size(), lorem().len() as u64);
+        assert_eq!(entries[0].crc(), Some(crc32fast::hash(&lorem())));
+        assert_eq!(entries[1].name(), "hello.txt");
+        assert_eq!(entries[1].size(), 14);
+        assert!(!entries[2].has_stream());
+        let mut data = Vec::new();
+        archive.read_entry(1, &mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+    }
+
+    fn solid_folders(solid: SolidOptions, names: &[&str]) -> Vec<Option<usize>> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_solid(solid);
+        for (i, name) in names.iter().enumerate() {
+            let data = [u8::try_from(i).unwrap(); 10];
+            writer.add_file(Entry::new(*name), &data[..]).unwrap();
+        }
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        let mut data = Vec::new();
+        archive
+            .for_each_entry(|_, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                data.push(buf);
+                Ok(())
+            })
+            .unwrap();
+        for (i, data) in data.iter().enumerate() {
+            assert_eq!(data, &vec![u8::try_from(i).unwrap(); 10]);
+        }
+        let mut buf = Vec::new();
+        archive.read_entry(names.len() - 1, &mut buf).unwrap();
+        assert_eq!(buf.len(), 10);
+        archive.entries().iter().map(|e| e.folder).collect()
+    }
+
+    #[test]
+    fn write_solid_archive() {
+        let names = ["a", "b", "c", "d", "e"];
+        assert_eq!(
+            solid_folders(SolidOptions::off(), &names),
+            [Some(0), Some(1), Some(2), Some(3), Some(4)]
+        );
+        assert_eq!(solid_folders(SolidOptions::on(), &names), [Some(0); 5]);
+        assert_eq!(
+            solid_folders(SolidOptions::on().max_files(2), &names),
+            [Some(0), Some(0), Some(1), Some(1), Some(2)]
+        );
+        assert_eq!(
+            solid_folders(SolidOptions::on().max_size(25), &names),
+            [Some(0), Some(0), Some(0), Some(1), Some(1)]
+        );
+        assert_eq!(
+            solid_folders(SolidOptions::on().max_size(25).max_files(2), &names),
+            [Some(0), Some(0), Some(1), Some(1), Some(2)]
+        );
+        assert_eq!(
+            solid_folders(
+                SolidOptions::on().per_extension(),
+                &["a.txt", "dir/b.txt", "c.bin", "d.TXT", "e", ".f", "g.d/h"]
+            ),
+            [
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(3),
+                Some(3)
+            ]
+        );
+    }
+
+    #[test]
+    fn write_solid_archive_with_different_methods() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_solid(SolidOptions::on());
+        writer.add_file(Entry::new("a"), &b"a"[..]).unwrap();
+        writer.add_dir(Entry::new("dir"));
+        writer.add_file(Entry::new("b"), &b"b"[..]).unwrap();
+        writer.set_method(Method::Copy);
+        writer.add_file(Entry::new("c"), &b"c"[..]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(
+            archive
+                .entries()
+                .iter()
+                .map(|e| e.folder)
+                .collect::<Vec<_>>(),
+            [Some(0), None, Some(0), Some(1)]
+        );
+        assert_eq!(archive.methods(3).unwrap().to_string(), "Copy");
+    }
+
+    #[test]
+    fn match_method_rules() {
+        let copy = || MethodChain::from(Method::Copy);
+        let rule = MethodRule::extensions([".JPG", "zip"], copy());
+        assert!(rule.matches(&Entry::new("dir/a.jpg")));
+        assert!(rule.matches(&Entry::new("b.Zip")));
+        assert!(!rule.matches(&Entry::new("c.txt")));
+        assert!(!rule.matches(&Entry::new("jpg")));
+
+        let rule = MethodRule::glob("*.t?t", copy());
+        assert!(rule.matches(&Entry::new("a.txt")));
+        assert!(rule.matches(&Entry::new("dir/b.tst")));
+        assert!(!rule.matches(&Entry::new("c.text")));
+        let rule = MethodRule::glob("src/**/*.rs", copy());
+        assert!(rule.matches(&Entry::new("src/lib.rs")));
+        assert!(rule.matches(&Entry::new("src/a/b/c.rs")));
+        assert!(!rule.matches(&Entry::new("lib.rs")));
+        let rule = MethodRule::glob("dir/*", copy());
+        assert!(rule.matches(&Entry::new("dir/a")));
+        assert!(!rule.matches(&Entry::new("dir/a/b")));
+
+        let rule = MethodRule::new(|entry| entry.name().len() > 3, copy());
+        assert!(rule.matches(&Entry::new("abcd")));
+        assert!(!rule.matches(&Entry::new("abc")));
+        assert_eq!(rule.layout(), &FolderLayout::from(copy()));
+    }
+
+    #[test]
+    fn write_archive_with_method_rules() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .set_solid(SolidOptions::on())
+            .add_rule(MethodRule::extensions(["jpg", "zip"], Method::Copy))
+            .unwrap()
+            .add_rule(MethodRule::glob(
+                "data/**",
+                "LZMA2:20 Delta:4".parse::<MethodChain>().unwrap(),
+            ))
+            .unwrap()
+            .add_rule(MethodRule::new(
+                |entry| entry.name().starts_with('e'),
+                "LZMA Swap2".parse::<MethodChain>().unwrap(),
+            ))
+            .unwrap();
+        let names = ["a.jpg", "b.zip", "c.txt", "data/d.wav", "e.bin", "f.txt"];
+        for name in names {
+            writer.add_file(Entry::new(name), &lorem()[..]).unwrap();
+        }
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(
+            archive
+                .entries()
+                .iter()
+                .map(|e| e.folder)
+                .collect::<Vec<_>>(),
+            [Some(0), Some(0), Some(1), Some(2), Some(3), Some(4)]
+        );
+        let methods = (0..names.len())
+            .map(|i| archive.methods(i).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            [
+                "Copy",
+                "Copy",
+                "LZMA2:24",
+                "LZMA2:20 Delta:4",
+                "LZMA:23 Swap2",
+                "LZMA2:24"
+            ]
+        );
+        let mut count = 0;
+        archive
+            .for_each_entry(|_, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                assert_eq!(buf, lorem());
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, names.len());
+    }
+
+    #[test]
+    fn write_archive_with_detected_filters() {
+        let mut elf = b"\x7fELF\x02\x01".to_vec();
+        elf.resize(18, 0);
+        elf.extend(62_u16.to_le_bytes());
+        elf.resize(64, 0);
+        let mut large_elf = elf.clone();
+        large_elf.extend(lorem().repeat(2));
+        elf.extend(lorem());
+        let files = [
+            ("a", &elf),
+            ("b", &large_elf),
+            ("c.txt", &lorem()),
+            ("d.jpg", &elf),
+        ];
+
+        for (detects, methods) in [
+            (true, ["LZMA2:24 BCJ", "LZMA2:24 BCJ", "LZMA2:24", "Copy"]),
+            (false, ["LZMA2:24", "LZMA2:24", "LZMA2:24", "Copy"]),
+        ] {
+            let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+            writer
+                .set_filter_detection(detects)
+                .add_rule(MethodRule::extensions(["jpg"], Method::Copy))
+                .unwrap();
+            for (name, data) in files {
+                let mut entry = writer.start_entry(Entry::new(name)).unwrap();
+                for chunk in data.chunks(1000) {
+                    entry.write_all(chunk).unwrap();
+                }
+                entry.finish().unwrap();
+            }
+            let buf = writer.finish().unwrap().into_inner();
+
+            let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            for (i, method) in methods.iter().enumerate() {
+                assert_eq!(archive.methods(i).unwrap().to_string(), *method);
+            }
+            let mut count = 0;
+            archive
+                .for_each_entry(|entry, reader| {
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf)?;
+                    let (name, data) = files[count];
+                    assert_eq!(entry.name(), name);
+                    assert_eq!(&buf, data);
+                    count += 1;
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(count, files.len());
+        }
+    }
+
+    #[test]
+    fn write_archive_with_incompressible_data() {
+        let mut state = 1_u32;
+        let random = (0..20000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                state.to_be_bytes()[0]
+            })
+            .collect::<Vec<_>>();
+        let files = [("a.txt", lorem()), ("b.jpg", random), ("c.txt", lorem())];
+
+        for (detects, methods, folders) in [
+            (true, ["LZMA2:24", "Copy", "LZMA2:24"], [0, 1, 2]),
+            (false, ["LZMA2:24", "LZMA2:24", "LZMA2:24"], [0, 0, 0]),
+        ] {
+            let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+            writer
+                .set_solid(SolidOptions::on())
+                .set_incompressible_detection(detects);
+            for (name, data) in &files {
+                writer.add_file(Entry::new(*name), &data[..]).unwrap();
+            }
+            let buf = writer.finish().unwrap().into_inner();
+
+            let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            for (i, method) in methods.iter().enumerate() {
+                assert_eq!(archive.methods(i).unwrap().to_string(), *method);
+                assert_eq!(archive.entries()[i].folder, Some(folders[i]));
+            }
+            let mut count = 0;
+            archive
+                .for_each_entry(|_, reader| {
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf)?;
+                    assert_eq!(buf, files[count].1);
+                    count += 1;
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(count, files.len());
+        }
+    }
+
+    #[test]
+    fn write_archive_with_compression_level() {
+        for (level, method, folders) in [
+            (CompressionLevel::STORE, "Copy", [0, 1, 2]),
+            (CompressionLevel::FASTEST, "LZMA2:18", [0, 0, 0]),
+            (CompressionLevel::ULTRA, "LZMA2:26", [0, 0, 0]),
+        ] {
+            let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+            writer.set_compression_level(level);
+            for name in ["a", "b", "c"] {
+                writer.add_file(Entry::new(name), &lorem()[..]).unwrap();
+            }
+            let buf = writer.finish().unwrap().into_inner();
+
+            let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            for (i, folder) in folders.into_iter().enumerate() {
+                assert_eq!(archive.methods(i).unwrap().to_string(), method);
+                assert_eq!(archive.entries()[i].folder, Some(folder));
+            }
+            archive
+                .for_each_entry(|_, reader| {
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf)?;
+                    assert_eq!(buf, lorem());
+                    Ok(())
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn write_archive_with_encoded_header() {
+        let names = (0..1000)
+            .map(|i| format!("dir/file{i:04}.txt"))
+            .collect::<Vec<_>>();
+        let mut sizes = Vec::new();
+        for (methods, id) in [
+            (None, Property::Header),
+            (Some("LZMA:20:fb254"), Property::EncodedHeader),
+            (Some("LZMA2 Delta:2"), Property::EncodedHeader),
+        ] {
+            let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+            writer
+                .set_solid(SolidOptions::on())
+                .set_header_compression(methods.map(|methods| methods.parse().unwrap()));
+            for name in &names {
+                writer.add_file(Entry::new(name), &b"abc"[..]).unwrap();
+            }
+            let buf = writer.finish().unwrap().into_inner();
+            let offset = u64::from_le_bytes(buf[12..20].try_into().unwrap());
+            let pos = usize::try_from(offset).unwrap() + 32;
+            assert_eq!(buf[pos], u8::from(id));
+            sizes.push(buf.len() - pos);
+
+            let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            assert_eq!(archive.entries().len(), names.len());
+            archive
+                .for_each_entry(|entry, reader| {
+                    assert!(entry.name().starts_with("dir/file"));
+                    let mut buf = Vec::new();
+                    reader.read_to_end(&mut buf)?;
+                    assert_eq!(buf, b"abc");
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(sizes[1] * 4 < sizes[0]);
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_header_compression(Some(Method::Sz(Sz::Ppmd).into()));
+        writer.add_file(Entry::new("a"), &b"abc"[..]).unwrap();
+        assert!(matches!(
+            writer.finish().unwrap_err(),
+            Error::UnsupportedArchive(UnsupportedArchive::CompressionMethod(_))
+        ));
+    }
+
+    #[test]
+    fn write_archive_with_timestamps() {
+        let time = FileTime::from(132_854_221_440_000_000);
+        let mut entry = Entry::new("a");
+        entry
+            .set_modified(Some(time))
+            .set_created(Some(time))
+            .set_accessed(Some(time))
+            .set_unix_mode(Some(0o100_755));
+
+        for (timestamps, expected) in [
+            (Timestamps::all(), [true, true, true]),
+            (Timestamps::none().modified(true), [true, false, false]),
+            (Timestamps::all().accessed(false), [true, true, false]),
+            (Timestamps::none(), [false, false, false]),
+        ] {
+            let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+            writer.set_timestamps(timestamps);
+            writer.add_file(entry.clone(), &b"a"[..]).unwrap();
+            writer.add_dir(entry.clone());
+            let buf = writer.finish().unwrap().into_inner();
+
+            let archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+            for entry in archive.entries() {
+                let times = [entry.modified(), entry.created(), entry.accessed()];
+                assert_eq!(times.map(|time| time.is_some()), expected);
+                assert!(times.iter().flatten().all(|&t| t == time));
+                assert_eq!(entry.unix_mode(), Some(0o100_755));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_archive_from_paths() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("sz-writer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_path("dir", &dir).unwrap();
+        writer.add_path("dir/run.sh", &path).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        let entries = archive.entries();
+        assert!(entries[0].is_dir());
+        assert_eq!(entries[0].file_type(), FileType::Directory);
+        assert_eq!(entries[1].unix_mode(), Some(0o100_755));
+        assert_eq!(entries[1].file_type(), FileType::Regular);
+        assert_eq!(entries[1].attributes().unwrap().bits() & 0xffff, 0x8020);
+        #[cfg(feature = "time")]
+        assert!(entries[1].modified().is_some());
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                if !entry.is_dir() {
+                    assert_eq!(buf, b"#!/bin/sh\n");
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn write_archive_with_symlinks() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_file(Entry::new("a"), &b"data"[..]).unwrap();
+        writer.add_symlink(Entry::new("b"), "a").unwrap();
+        let mut entry = Entry::new("c");
+        entry.set_unix_mode(Some(0o100_755));
+        writer.add_symlink(entry, "../a").unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        let entries = archive.entries();
+        assert_eq!(entries[0].file_type(), FileType::Regular);
+        assert_eq!(entries[1].file_type(), FileType::Symlink);
+        assert_eq!(entries[1].unix_mode(), Some(0o120_777));
+        assert_eq!(entries[2].unix_mode(), Some(0o120_755));
+        assert_eq!(entries[1].symlink_target(), None);
+        assert_eq!(archive.symlink_target(0).unwrap(), None);
+        assert_eq!(archive.symlink_target(2).unwrap().as_deref(), Some("../a"));
+        let mut targets = Vec::new();
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                if let Some(target) = entry.symlink_target() {
+                    assert_eq!(buf, target.as_bytes());
+                }
+                targets.push(entry.symlink_target().map(String::from));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(targets, [None, Some("a".into()), Some("../a".into())]);
+    }
+
+    #[test]
+    fn read_archive_with_invalid_symlinks() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut entry = Entry::new("a");
+        entry.set_unix_mode(Some(0o120_777));
+        writer.add_file(entry, &b"\xff"[..]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert!(matches!(
+            archive.symlink_target(0).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::SymlinkTarget)
+        ));
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf)?;
+                assert_eq!(entry.symlink_target(), None);
+                assert_eq!(buf, b"\xff");
+                Ok(())
+            })
+            .unwrap();
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut entry = Entry::new("a");
+        entry.set_unix_mode(Some(0o120_777));
+        writer.add_file(entry, &vec![b'a'; 0x10001][..]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert!(matches!(
+            archive.symlink_target(0).unwrap_err(),
+            Error::InvalidArchive(InvalidArchive::SymlinkTarget)
+        ));
+        archive
+            .for_each_entry(|entry, reader| {
+                assert_eq!(entry.symlink_target(), None);
+                assert_eq!(io::copy(reader, &mut io::sink())?, 0x10001);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_archive_from_symlinks() {
+        let dir = std::env::temp_dir().join(format!("sz-symlink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        fs::write(&path, "data").unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("file", &link).unwrap();
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_path("followed", &link).unwrap();
+        writer.set_follow_symlinks(false);
+        writer.add_path("link", &link).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(archive.entries()[0].file_type(), FileType::Regular);
+        assert_eq!(archive.entries()[1].file_type(), FileType::Symlink);
+        let extracted = dir.join("extracted");
+        archive
+            .for_each_entry(|entry, _| {
+                if entry.file_type() == FileType::Symlink {
+                    entry.create_symlink(&extracted)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        let target = fs::read_link(&extracted).unwrap();
+        let data = fs::read(&extracted).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(target, Path::new("file"));
+        assert_eq!(data, b"data");
+    }
+
+    #[test]
+    fn write_archive_with_folder_layout() {
+        // The coder which reads the packed stream is stored first.
+        let layout = FolderBuilder::new()
+            .filter(Method::Lzma2)
+            .add_coder(CoderSpec::new("Delta:2".parse::<MethodSpec>().unwrap()))
+            .bind_pair(1, 0)
+            .packed_stream(0)
+            .build()
+            .unwrap();
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_folder_layout(layout).unwrap();
+        writer.add_file(Entry::new("a"), &lorem()[..]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(archive.methods(0).unwrap().to_string(), "LZMA2:23 Delta:2");
+        let mut data = Vec::new();
+        archive.read_entry(0, &mut data).unwrap();
+        assert_eq!(data, lorem());
+
+        let layout = FolderBuilder::new()
+            .add_coder(CoderSpec::with_streams(Method::Copy, 2, 1))
+            .packed_stream(0)
+            .packed_stream(1)
+            .build()
+            .unwrap();
+        writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(matches!(
+            writer.set_folder_layout(layout.clone()).unwrap_err(),
+            Error::UnsupportedArchive(UnsupportedArchive::FolderLayout)
+        ));
+        assert!(writer
+            .add_rule(MethodRule::extensions(["exe"], layout))
+            .is_err());
+        assert!(writer.start_entry(Entry::new("a.exe")).is_ok());
+    }
+
+    #[test]
+    fn write_empty_archive() {
+        let buf = ArchiveWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .finish()
+            .unwrap()
+            .into_inner();
+        assert_eq!(buf.len(), 32);
+        let archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert!(archive.entries().is_empty());
+    }
+
+    #[test]
+    fn write_archive_with_only_empty_entries() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add_dir(Entry::new("dir"));
+        writer.add_file(Entry::new("empty"), io::empty()).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+        let archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        let entries = archive.entries();
+        assert!(entries[0].is_dir());
+        assert!(!entries[1].is_dir());
+        assert!(!entries[1].has_stream());
+    }
+
+    #[test]
+    fn write_archive_with_unsupported_method() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.set_method(Method::Misc(Misc::Bzip2));
+        assert!(writer.start_entry(Entry::new("a")).is_err());
+        assert!(matches!(
+            writer.add_file(Entry::new("a"), &b"a"[..]).unwrap_err(),
+            Error::UnsupportedArchive(UnsupportedArchive::CompressionMethod(name))
+                if name == "BZip2"
+        ));
+
+        writer
+            .set_method(Method::Lzma2)
+            .add_rule(MethodRule::glob(
+                "*.bz2",
+                "LZMA2 BZip2".parse::<MethodChain>().unwrap(),
+            ))
+            .unwrap();
+        assert!(writer.start_entry(Entry::new("a")).is_ok());
+        assert!(writer.start_entry(Entry::new("a.bz2")).is_err());
+    }
+
+    #[test]
+    fn write_archive_with_invalid_method_param() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        for methods in [
+            "LZMA2:mf=bt9",
+            "LZMA2:d=bogus",
+            "LZMA:fb=1",
+            "Delta:0 LZMA2",
+        ] {
+            writer.set_method_chain(methods.parse().unwrap());
+            for detection in [true, false] {
+                writer
+                    .set_incompressible_detection(detection)
+                    .set_filter_detection(detection);
+                assert!(writer.start_entry(Entry::new("a.txt")).is_err());
+                assert!(matches!(
+                    writer
+                        .add_file(Entry::new("a.txt"), &b"hello"[..])
+                        .unwrap_err(),
+                    Error::UnsupportedArchive(UnsupportedArchive::MethodParam(_))
+                ));
+            }
+        }
+        writer.set_method(Method::Lzma2);
+        writer.add_file(Entry::new("b.txt"), &b"hello"[..]).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(archive.entries().len(), 1);
+        let mut data = Vec::new();
+        archive.read_entry(0, &mut data).unwrap();
+        assert_eq!(data, b"hello");
+    }
+}